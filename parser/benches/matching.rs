@@ -0,0 +1,166 @@
+//! Compares matching every rule against every element with matching through the rule buckets
+//! and the ancestor filter, on a synthetic page with a few thousand rules.
+//!
+//! Run with `cargo bench --bench matching`.
+
+use std::time::{Duration, Instant};
+
+use parser::{
+    css::{parse_css, Stylesheet},
+    datatypes::Node,
+    dom::parse_element,
+    matching::{matches, AncestorFilter, ElementContext, RuleMap},
+    media::Viewport,
+    styles::style_tree,
+    tokenizer::Tokenizer,
+};
+
+const SECTIONS: usize = 40;
+const ITEMS: usize = 25;
+const RULES: usize = 4000;
+const RUNS: u32 = 5;
+
+fn page() -> String {
+    let mut html = String::from("<html><body>");
+    for section in 0..SECTIONS {
+        html += &format!("<div id=s{} class=section>", section);
+        html += "<h1 class=title>Title</h1><ul class=list>";
+        for item in 0..ITEMS {
+            html += &format!(
+                "<li class='item i{} k{}'><a class=link>Link</a><span>text</span></li>",
+                item,
+                (section + item) % 10,
+            );
+        }
+        html += "</ul><p class=footer>Footer</p></div>";
+    }
+    html += "</body></html>";
+    html
+}
+
+fn stylesheet() -> String {
+    let mut css = String::new();
+    for i in 0..RULES {
+        let selector = match i % 8 {
+            0 => format!("#s{}", i % (SECTIONS * 2)),
+            1 => format!(".i{}", i % (ITEMS * 2)),
+            2 => format!(".section .k{} a", i % 20),
+            3 => format!("#s{} > ul > li.i{}", i % SECTIONS, i % ITEMS),
+            4 => format!(".list li.k{} + li span", i % 10),
+            5 => format!("div.missing{} p", i),
+            6 => format!("h1.title ~ ul.x{}", i),
+            _ => format!("body .section{} span", i),
+        };
+        css += &format!("{} {{ margin: {}px; }}\n", selector, i % 7);
+    }
+    css
+}
+
+fn parse_page(html: &str) -> Node {
+    let mut tokenizer = Tokenizer::new(html);
+    let mut tokens = Vec::new();
+    while let Some(token) = tokenizer.next_token() {
+        tokens.push(token);
+    }
+    let (element, _) = parse_element(&tokens);
+    Node::Element(element)
+}
+
+// Call `visit` with the context of every element of the tree and the filter of its ancestors.
+fn walk(
+    node: &Node,
+    parent: Option<&ElementContext>,
+    preceding_siblings: &[Node],
+    filter: &mut AncestorFilter,
+    visit: &mut dyn FnMut(&ElementContext, &AncestorFilter),
+) {
+    let element = match node {
+        Node::Element(element) => element,
+        Node::Text(_) => return,
+    };
+    let context = ElementContext {
+        element,
+        parent,
+        preceding_siblings,
+    };
+    visit(&context, filter);
+    filter.push(element);
+    for (i, child) in element.children.iter().enumerate() {
+        walk(child, Some(&context), &element.children[..i], filter, visit);
+    }
+    filter.pop(element);
+}
+
+fn naive(root: &Node, stylesheet: &Stylesheet) -> usize {
+    let mut count = 0;
+    walk(
+        root,
+        None,
+        &[],
+        &mut AncestorFilter::new(),
+        &mut |context, _| {
+            count += stylesheet
+                .rules()
+                .iter()
+                .filter(|rule| {
+                    rule.selector
+                        .iter()
+                        .any(|selector| matches(context, selector, None))
+                })
+                .count();
+        },
+    );
+    count
+}
+
+fn bucketed(root: &Node, rules: &RuleMap) -> usize {
+    let mut count = 0;
+    walk(
+        root,
+        None,
+        &[],
+        &mut AncestorFilter::new(),
+        &mut |context, filter| {
+            count += rules.matching_rules(context, filter, None).len();
+        },
+    );
+    count
+}
+
+fn time<T>(name: &str, mut f: impl FnMut() -> T) -> T {
+    let mut best = Duration::MAX;
+    let mut result = None;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        result = Some(f());
+        best = best.min(start.elapsed());
+    }
+    println!("{:<24} {:>10.2?}", name, best);
+    result.unwrap()
+}
+
+fn main() {
+    let root = parse_page(&page());
+    let stylesheets = vec![parse_css(stylesheet())];
+    let viewport = Viewport::new(800.0, 600.0);
+    println!(
+        "{} elements x {} rules, best of {} runs",
+        SECTIONS * (ITEMS * 3 + 4) + 2,
+        RULES,
+        RUNS
+    );
+
+    let naive_count = time("naive matching", || naive(&root, &stylesheets[0]));
+    let sheets: Vec<&Stylesheet> = stylesheets.iter().collect();
+    let rules = time("building rule map", || RuleMap::new(&sheets, &viewport));
+    let bucketed_count = time("bucketed matching", || bucketed(&root, &rules));
+    assert_eq!(
+        naive_count, bucketed_count,
+        "bucketed matching found different rules"
+    );
+    println!("{} matched rules", bucketed_count);
+
+    time("style_tree", || {
+        style_tree(&root, &stylesheets, &viewport).children.len()
+    });
+}
@@ -0,0 +1,120 @@
+//! Compares styling a large generated document sequentially and in parallel, and checks that
+//! both give the same styles.
+//!
+//! Run with `cargo bench --bench style`.
+
+use std::time::{Duration, Instant};
+
+use parser::{
+    css::parse_css,
+    datatypes::Node,
+    dom::parse_element,
+    media::Viewport,
+    styles::{StyleNode, Styler},
+    tokenizer::Tokenizer,
+};
+
+const ARTICLES: usize = 200;
+const PARAGRAPHS: usize = 40;
+const RUNS: u32 = 5;
+
+fn page() -> String {
+    let mut html = String::from("<html><body>");
+    for article in 0..ARTICLES {
+        html += &format!("<article id=a{} class='article t{}'>", article, article % 7);
+        html += "<header><h1 class=title>Title</h1><p class=byline>By someone</p></header>";
+        for paragraph in 0..PARAGRAPHS {
+            html += &format!(
+                "<p class='para p{}'>Some <em>text</em> with <a class=link>a link</a>.</p>",
+                paragraph % 5
+            );
+        }
+        html += "<footer><ul class=tags><li>one</li><li>two</li><li>three</li></ul></footer>";
+        html += "</article>";
+    }
+    html += "</body></html>";
+    html
+}
+
+fn stylesheet() -> String {
+    let mut css = String::from(
+        ".article { margin: 10px; padding: 1em; }
+         .title { font-size: 2em; font-weight: bold; }
+         .para { margin: 0.5em 0; }
+         .para em { font-style: italic; }
+         .tags li::before { content: \"#\"; }
+         header + p { color: #333333; }
+         .p3 ~ p { width: calc(100% - 2em); }",
+    );
+    for i in 0..500 {
+        css += &format!(
+            ".t{} .p{} a {{ color: #{:02x}{:02x}{:02x}; }}\n",
+            i % 7,
+            i % 5,
+            i % 256,
+            (i * 7) % 256,
+            (i * 13) % 256
+        );
+    }
+    css
+}
+
+fn parse_page(html: &str) -> Node {
+    let mut tokenizer = Tokenizer::new(html);
+    let mut tokens = Vec::new();
+    while let Some(token) = tokenizer.next_token() {
+        tokens.push(token);
+    }
+    let (element, _) = parse_element(&tokens);
+    Node::Element(element)
+}
+
+fn same_styles(a: &StyleNode, b: &StyleNode) -> bool {
+    a.style == b.style
+        && a.children.len() == b.children.len()
+        && a.children
+            .iter()
+            .zip(&b.children)
+            .all(|(a, b)| same_styles(a, b))
+}
+
+fn time(name: &str, mut f: impl FnMut()) {
+    let mut best = Duration::MAX;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        f();
+        best = best.min(start.elapsed());
+    }
+    println!("{:<24} {:>10.2?}", name, best);
+}
+
+fn main() {
+    let mut root = parse_page(&page());
+    let stylesheets = vec![parse_css(stylesheet())];
+    let viewport = Viewport::new(800.0, 600.0);
+    println!(
+        "{} elements on {} threads, best of {} runs",
+        2 + ARTICLES * (8 + PARAGRAPHS * 3),
+        rayon::current_num_threads(),
+        RUNS
+    );
+
+    let mut sequential = Styler::new();
+    let mut parallel = Styler::new();
+    parallel.parallel = true;
+    sequential.restyle(&mut root, &stylesheets, &viewport);
+    parallel.restyle(&mut root, &stylesheets, &viewport);
+    assert!(
+        same_styles(&sequential.style_tree(&root), &parallel.style_tree(&root)),
+        "parallel styling gave different styles"
+    );
+
+    // A new viewport makes every run restyle the whole document.
+    let mut width = 800.0;
+    for (name, styler) in [("sequential", &mut sequential), ("parallel", &mut parallel)] {
+        time(name, || {
+            width += 1.0;
+            styler.restyle(&mut root, &stylesheets, &Viewport::new(width, 600.0));
+        });
+    }
+}
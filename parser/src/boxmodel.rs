@@ -1,9 +1,9 @@
 use crate::{
-    css::{
-        Unit::Px,
-        Value::{self, Length},
-    },
+    computed::{Direction, LengthPercentage, LengthPercentageOrAuto},
     datatypes::Node,
+    font::LineMetrics,
+    fontdb::FontDatabase,
+    shaping::GlyphRun,
     styles::{Display, StyleNode},
 };
 
@@ -36,6 +36,22 @@ pub struct LayoutBox<'a> {
     pub dimensions: Dimension,
     pub box_type: BoxType<'a>,
     pub children: Vec<LayoutBox<'a>>,
+    // The parts of an inline box on each line it spans, in order.
+    pub fragments: Vec<Fragment>,
+}
+
+/// The part of an inline box on one line.
+#[derive(Debug, Clone)]
+pub struct Fragment {
+    // Content area of the box on the line, which is as high as its font.
+    pub rect: Rect,
+    // The text on the line shaped into glyphs, for boxes of text.
+    pub text: Option<GlyphRun>,
+    // Distance from the top of the content area down to the baseline, and where the font
+    // puts lines under and through the text, for text decorations.
+    pub baseline: f32,
+    pub underline: LineMetrics,
+    pub strikeout: LineMetrics,
 }
 
 #[derive(Debug)]
@@ -51,6 +67,7 @@ impl<'a> LayoutBox<'a> {
             box_type: box_type,
             dimensions: Default::default(),
             children: Vec::new(),
+            fragments: Vec::new(),
         }
     }
     fn get_style_node(&self) -> &'a StyleNode<'a> {
@@ -60,6 +77,14 @@ impl<'a> LayoutBox<'a> {
         }
     }
 
+    /// Whether this is the box of a text node.
+    pub fn is_text(&self) -> bool {
+        match self.box_type {
+            BoxType::InlineNode(node) => matches!(*node.node, Node::Text(_)),
+            BoxType::BlockNode(_) | BoxType::AnonymousBlock => false,
+        }
+    }
+
     fn get_inline_container(&mut self) -> &mut LayoutBox<'a> {
         match self.box_type {
             BoxType::InlineNode(_) | BoxType::AnonymousBlock => self,
@@ -77,131 +102,124 @@ impl<'a> LayoutBox<'a> {
             }
         }
     }
-    fn layout(&mut self, block: Dimension) {
+    // `direction` is the direction of the containing block.
+    fn layout(&mut self, block: Dimension, direction: Direction, fonts: &FontDatabase) {
         match self.box_type {
-            BoxType::BlockNode(_) => self.layout_block(block),
+            BoxType::BlockNode(_) => self.layout_block(block, direction, fonts),
             BoxType::InlineNode(_) | BoxType::AnonymousBlock => {}
         }
     }
 
-    fn layout_block(&mut self, block: Dimension) {
-        self.calculate_block_width(&block);
+    fn layout_block(&mut self, block: Dimension, direction: Direction, fonts: &FontDatabase) {
+        self.calculate_block_width(&block, direction);
 
         self.calculate_block_position(&block);
 
-        self.layout_block_children();
+        self.layout_block_children(fonts);
 
         self.calculate_block_height();
     }
 
-    fn calculate_block_width(&mut self, block: &Dimension) {
-        let style = self.get_style_node();
-
-        // `width` has initial value `auto`.
-        let auto = Value::Keyword("auto".to_string());
-        let mut width = style.value("width").unwrap_or(auto.clone());
-
-        // margin, border, and padding have initial value 0.
-        let zero = Length(0.0, Px);
+    fn calculate_block_width(&mut self, block: &Dimension, direction: Direction) {
+        let style = &self.get_style_node().style;
+        let box_model = &style.box_model;
+        // Percentages, also in `calc()`, refer to the width of the containing block.
+        let basis = block.content.width;
 
-        let mut margin_left = style.lookup("margin-left", "margin", &zero);
-        let mut margin_right = style.lookup("margin-right", "margin", &zero);
+        // `None` stands for `auto`.
+        let mut width = box_model.width.resolve(basis);
+        let mut margin_left = box_model.margin_left.resolve(basis);
+        let mut margin_right = box_model.margin_right.resolve(basis);
 
-        let border_left = style.lookup("border-left-width", "border-width", &zero);
-        let border_right = style.lookup("border-right-width", "border-width", &zero);
+        let border_left = style.border.border_left_width;
+        let border_right = style.border.border_right_width;
 
-        let padding_left = style.lookup("padding-left", "padding", &zero);
-        let padding_right = style.lookup("padding-right", "padding", &zero);
+        let padding_left = box_model.padding_left.resolve(basis);
+        let padding_right = box_model.padding_right.resolve(basis);
 
         let total = sum([
-            &margin_left,
-            &margin_right,
-            &border_left,
-            &border_right,
-            &padding_left,
-            &padding_right,
-            &width,
+            margin_left.unwrap_or(0.0),
+            margin_right.unwrap_or(0.0),
+            border_left,
+            border_right,
+            padding_left,
+            padding_right,
+            width.unwrap_or(0.0),
         ]
-        .iter()
-        .map(|v| v.to_px()));
+        .into_iter());
 
-        if width != auto && total > block.content.width {
-            if margin_left == auto {
-                margin_left = Length(0.0, Px);
-            }
-            if margin_right == auto {
-                margin_right = Length(0.0, Px);
-            }
+        if width.is_some() && total > block.content.width {
+            margin_left.get_or_insert(0.0);
+            margin_right.get_or_insert(0.0);
         }
 
         let underflow = block.content.width - total;
 
-        match (width == auto, margin_left == auto, margin_right == auto) {
-            (false, false, false) => {
-                margin_right = Length(margin_right.to_px() + underflow, Px);
-            }
+        match (width, margin_left, margin_right) {
+            // An over-constrained box gives way at its end margin, which is on the left in a
+            // right-to-left containing block.
+            (Some(_), Some(left), Some(right)) => match direction {
+                Direction::Ltr => margin_right = Some(right + underflow),
+                Direction::Rtl => margin_left = Some(left + underflow),
+            },
 
-            (false, false, true) => {
-                margin_right = Length(underflow, Px);
+            (Some(_), Some(_), None) => {
+                margin_right = Some(underflow);
             }
-            (false, true, false) => {
-                margin_left = Length(underflow, Px);
+            (Some(_), None, Some(_)) => {
+                margin_left = Some(underflow);
             }
 
-            (true, _, _) => {
-                if margin_left == auto {
-                    margin_left = Length(0.0, Px);
-                }
-                if margin_right == auto {
-                    margin_right = Length(0.0, Px);
-                }
+            (None, _, _) => {
+                let left = margin_left.get_or_insert(0.0);
+                let right = margin_right.get_or_insert(0.0);
 
                 if underflow >= 0.0 {
-                    width = Length(underflow, Px);
+                    width = Some(underflow);
                 } else {
-                    // Width can't be negative. Adjust the right margin instead.
-                    width = Length(0.0, Px);
-                    margin_right = Length(margin_right.to_px() + underflow, Px);
+                    // Width can't be negative. Adjust the end margin instead.
+                    width = Some(0.0);
+                    match direction {
+                        Direction::Ltr => *right += underflow,
+                        Direction::Rtl => *left += underflow,
+                    }
                 }
             }
 
-            (false, true, true) => {
-                margin_left = Length(underflow / 2.0, Px);
-                margin_right = Length(underflow / 2.0, Px);
+            (Some(_), None, None) => {
+                margin_left = Some(underflow / 2.0);
+                margin_right = Some(underflow / 2.0);
             }
         }
 
         let d = &mut self.dimensions;
-        d.content.width = width.to_px();
+        d.content.width = width.unwrap_or(0.0);
 
-        d.padding.left = padding_left.to_px();
-        d.padding.right = padding_right.to_px();
+        d.padding.left = padding_left;
+        d.padding.right = padding_right;
 
-        d.border.left = border_left.to_px();
-        d.border.right = border_right.to_px();
+        d.border.left = border_left;
+        d.border.right = border_right;
 
-        d.margin.left = margin_left.to_px();
-        d.margin.right = margin_right.to_px();
+        d.margin.left = margin_left.unwrap_or(0.0);
+        d.margin.right = margin_right.unwrap_or(0.0);
     }
 
     fn calculate_block_position(&mut self, containing_block: &Dimension) {
-        let style = self.get_style_node();
+        let style = &self.get_style_node().style;
+        let box_model = &style.box_model;
         let d = &mut self.dimensions;
+        // Vertical margins and padding also take percentages of the containing block's width.
+        let basis = containing_block.content.width;
 
-        let zero = Length(0.0, Px);
-
-        d.margin.top = style.lookup("margin-top", "margin", &zero).to_px();
-        d.margin.bottom = style.lookup("margin-bottom", "margin", &zero).to_px();
+        d.margin.top = box_model.margin_top.resolve(basis).unwrap_or(0.0);
+        d.margin.bottom = box_model.margin_bottom.resolve(basis).unwrap_or(0.0);
 
-        d.border.top = style
-            .lookup("border-top-width", "border-width", &zero)
-            .to_px();
-        d.border.bottom = style
-            .lookup("border-bottom-width", "border-width", &zero)
-            .to_px();
+        d.border.top = style.border.border_top_width;
+        d.border.bottom = style.border.border_bottom_width;
 
-        d.padding.top = style.lookup("padding-top", "padding", &zero).to_px();
-        d.padding.bottom = style.lookup("padding-bottom", "padding", &zero).to_px();
+        d.padding.top = box_model.padding_top.resolve(basis);
+        d.padding.bottom = box_model.padding_bottom.resolve(basis);
 
         d.content.x = containing_block.content.x + d.margin.left + d.border.left + d.padding.left;
 
@@ -212,35 +230,67 @@ impl<'a> LayoutBox<'a> {
             + d.padding.top;
     }
 
-    fn layout_block_children(&mut self) {
+    fn layout_block_children(&mut self, fonts: &FontDatabase) {
+        let style = &self.get_style_node().style;
         let d = &mut self.dimensions;
-        for child in &mut self.children {
-            child.layout(*d);
+        for (i, child) in self.children.iter_mut().enumerate() {
+            match child.box_type {
+                BoxType::AnonymousBlock => child.layout_anonymous_block(*d, style, fonts, i == 0),
+                _ => child.layout(*d, style.text.direction, fonts),
+            }
             d.content.height = d.content.height + child.dimensions.margin_box().height;
         }
     }
 
     fn calculate_block_height(&mut self) {
         let style = self.get_style_node();
-        if let Some(Length(h, Px)) = style.value("height") {
+        // Percentage heights would need the height of the containing block.
+        if let LengthPercentageOrAuto::LengthPercentage(LengthPercentage::Length(h)) =
+            style.style.box_model.height
+        {
             self.dimensions.content.height = h;
         }
+    }
 
-        if let Some(snode) = style.children.first() {
-            match snode.node {
-                Node::Element(_) => {}
-                Node::Text(text) => {
-                    if let Some(Length(h, Px)) = style.value("font-size") {
-                        self.dimensions.content.height = h;
-                    }
+    /// The dimensions of each part of the box: one for a block, and one for each line an
+    /// inline box is on, which only has the start edges on its first line and the end edges
+    /// on its last. The start edges are on the right in right-to-left text.
+    pub fn fragment_dimensions(&self) -> Vec<Dimension> {
+        let style_node = match self.box_type {
+            BoxType::InlineNode(style_node) => style_node,
+            _ => return vec![self.dimensions],
+        };
+        let rtl = style_node.style.text.direction == Direction::Rtl;
+        let last = self.fragments.len().saturating_sub(1);
+        self.fragments
+            .iter()
+            .enumerate()
+            .map(|(i, fragment)| {
+                let mut d = self.dimensions;
+                d.content = fragment.rect;
+                let (no_left, no_right) = match rtl {
+                    false => (i > 0, i < last),
+                    true => (i < last, i > 0),
+                };
+                if no_left {
+                    (d.margin.left, d.border.left, d.padding.left) = (0.0, 0.0, 0.0);
                 }
-            }
-        }
+                if no_right {
+                    (d.margin.right, d.border.right, d.padding.right) = (0.0, 0.0, 0.0);
+                }
+                d
+            })
+            .collect()
     }
 }
 
 // Build Layout Tree with calculations
-pub fn layout_tree<'a>(width: f32, height: f32, node: &'a StyleNode<'a>) -> LayoutBox<'a> {
+pub fn layout_tree<'a>(
+    width: f32,
+    height: f32,
+    node: &'a StyleNode<'a>,
+    fonts: &FontDatabase,
+) -> LayoutBox<'a> {
     let mut block = Dimension {
         content: Rect {
             x: 0.0,
@@ -269,21 +319,21 @@ pub fn layout_tree<'a>(width: f32, height: f32, node: &'a StyleNode<'a>) -> Layo
     };
     block.content.height = 0.0;
     let mut root_box = build_layout_tree(node);
-    root_box.layout(block);
+    root_box.layout(block, Direction::Ltr, fonts);
     root_box
 }
 
 // Build Layout Tree without calculations
 pub fn build_layout_tree<'a>(style_node: &'a StyleNode<'a>) -> LayoutBox<'a> {
     let mut root = LayoutBox::new(match style_node.display() {
-        Display::Block => BoxType::BlockNode(style_node),
+        Display::Block | Display::ListItem => BoxType::BlockNode(style_node),
         Display::Inline => BoxType::InlineNode(style_node),
         Display::None => panic!("Root node has display: none."),
     });
 
     for child in &style_node.children {
         match child.display() {
-            Display::Block => root.children.push(build_layout_tree(child)),
+            Display::Block | Display::ListItem => root.children.push(build_layout_tree(child)),
             Display::Inline => root
                 .get_inline_container()
                 .children
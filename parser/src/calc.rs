@@ -0,0 +1,346 @@
+use std::fmt;
+
+use crate::{
+    computed::LengthContext,
+    css::{Unit, Value},
+};
+
+/// A math expression from `calc()`, `min()`, `max()` or `clamp()`, e.g. `calc(100% - 2em)`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Calc {
+    Number(f32),
+    // A length or percentage.
+    Dimension(f32, Unit),
+    Sum(Box<Calc>, Box<Calc>),
+    Difference(Box<Calc>, Box<Calc>),
+    Product(Box<Calc>, Box<Calc>),
+    Quotient(Box<Calc>, Box<Calc>),
+    Min(Vec<Calc>),
+    Max(Vec<Calc>),
+    // `clamp(min, value, max)`.
+    Clamp(Box<Calc>, Box<Calc>, Box<Calc>),
+}
+
+/// What an expression resolves to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CalcType {
+    Number,
+    Length,
+    Percentage,
+    // A length that depends on a percentage, e.g. `100% - 2em`.
+    LengthPercentage,
+}
+
+impl Calc {
+    /// The type of the expression, or `None` if it mixes incompatible types, such as in
+    /// `1px + 2` or `1px * 2px`.
+    pub fn check(&self) -> Option<CalcType> {
+        match self {
+            Calc::Number(_) => Some(CalcType::Number),
+            Calc::Dimension(_, Unit::Pr) => Some(CalcType::Percentage),
+            Calc::Dimension(..) => Some(CalcType::Length),
+            Calc::Sum(a, b) | Calc::Difference(a, b) => add_types(a.check()?, b.check()?),
+            Calc::Product(a, b) => match (a.check()?, b.check()?) {
+                (CalcType::Number, t) | (t, CalcType::Number) => Some(t),
+                _ => None,
+            },
+            Calc::Quotient(a, b) => match (a.check()?, b.check()?) {
+                (t, CalcType::Number) => Some(t),
+                _ => None,
+            },
+            Calc::Min(args) | Calc::Max(args) => {
+                let (first, rest) = args.split_first()?;
+                rest.iter()
+                    .try_fold(first.check()?, |t, arg| add_types(t, arg.check()?))
+            }
+            Calc::Clamp(min, value, max) => {
+                add_types(add_types(min.check()?, value.check()?)?, max.check()?)
+            }
+        }
+    }
+
+    /// Compute the expression as far as possible without knowing the containing block:
+    /// relative lengths such as `em` become pixels, and everything but the operations on
+    /// percentages is folded into a single value.
+    pub fn simplify(self, lengths: &LengthContext) -> Calc {
+        let calc = self.absolutize(lengths);
+        if !calc.has_percentage() {
+            return match calc.check() {
+                Some(CalcType::Number) => Calc::Number(calc.evaluate(0.0)),
+                _ => Calc::Dimension(calc.evaluate(0.0), Unit::Px),
+            };
+        }
+        let linear = calc
+            .linear()
+            .map(|(px, percent)| (finite(px), finite(percent)));
+        match linear {
+            Some((px, percent)) if px == 0.0 => Calc::Dimension(percent, Unit::Pr),
+            Some((px, percent)) if px < 0.0 => Calc::Difference(
+                Box::new(Calc::Dimension(percent, Unit::Pr)),
+                Box::new(Calc::Dimension(-px, Unit::Px)),
+            ),
+            Some((px, percent)) => Calc::Sum(
+                Box::new(Calc::Dimension(percent, Unit::Pr)),
+                Box::new(Calc::Dimension(px, Unit::Px)),
+            ),
+            None => calc.map_args(&|arg| arg.simplify(lengths)),
+        }
+    }
+
+    /// The value of the expression, with percentages resolved against `basis`. Relative
+    /// lengths must have been converted by `simplify` first.
+    pub fn evaluate(&self, basis: f32) -> f32 {
+        finite(self.value(basis))
+    }
+
+    // The value of the expression, which may be infinite or NaN after a division by zero.
+    // Operands may be, as in `min(1px / 0, 10px)`, as long as the result is not.
+    fn value(&self, basis: f32) -> f32 {
+        match self {
+            Calc::Number(n) => *n,
+            Calc::Dimension(n, Unit::Pr) => n / 100.0 * basis,
+            Calc::Dimension(n, _) => *n,
+            Calc::Sum(a, b) => a.value(basis) + b.value(basis),
+            Calc::Difference(a, b) => a.value(basis) - b.value(basis),
+            Calc::Product(a, b) => a.value(basis) * b.value(basis),
+            Calc::Quotient(a, b) => a.value(basis) / b.value(basis),
+            Calc::Min(args) => args
+                .iter()
+                .map(|arg| arg.value(basis))
+                .fold(f32::INFINITY, f32::min),
+            Calc::Max(args) => args
+                .iter()
+                .map(|arg| arg.value(basis))
+                .fold(f32::NEG_INFINITY, f32::max),
+            Calc::Clamp(min, value, max) => value
+                .value(basis)
+                .min(max.value(basis))
+                .max(min.value(basis)),
+        }
+    }
+
+    /// The expression as a plain value if it has been simplified down to one.
+    pub fn into_value(self) -> Value {
+        match self {
+            Calc::Number(n) => Value::Number(n),
+            Calc::Dimension(n, unit) => Value::Length(n, unit),
+            calc => Value::Calc(Box::new(calc)),
+        }
+    }
+
+    fn has_percentage(&self) -> bool {
+        match self {
+            Calc::Number(_) => false,
+            Calc::Dimension(_, unit) => *unit == Unit::Pr,
+            Calc::Sum(a, b)
+            | Calc::Difference(a, b)
+            | Calc::Product(a, b)
+            | Calc::Quotient(a, b) => a.has_percentage() || b.has_percentage(),
+            Calc::Min(args) | Calc::Max(args) => args.iter().any(Calc::has_percentage),
+            Calc::Clamp(min, value, max) => {
+                min.has_percentage() || value.has_percentage() || max.has_percentage()
+            }
+        }
+    }
+
+    fn absolutize(self, lengths: &LengthContext) -> Calc {
+        match self {
+            Calc::Dimension(n, unit) => match lengths.to_px(n, &unit) {
+                Some(px) => Calc::Dimension(px, Unit::Px),
+                None => Calc::Dimension(n, unit),
+            },
+            calc => calc.map_args(&|arg| arg.absolutize(lengths)),
+        }
+    }
+
+    // Apply `f` to the operands of the expression.
+    fn map_args(self, f: &dyn Fn(Calc) -> Calc) -> Calc {
+        let boxed = |calc: Box<Calc>| Box::new(f(*calc));
+        match self {
+            Calc::Sum(a, b) => Calc::Sum(boxed(a), boxed(b)),
+            Calc::Difference(a, b) => Calc::Difference(boxed(a), boxed(b)),
+            Calc::Product(a, b) => Calc::Product(boxed(a), boxed(b)),
+            Calc::Quotient(a, b) => Calc::Quotient(boxed(a), boxed(b)),
+            Calc::Min(args) => Calc::Min(args.into_iter().map(f).collect()),
+            Calc::Max(args) => Calc::Max(args.into_iter().map(f).collect()),
+            Calc::Clamp(min, value, max) => Calc::Clamp(boxed(min), boxed(value), boxed(max)),
+            leaf => leaf,
+        }
+    }
+
+    // The expression as `px + percent%`, unless it takes the minimum or maximum of values.
+    // Numbers count as pixels, which is only meaningful for factors.
+    fn linear(&self) -> Option<(f32, f32)> {
+        match self {
+            Calc::Number(n) => Some((*n, 0.0)),
+            Calc::Dimension(n, Unit::Pr) => Some((0.0, *n)),
+            Calc::Dimension(n, _) => Some((*n, 0.0)),
+            Calc::Sum(a, b) | Calc::Difference(a, b) => {
+                let (a, b) = (a.linear()?, b.linear()?);
+                let sign = if let Calc::Sum(..) = self { 1.0 } else { -1.0 };
+                Some((a.0 + sign * b.0, a.1 + sign * b.1))
+            }
+            Calc::Product(a, b) => match (a.check()?, a.linear()?, b.linear()?) {
+                (CalcType::Number, (factor, _), (px, percent))
+                | (_, (px, percent), (factor, _)) => Some((px * factor, percent * factor)),
+            },
+            Calc::Quotient(a, b) => {
+                let ((px, percent), (divisor, _)) = (a.linear()?, b.linear()?);
+                Some((px / divisor, percent / divisor))
+            }
+            Calc::Min(_) | Calc::Max(_) | Calc::Clamp(..) => None,
+        }
+    }
+}
+
+// Results beyond this, such as those of dividing by zero, are clamped to it, so that layout
+// only ever sees finite lengths.
+const LARGEST_VALUE: f32 = 1e7;
+
+// The result of an expression as the value it stands for: infinities are clamped to the
+// largest value, and NaN is 0.
+fn finite(n: f32) -> f32 {
+    match n.is_nan() {
+        true => 0.0,
+        false => n.clamp(-LARGEST_VALUE, LARGEST_VALUE),
+    }
+}
+
+fn add_types(a: CalcType, b: CalcType) -> Option<CalcType> {
+    match (a, b) {
+        (a, b) if a == b => Some(a),
+        (CalcType::Number, _) | (_, CalcType::Number) => None,
+        _ => Some(CalcType::LengthPercentage),
+    }
+}
+
+impl fmt::Display for Calc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Calc::Number(n) => write!(f, "{}", n),
+            Calc::Dimension(n, unit) => write!(f, "{}{}", n, unit),
+            Calc::Sum(a, b) => write!(f, "{} + {}", a, b),
+            Calc::Difference(a, b) => {
+                write!(f, "{} - ", a)?;
+                write_operand(f, b)
+            }
+            Calc::Product(a, b) | Calc::Quotient(a, b) => {
+                write_operand(f, a)?;
+                write!(
+                    f,
+                    " {} ",
+                    if let Calc::Product(..) = self {
+                        '*'
+                    } else {
+                        '/'
+                    }
+                )?;
+                write_operand(f, b)
+            }
+            Calc::Min(args) | Calc::Max(args) => {
+                write!(
+                    f,
+                    "{}(",
+                    if let Calc::Min(_) = self {
+                        "min"
+                    } else {
+                        "max"
+                    }
+                )?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", arg)?;
+                }
+                write!(f, ")")
+            }
+            Calc::Clamp(min, value, max) => write!(f, "clamp({}, {}, {})", min, value, max),
+        }
+    }
+}
+
+// Sums and differences need parentheses when they are operands of another operation.
+fn write_operand(f: &mut fmt::Formatter, calc: &Calc) -> fmt::Result {
+    match calc {
+        Calc::Sum(..) | Calc::Difference(..) => write!(f, "({})", calc),
+        calc => write!(f, "{}", calc),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::css::parse_value;
+
+    const LENGTHS: LengthContext = LengthContext {
+        font_size: 20.0,
+        root_font_size: 16.0,
+        viewport_width: 800.0,
+        viewport_height: 600.0,
+    };
+
+    fn calc(text: &str) -> Calc {
+        match parse_value(text) {
+            Some(Value::Calc(calc)) => *calc,
+            value => panic!("Not a math function: {:?}", value),
+        }
+    }
+
+    #[test]
+    fn types_are_checked() {
+        assert_eq!(calc("calc(1px + 2em)").check(), Some(CalcType::Length));
+        assert_eq!(calc("calc(2 * 3)").check(), Some(CalcType::Number));
+        assert_eq!(calc("calc(50% / 2)").check(), Some(CalcType::Percentage));
+        assert_eq!(
+            calc("min(100% - 1em, 300px)").check(),
+            Some(CalcType::LengthPercentage)
+        );
+        let px = || Box::new(Calc::Dimension(1.0, Unit::Px));
+        let two = || Box::new(Calc::Number(2.0));
+        assert_eq!(Calc::Sum(px(), two()).check(), None);
+        assert_eq!(Calc::Product(px(), px()).check(), None);
+        assert_eq!(Calc::Quotient(two(), px()).check(), None);
+        // Math functions of mixed types are invalid values.
+        assert_eq!(parse_value("clamp(1px, 2, 3px)"), None);
+        assert_eq!(parse_value("calc(2 / 1px)"), None);
+    }
+
+    #[test]
+    fn relative_lengths_are_folded_into_pixels() {
+        let simplified = calc("calc(2em + 1rem - 10vw / 2)").simplify(&LENGTHS);
+        assert_eq!(simplified, Calc::Dimension(16.0, Unit::Px));
+        assert_eq!(
+            calc("calc(2 * 3 + 1)").simplify(&LENGTHS),
+            Calc::Number(7.0)
+        );
+        let simplified = calc("max(1em, 30px, 1in / 4)").simplify(&LENGTHS);
+        assert_eq!(simplified, Calc::Dimension(30.0, Unit::Px));
+    }
+
+    #[test]
+    fn percentages_are_kept_for_layout() {
+        let simplified = calc("calc((100% - 2em) / 2)").simplify(&LENGTHS);
+        assert_eq!(simplified.to_string(), "50% - 20px");
+        assert_eq!(simplified.evaluate(200.0), 80.0);
+        let simplified = calc("min(100%, 1em * 10)").simplify(&LENGTHS);
+        assert_eq!(simplified.to_string(), "min(100%, 200px)");
+        assert_eq!(simplified.evaluate(100.0), 100.0);
+        assert_eq!(simplified.evaluate(1000.0), 200.0);
+        let simplified = calc("clamp(10px, 50%, 100px)").simplify(&LENGTHS);
+        assert_eq!(simplified.evaluate(0.0), 10.0);
+        assert_eq!(simplified.evaluate(1000.0), 100.0);
+    }
+
+    #[test]
+    fn division_by_zero_gives_finite_values() {
+        let simplified = calc("calc(1px / 0)").simplify(&LENGTHS);
+        assert_eq!(simplified, Calc::Dimension(LARGEST_VALUE, Unit::Px));
+        assert_eq!(calc("calc(-1px / 0)").evaluate(0.0), -LARGEST_VALUE);
+        assert_eq!(calc("calc(0px / 0)").evaluate(0.0), 0.0);
+        let simplified = calc("calc(100% / 0)").simplify(&LENGTHS);
+        assert_eq!(simplified.evaluate(1000.0), LARGEST_VALUE);
+        // Operands may be infinite as long as the result is not.
+        assert_eq!(calc("min(1px / 0, 10px)").evaluate(0.0), 10.0);
+    }
+}
@@ -0,0 +1,1355 @@
+use std::{collections::HashMap, mem, slice};
+
+use crate::{
+    calc::{Calc, CalcType},
+    css::{parse_value, Color, Unit, Value},
+    media::Viewport,
+    properties::{properties, property, ComputedStyle},
+    styles::Display,
+    styles::PropertyMap,
+    variables::{
+        custom_properties, is_custom_property, matches_syntax, substitute_vars, Registrations,
+    },
+};
+
+// The font size of the root element when nothing else is specified.
+const MEDIUM_FONT_SIZE: f32 = 16.0;
+
+/// What lengths in relative units are relative to: the font size of the element for `em`,
+/// that of the root element for `rem`, and the viewport for `vw`, `vh`, `vmin` and `vmax`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LengthContext {
+    pub font_size: f32,
+    pub root_font_size: f32,
+    pub viewport_width: f32,
+    pub viewport_height: f32,
+}
+
+impl LengthContext {
+    /// `n` in `unit` converted to pixels, or `None` for percentages, whose basis depends on
+    /// the property.
+    pub fn to_px(&self, n: f32, unit: &Unit) -> Option<f32> {
+        let px = match *unit {
+            Unit::Px => 1.0,
+            Unit::Em => self.font_size,
+            Unit::Rem => self.root_font_size,
+            Unit::Vw => self.viewport_width / 100.0,
+            Unit::Vh => self.viewport_height / 100.0,
+            Unit::Vmin => self.viewport_width.min(self.viewport_height) / 100.0,
+            Unit::Vmax => self.viewport_width.max(self.viewport_height) / 100.0,
+            // An inch is 96 pixels.
+            Unit::Pt => 96.0 / 72.0,
+            Unit::Pc => 96.0 / 6.0,
+            Unit::In => 96.0,
+            Unit::Cm => 96.0 / 2.54,
+            Unit::Mm => 96.0 / 25.4,
+            Unit::Q => 96.0 / 101.6,
+            Unit::Pr => return None,
+        };
+        Some(n * px)
+    }
+}
+
+/// Turn the cascaded values of an element into computed values: apply inheritance, initial
+/// values and the CSS-wide keywords, then make values absolute (relative lengths in pixels,
+/// `currentcolor` as a color, font keywords as numbers). `root` is the style of the root
+/// element, or `None` when styling the root element itself.
+///
+/// Custom properties are computed first and substituted for the `var()` references in other
+/// values. Properties registered with `@property` are computed like any other typed value.
+///
+/// A value that does not have the type of its property, such as `width: red`, is invalid at
+/// computed-value time and the property behaves as `unset`. Unknown properties are dropped.
+pub fn compute_values(
+    cascaded: PropertyMap,
+    parent: Option<&ComputedStyle>,
+    root: Option<&ComputedStyle>,
+    viewport: &Viewport,
+    registrations: &Registrations,
+) -> ComputedStyle {
+    let (custom, cascaded): (PropertyMap, PropertyMap) = cascaded
+        .into_iter()
+        .partition(|(name, _)| is_custom_property(name));
+    let custom = custom_properties(custom, parent.map(|p| &p.custom), registrations);
+
+    let mut specified = HashMap::new();
+
+    for (name, value) in cascaded
+        .into_iter()
+        .filter(|(name, _)| property(name).is_some())
+    {
+        // A value whose references cannot be substituted is invalid at computed-value time,
+        // which makes the property behave as `unset`.
+        let value = substitute_vars(&name, value, &custom)
+            .unwrap_or_else(|| Value::Keyword("unset".to_string()));
+        specified.insert(name, value);
+    }
+
+    for property in properties() {
+        let inherit = |parent: Option<&ComputedStyle>| {
+            parent
+                .and_then(|p| p.get(property.name))
+                .unwrap_or_else(|| property.initial.clone())
+        };
+        let value = match specified_keyword(&specified, property.name) {
+            // `revert` has already been rolled back to a lower origin during the cascade, so
+            // if it is still here no lower origin specified a value and it acts like `unset`.
+            Some("inherit") => inherit(parent),
+            Some("initial") => property.initial.clone(),
+            Some("unset") | Some("revert") | None if property.inherited => inherit(parent),
+            Some("unset") | Some("revert") | None => property.initial.clone(),
+            Some(_) => specified[property.name].clone(),
+        };
+        specified.insert(property.name.to_string(), value);
+    }
+
+    let mut style = ComputedStyle::initial().clone();
+    let set = |style: &mut ComputedStyle, name: &str, value: Value, lengths, color: &Value| {
+        if !style.set(name, &value) {
+            let property = property(name).unwrap();
+            match parent.filter(|_| property.inherited) {
+                Some(parent) => style.copy(name, parent),
+                None => {
+                    let initial = compute_value(property.initial.clone(), lengths, color);
+                    style.set(name, &initial);
+                }
+            }
+        }
+    };
+
+    // Other values may be relative to the font size and color, so compute them first. The
+    // font size itself is relative to the parent's.
+    let parent_lengths = LengthContext {
+        font_size: parent.map_or(MEDIUM_FONT_SIZE, |p| p.font.font_size),
+        root_font_size: root.map_or(MEDIUM_FONT_SIZE, |r| r.font.font_size),
+        viewport_width: viewport.width,
+        viewport_height: viewport.height,
+    };
+    let font_size = compute_font_size(&specified["font-size"], &parent_lengths);
+    style.font.font_size = font_size;
+    let lengths = &LengthContext {
+        font_size,
+        ..parent_lengths
+    };
+
+    let parent_color = parent.map_or(&ComputedStyle::initial().text.color, |p| &p.text.color);
+    let color = match specified["color"] {
+        Value::Keyword(ref k) if k == "currentcolor" => Value::Color(parent_color.clone()),
+        ref color => color.clone(),
+    };
+    set(
+        &mut style,
+        "color",
+        color,
+        lengths,
+        &Value::Color(parent_color.clone()),
+    );
+    let color = Value::Color(style.text.color.clone());
+
+    let parent_font_weight = parent.map_or(400.0, |p| p.font.font_weight as f32);
+
+    for (name, value) in specified {
+        let value = match &*name {
+            "font-size" | "color" => continue,
+            "font-weight" => compute_font_weight(value, parent_font_weight),
+            // Percentages are of the font size, and compute to the length.
+            "line-height" | "text-decoration-thickness" => match value {
+                Value::Length(n, Unit::Pr) => Value::Length(n / 100.0 * font_size, Unit::Px),
+                value => compute_value(value, lengths, &color),
+            },
+            _ => compute_value(value, lengths, &color),
+        };
+        set(&mut style, &name, value, lengths, &color);
+    }
+
+    for (name, text) in custom {
+        let value = match registrations.get(&*name) {
+            Some(registration) => {
+                let value =
+                    parse_value(&text).filter(|value| matches_syntax(&registration.syntax, value));
+                if let Some(value) = value {
+                    compute_value(value, lengths, &color)
+                } else {
+                    // Invalid at computed-value time, so the property acts as `unset`.
+                    let inherited = parent
+                        .and_then(|p| p.custom.get(&name))
+                        .filter(|_| registration.inherits);
+                    match inherited.or(registration.initial.as_ref()) {
+                        Some(value) => value.clone(),
+                        None => continue,
+                    }
+                }
+            }
+            None => Value::Unparsed(text),
+        };
+        style.custom.insert(name, value);
+    }
+    style
+}
+
+pub fn is_css_wide_keyword(keyword: &str) -> bool {
+    matches!(keyword, "inherit" | "initial" | "unset" | "revert")
+}
+
+fn specified_keyword<'v>(values: &'v PropertyMap, name: &str) -> Option<&'v str> {
+    match values.get(name) {
+        Some(Value::Keyword(k)) if is_css_wide_keyword(k) => Some(k),
+        Some(_) => Some(""),
+        None => None,
+    }
+}
+
+fn compute_value(value: Value, lengths: &LengthContext, color: &Value) -> Value {
+    match value {
+        Value::Length(n, ref unit) if *unit != Unit::Pr => match lengths.to_px(n, unit) {
+            Some(px) => Value::Length(px, Unit::Px),
+            None => value,
+        },
+        Value::Calc(calc) => calc.simplify(lengths).into_value(),
+        Value::Keyword(ref k) if k == "currentcolor" => color.clone(),
+        Value::List(values) => Value::List(
+            values
+                .into_iter()
+                .map(|v| compute_value(v, lengths, color))
+                .collect(),
+        ),
+        Value::CommaList(values) => Value::CommaList(
+            values
+                .into_iter()
+                .map(|v| compute_value(v, lengths, color))
+                .collect(),
+        ),
+        value => value,
+    }
+}
+
+// `lengths` has the font size of the parent, which `em` and percentages are relative to.
+fn compute_font_size(value: &Value, lengths: &LengthContext) -> f32 {
+    let parent_font_size = lengths.font_size;
+    match *value {
+        Value::Length(n, Unit::Pr) => n / 100.0 * parent_font_size,
+        Value::Length(n, ref unit) => lengths.to_px(n, unit).unwrap_or(parent_font_size),
+        Value::Calc(ref calc) => calc.clone().simplify(lengths).evaluate(parent_font_size),
+        Value::Keyword(ref k) => match &**k {
+            "xx-small" => 9.0,
+            "x-small" => 10.0,
+            "small" => 13.0,
+            "medium" => MEDIUM_FONT_SIZE,
+            "large" => 18.0,
+            "x-large" => 24.0,
+            "xx-large" => 32.0,
+            "xxx-large" => 48.0,
+            "larger" => parent_font_size * 1.2,
+            "smaller" => parent_font_size / 1.2,
+            _ => parent_font_size,
+        },
+        _ => parent_font_size,
+    }
+}
+
+fn compute_font_weight(value: Value, parent_weight: f32) -> Value {
+    let weight = match value {
+        Value::Number(n) => n,
+        Value::Keyword(ref k) => match &**k {
+            "normal" => 400.0,
+            "bold" => 700.0,
+            // Relative weights follow the table in CSS Fonts 4, section 2.2.1.
+            "bolder" if parent_weight < 350.0 => 400.0,
+            "bolder" if parent_weight < 550.0 => 700.0,
+            "bolder" => parent_weight.max(900.0),
+            "lighter" if parent_weight < 550.0 => parent_weight.min(100.0),
+            "lighter" if parent_weight < 750.0 => 400.0,
+            "lighter" => 700.0,
+            _ => parent_weight,
+        },
+        _ => parent_weight,
+    };
+    Value::Number(weight)
+}
+
+/// Conversion between the typed computed value of a property and the generic `Value` that
+/// stylesheets and the cascade work with.
+pub trait ComputedValue: Sized + Clone + Default {
+    /// The typed value, or `None` if `value` does not have this type.
+    fn from_value(value: &Value) -> Option<Self>;
+    fn to_value(&self) -> Value;
+
+    /// Whether a math function that resolves to `calc` is a valid value of this type.
+    fn accepts_calc(_calc: CalcType) -> bool {
+        false
+    }
+
+    /// Whether `value`, other than a math function, can be specified for this type. Values
+    /// that only turn out to be invalid once computed are accepted.
+    fn accepts(_value: &Value) -> bool {
+        true
+    }
+}
+
+// Lengths in pixels.
+impl ComputedValue for f32 {
+    fn from_value(value: &Value) -> Option<Self> {
+        match *value {
+            Value::Length(n, Unit::Px) => Some(n),
+            Value::Number(n) if n == 0.0 => Some(0.0),
+            _ => None,
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        Value::Length(*self, Unit::Px)
+    }
+
+    fn accepts_calc(calc: CalcType) -> bool {
+        calc == CalcType::Length
+    }
+}
+
+impl ComputedValue for u16 {
+    fn from_value(value: &Value) -> Option<Self> {
+        match *value {
+            Value::Number(n) if n >= 0.0 => Some(n as u16),
+            _ => None,
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        Value::Number(*self as f32)
+    }
+
+    fn accepts_calc(calc: CalcType) -> bool {
+        calc == CalcType::Number
+    }
+}
+
+// Keywords.
+impl ComputedValue for String {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Keyword(k) => Some(k.clone()),
+            _ => None,
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        Value::Keyword(self.clone())
+    }
+}
+
+impl ComputedValue for Color {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Color(color) => Some(color.clone()),
+            Value::Keyword(k) if k == "transparent" => Some(Color::default()),
+            _ => None,
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        Value::Color(self.clone())
+    }
+}
+
+impl ComputedValue for Display {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Keyword(k) => Some(match &**k {
+                "inline" => Display::Inline,
+                "block" => Display::Block,
+                "list-item" => Display::ListItem,
+                "none" => Display::None,
+                // Table, flex and grid layout are not implemented, so their boxes stack like
+                // blocks, and flow like inline boxes when they are inline-level.
+                "table" | "table-caption" | "table-header-group" | "table-row-group"
+                | "table-footer-group" | "table-row" | "table-cell" | "flex" | "grid"
+                | "flow-root" => Display::Block,
+                "inline-block" | "inline-table" | "inline-flex" | "inline-grid" => Display::Inline,
+                _ => return None,
+            }),
+            _ => None,
+        }
+    }
+
+    fn accepts(value: &Value) -> bool {
+        Display::from_value(value).is_some()
+    }
+
+    fn to_value(&self) -> Value {
+        let keyword = match self {
+            Display::Inline => "inline",
+            Display::Block => "block",
+            Display::ListItem => "list-item",
+            Display::None => "none",
+        };
+        Value::Keyword(keyword.to_string())
+    }
+}
+
+/// A length in pixels or a percentage of some basis, such as the width of the containing
+/// block.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LengthPercentage {
+    Length(f32),
+    Percentage(f32),
+    // An expression mixing both, simplified as far as possible without the basis.
+    Calc(Box<Calc>),
+}
+
+impl LengthPercentage {
+    /// The length in pixels, with percentages resolved against `basis`.
+    pub fn resolve(&self, basis: f32) -> f32 {
+        match self {
+            LengthPercentage::Length(n) => *n,
+            LengthPercentage::Percentage(n) => n / 100.0 * basis,
+            LengthPercentage::Calc(calc) => calc.evaluate(basis),
+        }
+    }
+}
+
+impl Default for LengthPercentage {
+    fn default() -> Self {
+        LengthPercentage::Length(0.0)
+    }
+}
+
+impl ComputedValue for LengthPercentage {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Length(n, Unit::Px) => Some(LengthPercentage::Length(*n)),
+            Value::Length(n, Unit::Pr) => Some(LengthPercentage::Percentage(*n)),
+            Value::Number(n) if *n == 0.0 => Some(LengthPercentage::Length(0.0)),
+            Value::Calc(calc) if calc.check().is_some_and(|t| t != CalcType::Number) => {
+                Some(LengthPercentage::Calc(calc.clone()))
+            }
+            _ => None,
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        match self {
+            LengthPercentage::Length(n) => Value::Length(*n, Unit::Px),
+            LengthPercentage::Percentage(n) => Value::Length(*n, Unit::Pr),
+            LengthPercentage::Calc(calc) => Value::Calc(calc.clone()),
+        }
+    }
+
+    fn accepts_calc(calc: CalcType) -> bool {
+        calc != CalcType::Number
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum LengthPercentageOrAuto {
+    #[default]
+    Auto,
+    LengthPercentage(LengthPercentage),
+}
+
+impl LengthPercentageOrAuto {
+    /// The length in pixels, with percentages resolved against `basis`, or `None` for `auto`.
+    pub fn resolve(&self, basis: f32) -> Option<f32> {
+        match self {
+            LengthPercentageOrAuto::Auto => None,
+            LengthPercentageOrAuto::LengthPercentage(length) => Some(length.resolve(basis)),
+        }
+    }
+}
+
+impl ComputedValue for LengthPercentageOrAuto {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Keyword(k) if k == "auto" => Some(LengthPercentageOrAuto::Auto),
+            value => {
+                LengthPercentage::from_value(value).map(LengthPercentageOrAuto::LengthPercentage)
+            }
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        match self {
+            LengthPercentageOrAuto::Auto => Value::Keyword("auto".to_string()),
+            LengthPercentageOrAuto::LengthPercentage(length) => length.to_value(),
+        }
+    }
+
+    fn accepts_calc(calc: CalcType) -> bool {
+        LengthPercentage::accepts_calc(calc)
+    }
+}
+
+// Implement `ComputedValue` for an enum of keywords.
+macro_rules! keyword_values {
+    ($Type:ident { $($keyword:literal => $variant:ident,)* }) => {
+        impl ComputedValue for $Type {
+            fn from_value(value: &Value) -> Option<Self> {
+                match value {
+                    $(Value::Keyword(k) if k == $keyword => Some($Type::$variant),)*
+                    _ => None,
+                }
+            }
+
+            fn to_value(&self) -> Value {
+                let keyword = match self {
+                    $($Type::$variant => $keyword,)*
+                };
+                Value::Keyword(keyword.to_string())
+            }
+        }
+    };
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Position {
+    #[default]
+    Static,
+    Relative,
+    Absolute,
+    Fixed,
+    Sticky,
+}
+
+keyword_values!(Position {
+    "static" => Static,
+    "relative" => Relative,
+    "absolute" => Absolute,
+    "fixed" => Fixed,
+    "sticky" => Sticky,
+});
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FontStyle {
+    #[default]
+    Normal,
+    Italic,
+    Oblique,
+}
+
+keyword_values!(FontStyle {
+    "normal" => Normal,
+    "italic" => Italic,
+    "oblique" => Oblique,
+});
+
+/// A family of `font-family`: the name of a family of fonts, or a generic family that stands
+/// for whichever of the usual fonts of its kind are installed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FontFamily {
+    Named(String),
+    Generic(GenericFamily),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GenericFamily {
+    Serif,
+    #[default]
+    SansSerif,
+    Monospace,
+    Cursive,
+    Fantasy,
+    SystemUi,
+}
+
+keyword_values!(GenericFamily {
+    "serif" => Serif,
+    "sans-serif" => SansSerif,
+    "monospace" => Monospace,
+    "cursive" => Cursive,
+    "fantasy" => Fantasy,
+    "system-ui" => SystemUi,
+});
+
+/// The families of `font-family`, most preferred first.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FontFamilyList(pub Vec<FontFamily>);
+
+impl ComputedValue for FontFamilyList {
+    fn from_value(value: &Value) -> Option<Self> {
+        let items = match value {
+            Value::CommaList(items) => &items[..],
+            item => std::slice::from_ref(item),
+        };
+        let family = |item: &Value| match item {
+            Value::Str(name) => Some(FontFamily::Named(name.clone())),
+            // Generic family keywords are case-insensitive, unlike names.
+            Value::Keyword(name) if !is_css_wide_keyword(name) => Some(
+                match GenericFamily::from_value(&Value::Keyword(name.to_ascii_lowercase())) {
+                    Some(generic) => FontFamily::Generic(generic),
+                    None => FontFamily::Named(name.clone()),
+                },
+            ),
+            // An unquoted name of several words, such as `Times New Roman`.
+            Value::List(words) => words
+                .iter()
+                .map(|word| match word {
+                    Value::Keyword(word) => Some(word.as_str()),
+                    _ => None,
+                })
+                .collect::<Option<Vec<_>>>()
+                .map(|words| FontFamily::Named(words.join(" "))),
+            _ => None,
+        };
+        items
+            .iter()
+            .map(family)
+            .collect::<Option<_>>()
+            .map(FontFamilyList)
+    }
+
+    fn to_value(&self) -> Value {
+        let mut items: Vec<Value> = self
+            .0
+            .iter()
+            .map(|family| match family {
+                FontFamily::Named(name) => Value::Str(name.clone()),
+                FontFamily::Generic(generic) => generic.to_value(),
+            })
+            .collect();
+        match items.len() {
+            1 => items.pop().unwrap(),
+            _ => Value::CommaList(items),
+        }
+    }
+}
+
+/// Where lines may break between letters, besides the usual places.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WordBreak {
+    #[default]
+    Normal,
+    // Between any two letters of a word.
+    BreakAll,
+    // Nowhere between letters, not even in CJK text.
+    KeepAll,
+    // Deprecated, like `normal` with `overflow-wrap: anywhere`.
+    BreakWord,
+}
+
+keyword_values!(WordBreak {
+    "normal" => Normal,
+    "break-all" => BreakAll,
+    "keep-all" => KeepAll,
+    "break-word" => BreakWord,
+});
+
+/// Whether a word too long for its line may break where there is no break opportunity.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverflowWrap {
+    #[default]
+    Normal,
+    Anywhere,
+    BreakWord,
+}
+
+keyword_values!(OverflowWrap {
+    "normal" => Normal,
+    "anywhere" => Anywhere,
+    "break-word" => BreakWord,
+});
+
+/// How strictly lines break around CJK punctuation and small kana.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LineBreak {
+    #[default]
+    Auto,
+    Loose,
+    Normal,
+    Strict,
+    // Between any two characters, around spaces and punctuation too.
+    Anywhere,
+}
+
+keyword_values!(LineBreak {
+    "auto" => Auto,
+    "loose" => Loose,
+    "normal" => Normal,
+    "strict" => Strict,
+    "anywhere" => Anywhere,
+});
+
+/// Inline base direction: the direction text is read in and lines start from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Direction {
+    #[default]
+    Ltr,
+    Rtl,
+}
+
+keyword_values!(Direction {
+    "ltr" => Ltr,
+    "rtl" => Rtl,
+});
+
+/// How an element takes part in the bidirectional algorithm: whether it opens an embedding
+/// or isolate in its `direction`, and whether that overrides the direction of its characters.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnicodeBidi {
+    #[default]
+    Normal,
+    Embed,
+    Isolate,
+    BidiOverride,
+    IsolateOverride,
+    // Each paragraph takes its direction from its first strong character.
+    Plaintext,
+}
+
+keyword_values!(UnicodeBidi {
+    "normal" => Normal,
+    "embed" => Embed,
+    "isolate" => Isolate,
+    "bidi-override" => BidiOverride,
+    "isolate-override" => IsolateOverride,
+    "plaintext" => Plaintext,
+});
+
+/// How the content of a line is aligned in the line box. `start` and `end` depend on the
+/// direction of the paragraph.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TextAlign {
+    #[default]
+    Start,
+    End,
+    Left,
+    Right,
+    Center,
+    // Spaces grow so lines fill the width, except the last line of a paragraph and lines
+    // that end at a forced break, which align to the start.
+    Justify,
+}
+
+keyword_values!(TextAlign {
+    "start" => Start,
+    "end" => End,
+    "left" => Left,
+    "right" => Right,
+    "center" => Center,
+    "justify" => Justify,
+});
+
+/// The height of the line boxes text takes up, out of which its font's ascent and descent
+/// are centered.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum LineHeight {
+    // As the font asks for.
+    #[default]
+    Normal,
+    // A multiple of the font size. Unlike a length, it is inherited as the number.
+    Number(f32),
+    Length(f32),
+}
+
+impl LineHeight {
+    /// The height in pixels for text of `font_size` whose font asks for `normal`.
+    pub fn resolve(&self, font_size: f32, normal: f32) -> f32 {
+        match *self {
+            LineHeight::Normal => normal,
+            LineHeight::Number(n) => n * font_size,
+            LineHeight::Length(n) => n,
+        }
+    }
+}
+
+impl ComputedValue for LineHeight {
+    fn from_value(value: &Value) -> Option<Self> {
+        match *value {
+            Value::Keyword(ref k) if k == "normal" => Some(LineHeight::Normal),
+            Value::Number(n) if n >= 0.0 => Some(LineHeight::Number(n)),
+            Value::Length(n, Unit::Px) if n >= 0.0 => Some(LineHeight::Length(n)),
+            _ => None,
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        match *self {
+            LineHeight::Normal => Value::Keyword("normal".to_string()),
+            LineHeight::Number(n) => Value::Number(n),
+            LineHeight::Length(n) => Value::Length(n, Unit::Px),
+        }
+    }
+
+    fn accepts_calc(calc: CalcType) -> bool {
+        matches!(calc, CalcType::Number | CalcType::Length)
+    }
+}
+
+/// Extra space in pixels between letters or after words, for `letter-spacing` and
+/// `word-spacing`. `normal` adds none.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Spacing(pub f32);
+
+impl ComputedValue for Spacing {
+    fn from_value(value: &Value) -> Option<Self> {
+        match *value {
+            Value::Keyword(ref k) if k == "normal" => Some(Spacing(0.0)),
+            _ => f32::from_value(value).map(Spacing),
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        self.0.to_value()
+    }
+
+    fn accepts_calc(calc: CalcType) -> bool {
+        f32::accepts_calc(calc)
+    }
+}
+
+/// Case changes applied to text before it is laid out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TextTransform {
+    #[default]
+    None,
+    // The first letter of each word in upper case.
+    Capitalize,
+    Uppercase,
+    Lowercase,
+    // ASCII characters as their full-width forms, to line up with CJK text.
+    FullWidth,
+}
+
+keyword_values!(TextTransform {
+    "none" => None,
+    "capitalize" => Capitalize,
+    "uppercase" => Uppercase,
+    "lowercase" => Lowercase,
+    "full-width" => FullWidth,
+});
+
+/// Whether white space collapses and newlines are kept, and whether lines wrap.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WhiteSpace {
+    #[default]
+    Normal,
+    Pre,
+    Nowrap,
+    PreWrap,
+    // Like `pre-wrap`, but spaces at the end of a line take up room and may wrap.
+    BreakSpaces,
+    PreLine,
+}
+
+impl WhiteSpace {
+    /// Whether runs of spaces and tabs collapse to a single space.
+    pub fn collapses_spaces(&self) -> bool {
+        matches!(
+            self,
+            WhiteSpace::Normal | WhiteSpace::Nowrap | WhiteSpace::PreLine
+        )
+    }
+
+    /// Whether newlines force line breaks, rather than collapsing like spaces.
+    pub fn preserves_newlines(&self) -> bool {
+        !matches!(self, WhiteSpace::Normal | WhiteSpace::Nowrap)
+    }
+
+    /// Whether lines may break other than at forced breaks.
+    pub fn wraps(&self) -> bool {
+        !matches!(self, WhiteSpace::Pre | WhiteSpace::Nowrap)
+    }
+}
+
+keyword_values!(WhiteSpace {
+    "normal" => Normal,
+    "pre" => Pre,
+    "nowrap" => Nowrap,
+    "pre-wrap" => PreWrap,
+    "break-spaces" => BreakSpaces,
+    "pre-line" => PreLine,
+});
+
+/// Which lines an element draws under, over and through its text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TextDecorationLine {
+    pub underline: bool,
+    pub overline: bool,
+    pub line_through: bool,
+}
+
+impl ComputedValue for TextDecorationLine {
+    fn from_value(value: &Value) -> Option<Self> {
+        let values = match value {
+            Value::List(values) => values.as_slice(),
+            value => slice::from_ref(value),
+        };
+        let mut line = TextDecorationLine::default();
+        // Blinking is valid, but text does not blink.
+        let mut blink = false;
+        for value in values {
+            let flag = match value {
+                Value::Keyword(k) => match &**k {
+                    "none" if values.len() == 1 => continue,
+                    "underline" => &mut line.underline,
+                    "overline" => &mut line.overline,
+                    "line-through" => &mut line.line_through,
+                    "blink" => &mut blink,
+                    _ => return None,
+                },
+                _ => return None,
+            };
+            if mem::replace(flag, true) {
+                return None;
+            }
+        }
+        Some(line)
+    }
+
+    fn to_value(&self) -> Value {
+        let lines = [
+            (self.underline, "underline"),
+            (self.overline, "overline"),
+            (self.line_through, "line-through"),
+        ];
+        let mut values: Vec<Value> = lines
+            .into_iter()
+            .filter(|&(set, _)| set)
+            .map(|(_, line)| Value::Keyword(line.to_string()))
+            .collect();
+        match values.len() {
+            0 => Value::Keyword("none".to_string()),
+            1 => values.remove(0),
+            _ => Value::List(values),
+        }
+    }
+}
+
+/// How decoration lines are drawn.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TextDecorationStyle {
+    #[default]
+    Solid,
+    Double,
+    Dotted,
+    Dashed,
+    Wavy,
+}
+
+keyword_values!(TextDecorationStyle {
+    "solid" => Solid,
+    "double" => Double,
+    "dotted" => Dotted,
+    "dashed" => Dashed,
+    "wavy" => Wavy,
+});
+
+/// How thick decoration lines are.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum TextDecorationThickness {
+    // As thick as the font asks for, since fonts say it anyway.
+    #[default]
+    Auto,
+    FromFont,
+    Length(f32),
+}
+
+impl ComputedValue for TextDecorationThickness {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Keyword(k) if k == "auto" => Some(TextDecorationThickness::Auto),
+            Value::Keyword(k) if k == "from-font" => Some(TextDecorationThickness::FromFont),
+            value => f32::from_value(value).map(TextDecorationThickness::Length),
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        match *self {
+            TextDecorationThickness::Auto => Value::Keyword("auto".to_string()),
+            TextDecorationThickness::FromFont => Value::Keyword("from-font".to_string()),
+            TextDecorationThickness::Length(n) => n.to_value(),
+        }
+    }
+
+    fn accepts_calc(calc: CalcType) -> bool {
+        f32::accepts_calc(calc)
+    }
+}
+
+/// A shadow of text, offset by `x` and `y` and blurred by a radius of `blur` pixels. Without
+/// a color it has the color of the text.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Shadow {
+    pub color: Option<Color>,
+    pub x: f32,
+    pub y: f32,
+    pub blur: f32,
+}
+
+impl ComputedValue for Shadow {
+    fn from_value(value: &Value) -> Option<Self> {
+        let values = match value {
+            Value::List(values) => values,
+            _ => return None,
+        };
+        // The color goes before or after the two or three lengths.
+        let (color, lengths) = match (values.split_first(), values.split_last()) {
+            (Some((first, rest)), _) if Color::from_value(first).is_some() => {
+                (Color::from_value(first), rest)
+            }
+            (_, Some((last, rest))) if Color::from_value(last).is_some() => {
+                (Color::from_value(last), rest)
+            }
+            _ => (None, values.as_slice()),
+        };
+        let lengths: Vec<f32> = lengths.iter().map(f32::from_value).collect::<Option<_>>()?;
+        match *lengths.as_slice() {
+            [x, y] => Some(Shadow {
+                color,
+                x,
+                y,
+                blur: 0.0,
+            }),
+            [x, y, blur] if blur >= 0.0 => Some(Shadow { color, x, y, blur }),
+            _ => None,
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        let mut values = vec![self.x.to_value(), self.y.to_value(), self.blur.to_value()];
+        values.extend(self.color.as_ref().map(Color::to_value));
+        Value::List(values)
+    }
+}
+
+/// The shadows of text, the first one drawn on top, or none for `none`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ShadowList(pub Vec<Shadow>);
+
+impl ComputedValue for ShadowList {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Keyword(k) if k == "none" => Some(ShadowList(Vec::new())),
+            Value::CommaList(values) => values
+                .iter()
+                .map(Shadow::from_value)
+                .collect::<Option<_>>()
+                .map(ShadowList),
+            value => Shadow::from_value(value).map(|shadow| ShadowList(vec![shadow])),
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        match self.0.len() {
+            0 => Value::Keyword("none".to_string()),
+            1 => self.0[0].to_value(),
+            _ => Value::CommaList(self.0.iter().map(Shadow::to_value).collect()),
+        }
+    }
+}
+
+/// The marks used by `open-quote` and `close-quote`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Quotes {
+    // Quotes appropriate for the language.
+    #[default]
+    Auto,
+    None,
+    // Open and close quote for each nesting level, outermost first.
+    Pairs(Vec<(String, String)>),
+}
+
+impl ComputedValue for Quotes {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Keyword(k) if k == "auto" => Some(Quotes::Auto),
+            Value::Keyword(k) if k == "none" => Some(Quotes::None),
+            Value::List(items) if items.len() % 2 == 0 => items
+                .chunks(2)
+                .map(|pair| match pair {
+                    [Value::Str(open), Value::Str(close)] => Some((open.clone(), close.clone())),
+                    _ => None,
+                })
+                .collect::<Option<_>>()
+                .map(Quotes::Pairs),
+            _ => None,
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        match self {
+            Quotes::Auto => Value::Keyword("auto".to_string()),
+            Quotes::None => Value::Keyword("none".to_string()),
+            Quotes::Pairs(pairs) => Value::List(
+                pairs
+                    .iter()
+                    .flat_map(|(open, close)| [Value::Str(open.clone()), Value::Str(close.clone())])
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// The `content` of a pseudo-element.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Content {
+    #[default]
+    Normal,
+    None,
+    Items(Vec<ContentItem>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ContentItem {
+    Text(String),
+    // `attr(name)`.
+    Attr(String),
+    // `counter(name, style)`.
+    Counter(String, String),
+    // `counters(name, separator, style)`.
+    Counters(String, String, String),
+    OpenQuote,
+    CloseQuote,
+    NoOpenQuote,
+    NoCloseQuote,
+}
+
+impl ContentItem {
+    fn from_value(value: &Value) -> Option<Self> {
+        let style = |rest: &[Value]| match rest {
+            [] => Some("decimal".to_string()),
+            [Value::Keyword(style)] => Some(style.clone()),
+            _ => None,
+        };
+        match value {
+            Value::Str(s) => Some(ContentItem::Text(s.clone())),
+            Value::Function(name, args) => match (&**name, &args[..]) {
+                ("attr", [Value::Keyword(attr)]) => Some(ContentItem::Attr(attr.clone())),
+                ("counter", [Value::Keyword(counter), rest @ ..]) => {
+                    Some(ContentItem::Counter(counter.clone(), style(rest)?))
+                }
+                ("counters", [Value::Keyword(counter), Value::Str(separator), rest @ ..]) => Some(
+                    ContentItem::Counters(counter.clone(), separator.clone(), style(rest)?),
+                ),
+                _ => None,
+            },
+            Value::Keyword(k) => match &**k {
+                "open-quote" => Some(ContentItem::OpenQuote),
+                "close-quote" => Some(ContentItem::CloseQuote),
+                "no-open-quote" => Some(ContentItem::NoOpenQuote),
+                "no-close-quote" => Some(ContentItem::NoCloseQuote),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        let keyword = |k: &str| Value::Keyword(k.to_string());
+        match self {
+            ContentItem::Text(s) => Value::Str(s.clone()),
+            ContentItem::Attr(attr) => Value::Function("attr".to_string(), vec![keyword(attr)]),
+            ContentItem::Counter(counter, style) => Value::Function(
+                "counter".to_string(),
+                vec![keyword(counter), keyword(style)],
+            ),
+            ContentItem::Counters(counter, separator, style) => Value::Function(
+                "counters".to_string(),
+                vec![
+                    keyword(counter),
+                    Value::Str(separator.clone()),
+                    keyword(style),
+                ],
+            ),
+            ContentItem::OpenQuote => keyword("open-quote"),
+            ContentItem::CloseQuote => keyword("close-quote"),
+            ContentItem::NoOpenQuote => keyword("no-open-quote"),
+            ContentItem::NoCloseQuote => keyword("no-close-quote"),
+        }
+    }
+}
+
+impl ComputedValue for Content {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Keyword(k) if k == "normal" => Some(Content::Normal),
+            Value::Keyword(k) if k == "none" => Some(Content::None),
+            Value::List(items) => items
+                .iter()
+                .map(ContentItem::from_value)
+                .collect::<Option<_>>()
+                .map(Content::Items),
+            item => ContentItem::from_value(item).map(|item| Content::Items(vec![item])),
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        match self {
+            Content::Normal => Value::Keyword("normal".to_string()),
+            Content::None => Value::Keyword("none".to_string()),
+            Content::Items(items) => Value::List(items.iter().map(ContentItem::to_value).collect()),
+        }
+    }
+}
+
+/// The counters of `counter-reset` or `counter-increment`, each with its value if one is
+/// given, e.g. `chapter section 2`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CounterList(pub Vec<(String, Option<i32>)>);
+
+impl ComputedValue for CounterList {
+    fn from_value(value: &Value) -> Option<Self> {
+        let items = match value {
+            Value::Keyword(k) if k == "none" => return Some(CounterList::default()),
+            Value::List(items) => &items[..],
+            item => std::slice::from_ref(item),
+        };
+        let mut counters: Vec<(String, Option<i32>)> = Vec::new();
+        for item in items {
+            match item {
+                Value::Keyword(name) if !is_css_wide_keyword(name) && name != "none" => {
+                    counters.push((name.clone(), None))
+                }
+                Value::Number(n) if n.fract() == 0.0 => match counters.last_mut() {
+                    Some((_, value @ None)) => *value = Some(*n as i32),
+                    _ => return None,
+                },
+                _ => return None,
+            }
+        }
+        Some(CounterList(counters))
+    }
+
+    fn to_value(&self) -> Value {
+        if self.0.is_empty() {
+            return Value::Keyword("none".to_string());
+        }
+        let mut items = Vec::new();
+        for (name, value) in &self.0 {
+            items.push(Value::Keyword(name.clone()));
+            items.extend(value.map(|n| Value::Number(n as f32)));
+        }
+        Value::List(items)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        css::{parse_css, parse_stylesheet, parse_value, Origin, Stylesheet},
+        datatypes::Node,
+        dom::parse_element,
+        styles::{style_tree, StyleNode},
+        tokenizer::Tokenizer,
+    };
+
+    const BLACK: Color = Color {
+        r: 0,
+        g: 0,
+        b: 0,
+        a: 255,
+    };
+
+    const GREEN: Color = Color {
+        r: 0,
+        g: 255,
+        b: 0,
+        a: 255,
+    };
+
+    const BLUE: Color = Color {
+        r: 0,
+        g: 0,
+        b: 255,
+        a: 255,
+    };
+
+    // The styles of the elements in `body`, in document order, with the author stylesheet
+    // `css` and the user stylesheet `user`.
+    fn styles(body: &str, user: &str, css: &str) -> Vec<Arc<ComputedStyle>> {
+        let html = format!("<html><body>{}</body></html>", body);
+        let mut tokenizer = Tokenizer::new(&html);
+        let mut tokens = Vec::new();
+        while let Some(token) = tokenizer.next_token() {
+            tokens.push(token);
+        }
+        let root = Node::Element(parse_element(&tokens).0);
+        let stylesheets: [Stylesheet; 2] = [
+            parse_stylesheet(user.to_string(), Origin::User),
+            parse_css(css.to_string()),
+        ];
+        let tree = style_tree(&root, &stylesheets, &Viewport::new(800.0, 600.0));
+        let mut styles = Vec::new();
+        collect(&tree.children[0], &mut styles);
+        styles
+    }
+
+    fn collect(node: &StyleNode, styles: &mut Vec<Arc<ComputedStyle>>) {
+        for child in &node.children {
+            if let (Node::Element(_), None) = (&*child.node, child.pseudo) {
+                styles.push(child.style.clone());
+                collect(child, styles);
+            }
+        }
+    }
+
+    fn px(n: f32) -> LengthPercentageOrAuto {
+        LengthPercentageOrAuto::LengthPercentage(LengthPercentage::Length(n))
+    }
+
+    #[test]
+    fn only_inherited_properties_are_inherited() {
+        let css = "body { color: #0000ff; font-size: 20px; margin-left: 30px } \
+                   p { font-size: 2em; margin-right: 1em }";
+        let styles = styles("<p>a</p>", "", css);
+        assert_eq!(styles[0].text.color, BLUE);
+        assert_eq!(styles[0].font.font_size, 40.0);
+        assert_eq!(styles[0].box_model.margin_left, px(0.0));
+        assert_eq!(styles[0].box_model.margin_right, px(40.0));
+    }
+
+    #[test]
+    fn css_wide_keywords() {
+        let css = "div { margin-left: 10px; color: #0000ff } \
+                   .inherit { margin-left: inherit; color: inherit } \
+                   .initial { margin-left: initial; color: initial } \
+                   .unset { margin-left: unset; color: unset }";
+        let styles = styles(
+            "<div><p class=\"inherit\">a</p><p class=\"initial\">b</p><p class=\"unset\">c</p></div>",
+            "",
+            css,
+        );
+        assert_eq!(styles[1].box_model.margin_left, px(10.0));
+        assert_eq!(styles[1].text.color, BLUE);
+        assert_eq!(styles[2].box_model.margin_left, px(0.0));
+        assert_eq!(styles[2].text.color, BLACK);
+        assert_eq!(styles[3].box_model.margin_left, px(0.0));
+        assert_eq!(styles[3].text.color, BLUE);
+    }
+
+    #[test]
+    fn revert_rolls_back_to_the_lower_origins() {
+        let user = "p { color: #00ff00 }";
+        let css = "div { color: #0000ff } p, strong { color: #ff0000; font-weight: 100 } \
+                   .revert { color: revert; font-weight: revert; display: revert }";
+        let styles = styles(
+            "<div><p class=\"revert\">a</p><strong class=\"revert\">b</strong></div>",
+            user,
+            css,
+        );
+        // To the user value, or the user-agent value without one.
+        assert_eq!(styles[1].text.color, GREEN);
+        assert_eq!(styles[1].box_model.display, Display::Block);
+        assert_eq!(styles[2].font.font_weight, 700);
+        // Without values from lower origins, the property is unset.
+        assert_eq!(styles[2].text.color, BLUE);
+        assert_eq!(styles[1].font.font_weight, 400);
+    }
+
+    #[test]
+    fn declarations_of_unknown_display_keywords_are_dropped() {
+        let stylesheet = parse_css("p { display: block; display: garbage; }".to_string());
+        let rule = &stylesheet.rules()[0];
+        assert_eq!(rule.declaration.len(), 1);
+        assert_eq!(rule.property("display"), parse_value("block").as_ref());
+    }
+
+    #[test]
+    fn unsupported_display_types_are_approximated() {
+        let display = |text: &str| Display::from_value(&parse_value(text).unwrap());
+        assert_eq!(display("inline-block"), Some(Display::Inline));
+        assert_eq!(display("flex"), Some(Display::Block));
+        assert_eq!(display("table-row"), Some(Display::Block));
+        assert_eq!(display("garbage"), None);
+    }
+}
@@ -0,0 +1,360 @@
+use std::{borrow::Cow, collections::HashMap, sync::Arc};
+
+use crate::{
+    computed::{Content, ContentItem, CounterList, Quotes},
+    css::PseudoElement,
+    datatypes::Node,
+    properties::ComputedStyle,
+    styles::{Display, StyleNode},
+};
+
+// Quotes used when no `quotes` property applies: “…” nested with ‘…’.
+const DEFAULT_QUOTES: [(&str, &str); 2] = [("\u{201C}", "\u{201D}"), ("\u{2018}", "\u{2019}")];
+
+/// Fill in the text of `::before`, `::after` and `::marker` boxes.
+///
+/// Counters and quote nesting depend on everything that precedes a box in document order,
+/// so this runs as a separate pass once the whole style tree has been built.
+pub fn generate_content(root: &mut StyleNode) {
+    let mut generator = ContentGenerator::default();
+    let mut created = Vec::new();
+    generator.visit(root, &mut created);
+}
+
+#[derive(Default)]
+struct ContentGenerator {
+    // Every counter in scope, innermost instance last.
+    counters: HashMap<String, Vec<i32>>,
+    quote_depth: usize,
+}
+
+impl ContentGenerator {
+    // `created` collects the counters instantiated by `node`. A counter is in scope for the
+    // element that created it, its descendants and its following siblings, so the caller pops
+    // them once it has visited all of its children.
+    fn visit(&mut self, node: &mut StyleNode, created: &mut Vec<String>) {
+        if let Node::Text(_) = *node.node {
+            return;
+        }
+
+        self.update_counters(node, created);
+
+        if node.pseudo.is_some() {
+            let text = match node.pseudo {
+                Some(PseudoElement::Marker) if !has_content(node) => self.marker_text(node),
+                _ => self.content_text(node),
+            };
+            node.children = vec![text_node(text, &node.style)];
+            return;
+        }
+
+        let mut scoped = Vec::new();
+        for child in &mut node.children {
+            self.visit(child, &mut scoped);
+        }
+        for name in scoped {
+            self.counters
+                .get_mut(&name)
+                .map(|instances| instances.pop());
+        }
+    }
+
+    fn update_counters(&mut self, node: &StyleNode, created: &mut Vec<String>) {
+        for (name, value) in counter_list(&node.style.content.counter_reset, 0) {
+            self.counters.entry(name.clone()).or_default().push(value);
+            created.push(name);
+        }
+
+        let mut increments = counter_list(&node.style.content.counter_increment, 1);
+        // List items implicitly increment the `list-item` counter used by their marker.
+        if node.pseudo.is_none()
+            && matches!(node.display(), Display::ListItem)
+            && !increments.iter().any(|(name, _)| name == "list-item")
+        {
+            increments.push(("list-item".to_string(), 1));
+        }
+
+        for (name, by) in increments {
+            let instances = self.counters.entry(name.clone()).or_default();
+            if instances.is_empty() {
+                instances.push(0);
+                created.push(name);
+            }
+            *instances.last_mut().unwrap() += by;
+        }
+    }
+
+    fn content_text(&mut self, node: &StyleNode) -> String {
+        let items = match node.style.content.content {
+            Content::Items(ref items) => &items[..],
+            Content::Normal | Content::None => &[],
+        };
+
+        let mut text = String::new();
+        for item in items {
+            match item {
+                ContentItem::Text(s) => text.push_str(s),
+                ContentItem::Attr(attr) => {
+                    if let Node::Element(ref elem) = *node.node {
+                        text.push_str(elem.attributes.get(attr).map_or("", |v| v));
+                    }
+                }
+                ContentItem::Counter(counter, style) => {
+                    let value = self
+                        .counters
+                        .get(counter)
+                        .and_then(|instances| instances.last().copied())
+                        .unwrap_or(0);
+                    text.push_str(&format_counter(value, style));
+                }
+                ContentItem::Counters(counter, separator, style) => {
+                    match self.counters.get(counter).filter(|i| !i.is_empty()) {
+                        Some(instances) => text.push_str(
+                            &instances
+                                .iter()
+                                .map(|&value| format_counter(value, style))
+                                .collect::<Vec<_>>()
+                                .join(separator),
+                        ),
+                        None => text.push_str(&format_counter(0, style)),
+                    }
+                }
+                ContentItem::OpenQuote => {
+                    text.push_str(self.quote(node, self.quote_depth).0);
+                    self.quote_depth += 1;
+                }
+                ContentItem::CloseQuote => {
+                    if self.quote_depth > 0 {
+                        self.quote_depth -= 1;
+                        text.push_str(self.quote(node, self.quote_depth).1);
+                    }
+                }
+                ContentItem::NoOpenQuote => self.quote_depth += 1,
+                ContentItem::NoCloseQuote => self.quote_depth = self.quote_depth.saturating_sub(1),
+            }
+        }
+        text
+    }
+
+    // The open and close quote for nesting level `depth`. Deeper levels than the `quotes`
+    // property lists reuse its last pair.
+    fn quote<'q>(&self, node: &'q StyleNode, depth: usize) -> (&'q str, &'q str) {
+        let pairs: Vec<(&str, &str)> = match node.style.text.quotes {
+            Quotes::None => return ("", ""),
+            Quotes::Pairs(ref pairs) => pairs
+                .iter()
+                .map(|(open, close)| (&**open, &**close))
+                .collect(),
+            Quotes::Auto => DEFAULT_QUOTES.to_vec(),
+        };
+        match pairs.get(depth).or(pairs.last()) {
+            Some(&pair) => pair,
+            None => ("", ""),
+        }
+    }
+
+    // The default marker for `list-style-type`, which markers inherit from their list item.
+    fn marker_text(&self, node: &StyleNode) -> String {
+        let style = &*node.style.text.list_style_type;
+        match style {
+            "none" => String::new(),
+            "disc" | "circle" | "square" => format!("{} ", format_counter(0, style)),
+            _ => {
+                let value = self
+                    .counters
+                    .get("list-item")
+                    .and_then(|instances| instances.last().copied())
+                    .unwrap_or(0);
+                format!("{}. ", format_counter(value, style))
+            }
+        }
+    }
+}
+
+fn text_node<'a>(text: String, parent: &ComputedStyle) -> StyleNode<'a> {
+    StyleNode {
+        node: Cow::Owned(Node::Text(text)),
+        pseudo: None,
+        style: Arc::new(ComputedStyle::inherit(parent)),
+        children: Vec::new(),
+    }
+}
+
+fn has_content(node: &StyleNode) -> bool {
+    node.style.content.content != Content::Normal
+}
+
+// The counters of `counter-reset` or `counter-increment`, with `default` as the value of
+// those without one.
+fn counter_list(counters: &CounterList, default: i32) -> Vec<(String, i32)> {
+    counters
+        .0
+        .iter()
+        .map(|(name, value)| (name.clone(), value.unwrap_or(default)))
+        .collect()
+}
+
+/// Render a counter value in one of the predefined counter styles.
+pub fn format_counter(value: i32, style: &str) -> String {
+    match style {
+        "none" => String::new(),
+        "disc" => "\u{2022}".to_string(),
+        "circle" => "\u{25E6}".to_string(),
+        "square" => "\u{25AA}".to_string(),
+        "decimal-leading-zero" if (0..10).contains(&value) => format!("0{}", value),
+        "lower-roman" if value > 0 && value < 4000 => roman(value as u32),
+        "upper-roman" if value > 0 && value < 4000 => roman(value as u32).to_ascii_uppercase(),
+        "lower-alpha" | "lower-latin" if value > 0 => alphabetic(value as u32, 'a'..='z'),
+        "upper-alpha" | "upper-latin" if value > 0 => alphabetic(value as u32, 'A'..='Z'),
+        "lower-greek" if value > 0 => alphabetic(value as u32, '\u{3B1}'..='\u{3C9}'),
+        _ => value.to_string(),
+    }
+}
+
+fn roman(mut value: u32) -> String {
+    const NUMERALS: [(u32, &str); 13] = [
+        (1000, "m"),
+        (900, "cm"),
+        (500, "d"),
+        (400, "cd"),
+        (100, "c"),
+        (90, "xc"),
+        (50, "l"),
+        (40, "xl"),
+        (10, "x"),
+        (9, "ix"),
+        (5, "v"),
+        (4, "iv"),
+        (1, "i"),
+    ];
+    let mut s = String::new();
+    for (n, numeral) in NUMERALS {
+        while value >= n {
+            s.push_str(numeral);
+            value -= n;
+        }
+    }
+    s
+}
+
+// Bijective base-n numbering: a, b, ..., z, aa, ab, ...
+fn alphabetic(mut value: u32, digits: std::ops::RangeInclusive<char>) -> String {
+    let digits: Vec<char> = digits.filter(|&c| c != '\u{3C2}').collect();
+    let base = digits.len() as u32;
+    let mut s = Vec::new();
+    while value > 0 {
+        value -= 1;
+        s.push(digits[(value % base) as usize]);
+        value /= base;
+    }
+    s.iter().rev().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        css::parse_css, dom::parse_element, media::Viewport, styles::style_tree,
+        tokenizer::Tokenizer,
+    };
+
+    // The text of the pseudo-elements of `body` styled with `css`, in document order.
+    fn generated(body: &str, css: &str) -> Vec<(PseudoElement, String)> {
+        let html = format!("<html><body>{}</body></html>", body);
+        let mut tokenizer = Tokenizer::new(&html);
+        let mut tokens = Vec::new();
+        while let Some(token) = tokenizer.next_token() {
+            tokens.push(token);
+        }
+        let root = Node::Element(parse_element(&tokens).0);
+        let stylesheets = [parse_css(css.to_string())];
+        let tree = style_tree(&root, &stylesheets, &Viewport::new(800.0, 600.0));
+        let mut texts = Vec::new();
+        collect(&tree, &mut texts);
+        texts
+    }
+
+    fn collect(node: &StyleNode, texts: &mut Vec<(PseudoElement, String)>) {
+        if let Some(pseudo) = node.pseudo {
+            let text = node.children.iter().map(|child| match *child.node {
+                Node::Text(ref text) => text.as_str(),
+                Node::Element(_) => "",
+            });
+            texts.push((pseudo, text.collect()));
+        }
+        for child in &node.children {
+            collect(child, texts);
+        }
+    }
+
+    fn before(text: &str) -> (PseudoElement, String) {
+        (PseudoElement::Before, text.to_string())
+    }
+
+    fn marker(text: &str) -> (PseudoElement, String) {
+        (PseudoElement::Marker, text.to_string())
+    }
+
+    #[test]
+    fn before_and_after_boxes_need_content() {
+        let css = "p::before { content: \"[\" attr(title) } p::after { content: \"]\" } \
+                   em::before { color: #ff0000 } em::after { content: none }";
+        assert_eq!(
+            generated("<p title=\"t\">a<em>b</em></p>", css),
+            [before("[t"), (PseudoElement::After, "]".to_string())]
+        );
+    }
+
+    #[test]
+    fn counters_are_reset_incremented_and_nested() {
+        let css = "section { counter-reset: part } h1 { counter-increment: part } \
+                   h1::before { content: counter(part) \". \" counters(part, \".\", upper-roman) }";
+        assert_eq!(
+            generated(
+                "<section><h1>a</h1><h1>b</h1><section><h1>c</h1></section><h1>d</h1></section>",
+                css
+            ),
+            [
+                before("1. I"),
+                before("2. II"),
+                before("1. II.I"),
+                // The counter of the inner section is in scope for its following siblings.
+                before("2. II.II"),
+            ]
+        );
+        // Counters no element creates are created by the first element that increments them.
+        let css = "p { counter-increment: n 2 } p::before { content: counter(n, lower-alpha) }";
+        assert_eq!(
+            generated("<p>a</p><p>b</p>", css),
+            [before("b"), before("d")]
+        );
+    }
+
+    #[test]
+    fn list_items_have_markers() {
+        let css = "li::marker { color: #ff0000 }";
+        assert_eq!(
+            generated("<ol><li>a</li><li>b</li></ol><ul><li>c</li></ul>", css),
+            [marker("1. "), marker("2. "), marker("\u{2022} ")]
+        );
+        let css = "ol { list-style-type: lower-roman } li:last-child::marker { content: \"-\" }";
+        assert_eq!(
+            generated("<ol><li>a</li><li>b</li><li>c</li></ol>", css),
+            [marker("i. "), marker("ii. "), marker("-")]
+        );
+    }
+
+    #[test]
+    fn quotes_nest() {
+        let css = "q::before { content: open-quote } q::after { content: close-quote }";
+        assert_eq!(
+            generated("<p><q>a <q>b</q></q></p>", css),
+            [
+                before("\u{201C}"),
+                before("\u{2018}"),
+                (PseudoElement::After, "\u{2019}".to_string()),
+                (PseudoElement::After, "\u{201D}".to_string()),
+            ]
+        );
+    }
+}
@@ -1,19 +1,127 @@
-use std::{println, str::Chars};
+use std::{
+    fmt, mem,
+    ops::RangeInclusive,
+    println,
+    str::Chars,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{
+    calc::Calc,
+    computed::FontStyle,
+    cssom::next_generation,
+    media::{parse_media_query_list, MediaQueryList},
+    properties,
+    variables::{contains_var, is_custom_property, matches_syntax},
+};
 
 #[derive(Debug)]
 pub struct Stylesheet {
-    pub rules: Vec<Rule>,
+    // Modified through `rules_mut`, which starts a new generation.
+    rules: Vec<Rule>,
+    pub origin: Origin,
+    // Full names of the cascade layers in the order they are first declared, e.g. `["base",
+    // "base.reset", "theme"]`.
+    pub layers: Vec<String>,
+    // The `media` attribute of the `<style>` or `<link>` element the sheet came from.
+    pub media: Option<MediaQueryList>,
+    // Sheets with a title belong to the style set of that name; alternate sheets are only
+    // enabled when their style set is selected.
+    pub title: Option<String>,
+    pub alternate: bool,
+    pub disabled: bool,
+    // `@import` rules that have not been resolved yet, see `import::resolve_imports`.
+    pub imports: Vec<Import>,
+    // Custom properties registered with `@property`.
+    pub registrations: Vec<PropertyRegistration>,
+    // Fonts declared with `@font-face`.
+    pub font_faces: Vec<FontFace>,
+    // Changes whenever the sheet is modified, see `cssom::StyleVersion`.
+    pub generation: u64,
+}
+
+/// An `@import` rule, e.g. `@import url(base.css) layer(base) screen;`.
+#[derive(Debug)]
+pub struct Import {
+    pub url: String,
+    // Full name of the layer the imported rules are put in.
+    pub layer: Option<String>,
+    pub media: Option<MediaQueryList>,
+    // Number of layers declared before the import, so the imported sheet's layers can be
+    // inserted in declaration order.
+    pub layer_position: usize,
+}
+
+/// Where a stylesheet comes from. Origins are listed from lowest to highest precedence for
+/// normal declarations; `!important` declarations reverse the order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Origin {
+    UserAgent,
+    User,
+    Author,
+}
+
+/// A custom property registered with `@property`, which gives it a syntax its values are
+/// checked against, an initial value and possibly makes it not inherited.
+#[derive(Debug, Clone)]
+pub struct PropertyRegistration {
+    pub name: String,
+    pub syntax: String,
+    pub inherits: bool,
+    pub initial: Option<Value>,
+}
+
+/// A font declared with `@font-face`, which `font-family` can then name like an installed
+/// family.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FontFace {
+    pub family: String,
+    // Where to get the font, tried in order until one loads.
+    pub sources: Vec<FontSource>,
+    // The weights the face is used for, a range for variable fonts, e.g. `100 900`.
+    pub weight: (u16, u16),
+    pub style: FontStyle,
+    // The characters the face is used for, or all of them if empty.
+    pub unicode_range: Vec<RangeInclusive<u32>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FontSource {
+    // An installed font, by its full or PostScript name, e.g. `local("Open Sans Bold")`.
+    Local(String),
+    // A font file and the formats `format()` says it is in, so that files in formats that
+    // are not supported are not fetched.
+    Url(String, Vec<String>),
 }
 
 #[derive(Debug)]
 pub struct Rule {
     pub selector: Vec<Selector>,
     pub declaration: Vec<Declaration>,
+    // Full name of the cascade layer the rule belongs to, or `None` if it is unlayered.
+    pub layer: Option<String>,
+    // Conditions of the enclosing `@media` rules, which must all match for the rule to apply.
+    pub media: Vec<MediaQueryList>,
 }
 
 #[derive(Debug)]
 pub enum Selector {
     Simple(SimpleSelector),
+    // A selector combined with the compound selector to its right, e.g. `ul > li` is
+    // `Combined(ul, Child, li)`. The rightmost compound is the one the element must match.
+    Combined(Box<Selector>, Combinator, SimpleSelector),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Combinator {
+    // `a b`
+    Descendant,
+    // `a > b`
+    Child,
+    // `a + b`
+    NextSibling,
+    // `a ~ b`
+    SubsequentSibling,
 }
 
 #[derive(Debug)]
@@ -21,12 +129,43 @@ pub struct SimpleSelector {
     pub tag: Option<String>,
     pub id: Option<String>,
     pub class: Vec<String>,
+    pub pseudo_classes: Vec<PseudoClass>,
+    pub pseudo_element: Option<PseudoElement>,
+}
+
+#[derive(Debug)]
+pub enum PseudoClass {
+    Root,
+    Empty,
+    FirstChild,
+    LastChild,
+    OnlyChild,
+    FirstOfType,
+    LastOfType,
+    OnlyOfType,
+    // `a` and `area` elements with an `href`. No link counts as visited.
+    Link,
+    Visited,
+    // States of user interaction, which never apply since there is no interaction yet.
+    Hover,
+    Active,
+    Focus,
+    // `:not(a, .b)`, which takes a list of compound selectors.
+    Not(Vec<SimpleSelector>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PseudoElement {
+    Before,
+    After,
+    Marker,
 }
 
 #[derive(Debug)]
 pub struct Declaration {
     pub name: String,
     pub value: Value,
+    pub important: bool,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -34,16 +173,48 @@ pub enum Value {
     Keyword(String),
     Length(f32, Unit),
     Color(Color),
+    Number(f32),
+    Str(String),
+    // A functional notation such as `attr(title)` or `counter(item, upper-roman)`.
+    Function(String, Vec<Value>),
+    // A space separated list of component values, e.g. `content: "(" attr(id) ")"`.
+    List(Vec<Value>),
+    // A comma separated list of values, e.g. `font-family: "Open Sans", sans-serif`.
+    CommaList(Vec<Value>),
+    // A math function such as `calc(100% - 2em)` that is not yet computed down to a length.
+    Calc(Box<Calc>),
+    // A `/` between component values, e.g. `font: 12px/30px serif` or `aspect-ratio: 16 / 9`.
+    Slash,
+    // The text of a custom property, or of a value containing `var()` which can only be
+    // parsed once the references are substituted at computed-value time.
+    Unparsed(String),
+    // The value of a longhand set by a shorthand containing `var()`: the shorthand's name and
+    // its unparsed text.
+    PendingShorthand(String, String),
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Unit {
     Px,
     Em,
+    // Relative to the font size of the root element.
+    Rem,
+    // Hundredths of the width, height, or smaller or larger side of the viewport.
+    Vw,
+    Vh,
+    Vmin,
+    Vmax,
+    // Absolute units, which are fixed multiples of `px`.
+    Pt,
+    Pc,
+    In,
+    Cm,
+    Mm,
+    Q,
     Pr,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct Color {
     pub r: u8,
     pub g: u8,
@@ -52,24 +223,143 @@ pub struct Color {
 }
 
 pub fn parse_css(input: String) -> Stylesheet {
+    parse_stylesheet(input, Origin::Author)
+}
+
+pub fn parse_stylesheet(input: String, origin: Origin) -> Stylesheet {
     let mut parser = CSS::new(input);
 
     Stylesheet {
         rules: parser.parse_rules(),
+        origin,
+        layers: parser.layers,
+        media: None,
+        title: None,
+        alternate: false,
+        disabled: false,
+        imports: parser.imports,
+        registrations: parser.registrations,
+        font_faces: parser.font_faces,
+        generation: next_generation(),
+    }
+}
+
+impl Stylesheet {
+    /// The rules of the sheet in order. They are modified with `insert_rule`, `delete_rule`
+    /// and `rule_mut`.
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    // The rules to modify, which makes the sheet a new generation.
+    pub(crate) fn rules_mut(&mut self) -> &mut Vec<Rule> {
+        self.generation = next_generation();
+        &mut self.rules
+    }
+}
+
+/// Parse the text of a single property value, e.g. `1px solid` or `"\201C" "\201D"`, or
+/// `None` if it is not valid CSS.
+pub fn parse_value(input: &str) -> Option<Value> {
+    let mut parser = CSS::new(input.to_string());
+    parser.consume_whitespace();
+    let value = parser.parse_values()?;
+    // Anything left over, such as an unbalanced `}`, makes the whole value invalid.
+    match parser.eof() {
+        true => Some(value),
+        false => None,
+    }
+}
+
+/// Parse a list of declarations without the surrounding braces, such as the contents of a
+/// `style` attribute.
+pub fn parse_declaration_block(input: &str) -> Vec<Declaration> {
+    let mut parser = CSS::new(input.to_string());
+    let mut declarations = Vec::new();
+    loop {
+        parser.consume_whitespace();
+        if parser.eof() {
+            break;
+        }
+        match parser.parse_declaration() {
+            Some(declaration) => declarations.push(declaration),
+            None => parser.skip_declaration(),
+        }
+        // There is no block for a `}` to close here, so it is skipped like any invalid text.
+        if !parser.eof() && parser.next_char() == '}' {
+            parser.advance();
+        }
+    }
+    declarations
+}
+
+// One range of `unicode-range`: a code point such as `U+0131`, a range such as `U+0000-00FF`,
+// or code points with trailing wildcards such as `U+4??`, which stands for `U+400-4FF`.
+// Returns `None` if the range is invalid.
+fn parse_unicode_range(text: &str) -> Option<RangeInclusive<u32>> {
+    let text = text.trim();
+    let digits = match text.get(..2) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("u+") => &text[2..],
+        _ => return None,
+    };
+    let code_point = |digits: &str| match digits.len() {
+        1..=6 => u32::from_str_radix(digits, 16).ok(),
+        _ => None,
+    };
+    let (start, end) = match digits.split_once('-') {
+        Some((start, end)) => (code_point(start)?, code_point(end)?),
+        None if digits.contains('?') => (
+            code_point(&digits.replace('?', "0"))?,
+            code_point(&digits.replace('?', "F"))?,
+        ),
+        None => (code_point(digits)?, code_point(digits)?),
+    };
+    Some(start..=end).filter(|_| start <= end)
+}
+
+fn pseudo_element(name: &str) -> Option<PseudoElement> {
+    match name {
+        "before" => Some(PseudoElement::Before),
+        "after" => Some(PseudoElement::After),
+        "marker" => Some(PseudoElement::Marker),
+        _ => None,
     }
 }
 
+fn anonymous_layer() -> String {
+    let n = ANONYMOUS_LAYERS.fetch_add(1, Ordering::Relaxed) + 1;
+    format!("<anonymous-{}>", n)
+}
+
 pub struct CSS {
     pos: usize,
     chars: String,
+    // Name of the `@layer` block being parsed.
+    layer: Option<String>,
+    // Conditions of the `@media` blocks being parsed.
+    media: Vec<MediaQueryList>,
+    layers: Vec<String>,
+    imports: Vec<Import>,
+    registrations: Vec<PropertyRegistration>,
+    font_faces: Vec<FontFace>,
 }
 
+// Anonymous layers are numbered across all stylesheets, so that the anonymous layers of an
+// imported sheet never share a name with those of the importing sheet.
+static ANONYMOUS_LAYERS: AtomicUsize = AtomicUsize::new(0);
+
 impl CSS {
     pub fn new(input: String) -> Self {
         let chars = input;
         CSS {
             pos: 0,
             chars: chars,
+            layer: None,
+            media: Vec::new(),
+            layers: Vec::new(),
+            imports: Vec::new(),
+            registrations: Vec::new(),
+            font_faces: Vec::new(),
         }
     }
 
@@ -77,17 +367,26 @@ impl CSS {
         self.chars[self.pos..].chars().next().unwrap()
     }
 
-    pub fn parse_simple_selector(&mut self) -> SimpleSelector {
+    /// Parse a compound selector such as `li.item:first-child`, or return `None` if it is
+    /// empty or uses a pseudo-class or pseudo-element that is not supported.
+    pub fn parse_simple_selector(&mut self) -> Option<SimpleSelector> {
+        let start = self.pos;
         let mut selector = SimpleSelector {
             tag: None,
             id: None,
             class: Vec::new(),
+            pseudo_classes: Vec::new(),
+            pseudo_element: None,
         };
 
         while !self.eof() {
-            self.consume_whitespace();
-
             match self.next_char() {
+                // Nothing may follow a pseudo-element.
+                '#' | '.' | '*' | ':' | 'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_'
+                    if selector.pseudo_element.is_some() =>
+                {
+                    return None
+                }
                 '#' => {
                     self.advance();
                     selector.id = Some(self.parse_identifier());
@@ -99,6 +398,22 @@ impl CSS {
                 '*' => {
                     self.advance();
                 }
+                ':' => {
+                    self.advance();
+                    let double_colon = !self.eof() && self.next_char() == ':';
+                    if double_colon {
+                        self.advance();
+                    }
+                    let name = self.parse_identifier().to_ascii_lowercase();
+                    // `::before` and the legacy single colon `:before` are equivalent.
+                    match pseudo_element(&name) {
+                        Some(pseudo) => selector.pseudo_element = Some(pseudo),
+                        None if double_colon => return None,
+                        None => selector
+                            .pseudo_classes
+                            .push(self.parse_pseudo_class(&name)?),
+                    }
+                }
                 'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => {
                     selector.tag = Some(self.parse_identifier());
                 }
@@ -106,16 +421,64 @@ impl CSS {
             }
         }
 
-        return selector;
+        if self.pos == start {
+            return None;
+        }
+        Some(selector)
+    }
+
+    // A pseudo-class whose name has been read, with the arguments of functional ones.
+    fn parse_pseudo_class(&mut self, name: &str) -> Option<PseudoClass> {
+        let functional = !self.eof() && self.next_char() == '(';
+        let pseudo_class = match (name, functional) {
+            ("root", false) => PseudoClass::Root,
+            ("empty", false) => PseudoClass::Empty,
+            ("first-child", false) => PseudoClass::FirstChild,
+            ("last-child", false) => PseudoClass::LastChild,
+            ("only-child", false) => PseudoClass::OnlyChild,
+            ("first-of-type", false) => PseudoClass::FirstOfType,
+            ("last-of-type", false) => PseudoClass::LastOfType,
+            ("only-of-type", false) => PseudoClass::OnlyOfType,
+            ("link", false) | ("any-link", false) => PseudoClass::Link,
+            ("visited", false) => PseudoClass::Visited,
+            ("hover", false) => PseudoClass::Hover,
+            ("active", false) => PseudoClass::Active,
+            ("focus", false) => PseudoClass::Focus,
+            ("not", true) => {
+                self.advance();
+                let mut selectors = Vec::new();
+                loop {
+                    self.consume_whitespace();
+                    let selector = self.parse_simple_selector()?;
+                    // Pseudo-elements are not allowed in `:not()`.
+                    if selector.pseudo_element.is_some() {
+                        return None;
+                    }
+                    selectors.push(selector);
+                    self.consume_whitespace();
+                    if self.eof() {
+                        return None;
+                    }
+                    match self.next_char() {
+                        ',' => self.advance(),
+                        ')' => break,
+                        _ => return None,
+                    }
+                }
+                self.advance();
+                PseudoClass::Not(selectors)
+            }
+            _ => return None,
+        };
+        Some(pseudo_class)
     }
 
     fn advance(&mut self) {
-        self.pos += 1;
+        self.pos += self.next_char().len_utf8();
     }
     fn parse_identifier(&mut self) -> String {
         let mut identifier = String::new();
         while !self.eof() {
-            self.consume_whitespace();
             match self.next_char() {
                 'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => {
                     identifier.push(self.next_char());
@@ -127,161 +490,1644 @@ impl CSS {
         identifier
     }
 
+    // Parse rules up to the end of input or the `}` closing the enclosing block.
     fn parse_rules(&mut self) -> Vec<Rule> {
         let mut rules = Vec::new();
         loop {
             self.consume_whitespace();
-            if self.eof() {
+            if self.eof() || self.next_char() == '}' {
                 break;
             }
-            rules.push(self.parse_rule());
+            if self.next_char() == '@' {
+                rules.extend(self.parse_at_rule());
+            } else {
+                rules.extend(self.parse_rule());
+            }
         }
         rules
     }
 
-    fn parse_rule(&mut self) -> Rule {
-        Rule {
-            selector: self.parse_selectors(),
+    // A style rule. If its selector is invalid, the whole rule is dropped.
+    fn parse_rule(&mut self) -> Option<Rule> {
+        let selector = match self.parse_selectors() {
+            Some(selector) => selector,
+            None => {
+                self.skip_rule(false);
+                return None;
+            }
+        };
+        Some(Rule {
+            selector,
             declaration: self.parse_declarations(),
-        }
+            layer: self.layer.clone(),
+            media: self.media.clone(),
+        })
     }
-    fn parse_selectors(&mut self) -> Vec<Selector> {
-        let mut selectors = Vec::new();
-        loop {
-            selectors.push(Selector::Simple(self.parse_simple_selector()));
-            self.consume_whitespace();
+
+    // Skip the rest of an invalid rule, up to the end of its `{ ... }` block. At-rules without
+    // a block end at a `;` instead. The `}` closing an enclosing block is left in place.
+    fn skip_rule(&mut self, at_rule: bool) {
+        let mut depth = 0;
+        while !self.eof() {
             match self.next_char() {
-                ',' => {
+                ';' if at_rule && depth == 0 => {
                     self.advance();
-                    self.consume_whitespace();
+                    return;
                 }
-                '{' => break,
-                c => panic!("Unexpected character {} in selector list", c),
+                '}' if depth == 0 => return,
+                '}' if depth == 1 => {
+                    self.advance();
+                    return;
+                }
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => depth -= 1,
+                '"' | '\'' => {
+                    self.parse_string();
+                    continue;
+                }
+                _ => {}
             }
+            self.advance();
         }
-        selectors.sort_by(|a, b| b.specificity().cmp(&a.specificity()));
-        return selectors;
     }
-    fn parse_declarations(&mut self) -> Vec<Declaration> {
-        if self.next_char() != '{' {
-            panic!("Errr");
-        }
+
+    fn parse_at_rule(&mut self) -> Vec<Rule> {
+        assert_eq!(self.next_char(), '@');
         self.advance();
-        let mut declarations = Vec::new();
+        match &*self.parse_identifier().to_ascii_lowercase() {
+            "import" => self.parse_import_rule(),
+            "layer" => self.parse_layer_rule(),
+            "media" => self.parse_media_rule(),
+            "property" => self.parse_property_rule(),
+            "font-face" => self.parse_font_face_rule(),
+            // Unknown at-rules, such as `@keyframes` or `@charset`, are ignored.
+            _ => {
+                self.skip_rule(true);
+                Vec::new()
+            }
+        }
+    }
+
+    // `@layer a, b;` only declares the order of layers, while `@layer a { ... }` (or an
+    // anonymous `@layer { ... }`) also assigns the rules in the block to the layer.
+    fn parse_layer_rule(&mut self) -> Vec<Rule> {
+        let mut names: Vec<String> = Vec::new();
         loop {
             self.consume_whitespace();
-            if self.next_char() == '}' {
-                self.advance();
+            if self.eof() {
                 break;
             }
-            declarations.push(self.parse_declaration());
+            match self.next_char() {
+                '{' => break,
+                ';' => {
+                    self.advance();
+                    break;
+                }
+                ',' => self.advance(),
+                _ => match self.parse_layer_name() {
+                    name if name.is_empty() => {
+                        self.skip_rule(true);
+                        return Vec::new();
+                    }
+                    name => names.push(name),
+                },
+            }
         }
-        declarations
+        if self.eof() || self.next_char() != '{' {
+            for name in names {
+                self.declare_layer(&name);
+            }
+            return Vec::new();
+        }
+
+        let name = names.pop().unwrap_or_else(anonymous_layer);
+        let parent = self.layer.clone();
+        self.layer = Some(self.declare_layer(&name));
+
+        self.advance();
+        let rules = self.parse_rules();
+        self.close_block();
+
+        self.layer = parent;
+        rules
     }
 
-    fn parse_declaration(&mut self) -> Declaration {
-        let property_name = self.parse_identifier();
+    // `@import url(...) [layer | layer(name)] [media-query-list];`. The imported sheet is only
+    // recorded here and loaded later by `import::resolve_imports`.
+    // Invalid imports are ignored.
+    fn parse_import_rule(&mut self) -> Vec<Rule> {
+        match self.parse_import() {
+            Some(import) => self.imports.push(import),
+            None => self.skip_rule(true),
+        }
+        Vec::new()
+    }
+
+    fn parse_import(&mut self) -> Option<Import> {
         self.consume_whitespace();
-        assert_eq!(self.next_char(), ':');
+        let url = self.parse_url()?;
+        self.consume_whitespace();
+
+        let start = self.pos;
+        let layer = match &*self.parse_identifier().to_ascii_lowercase() {
+            "layer" if !self.eof() && self.next_char() == '(' => {
+                self.advance();
+                self.consume_whitespace();
+                let name = self.parse_layer_name();
+                self.consume_whitespace();
+                if name.is_empty() || self.eof() || self.next_char() != ')' {
+                    return None;
+                }
+                self.advance();
+                Some(name)
+            }
+            "layer" => Some(anonymous_layer()),
+            _ => {
+                self.pos = start;
+                None
+            }
+        };
+
+        let prelude = self.parse_prelude();
+        if !self.eof() {
+            if self.next_char() != ';' {
+                return None;
+            }
+            self.advance();
+        }
+
+        let layer = layer.map(|name| self.declare_layer(&name));
+        Some(Import {
+            url,
+            layer,
+            media: Some(prelude)
+                .filter(|media| !media.is_empty())
+                .map(|media| parse_media_query_list(&media)),
+            layer_position: self.layers.len(),
+        })
+    }
+
+    // A URL is either a string or `url(...)`, in which the address may be left unquoted.
+    fn parse_url(&mut self) -> Option<String> {
+        if !self.eof() && matches!(self.next_char(), '"' | '\'') {
+            return Some(self.parse_string());
+        }
+        let function = self.parse_identifier();
+        if !function.eq_ignore_ascii_case("url") || self.eof() || self.next_char() != '(' {
+            return None;
+        }
         self.advance();
         self.consume_whitespace();
-        let value = self.parse_value();
+
+        let url = if !self.eof() && matches!(self.next_char(), '"' | '\'') {
+            self.parse_string()
+        } else {
+            let start = self.pos;
+            while !self.eof() && !matches!(self.next_char(), ')' | ' ' | '\t' | '\n') {
+                self.advance();
+            }
+            self.chars[start..self.pos].to_string()
+        };
         self.consume_whitespace();
-        assert_eq!(self.next_char(), ';');
+        if self.eof() || self.next_char() != ')' {
+            return None;
+        }
         self.advance();
+        Some(url)
+    }
 
-        Declaration {
-            name: property_name,
-            value: value,
+    fn parse_media_rule(&mut self) -> Vec<Rule> {
+        let prelude = self.parse_prelude();
+        if self.eof() || self.next_char() != '{' {
+            self.skip_rule(true);
+            return Vec::new();
+        }
+        self.advance();
+
+        self.media.push(parse_media_query_list(&prelude));
+        let rules = self.parse_rules();
+        self.media.pop();
+
+        self.close_block();
+        rules
+    }
+
+    // Read the `}` that closes a block, which may be left out at the end of the input.
+    fn close_block(&mut self) {
+        if !self.eof() && self.next_char() == '}' {
+            self.advance();
         }
     }
 
-    fn consume_whitespace(&mut self) {
-        while !self.eof() {
-            match self.next_char() {
-                s => {
-                    if s.is_whitespace() {
-                        self.advance()
-                    } else {
-                        break;
-                    }
+    // `@property --name { syntax: "<length>"; inherits: false; initial-value: 0px }`. Invalid
+    // registrations are ignored.
+    fn parse_property_rule(&mut self) -> Vec<Rule> {
+        self.consume_whitespace();
+        let name = self.parse_identifier();
+        self.consume_whitespace();
+        if self.eof() || self.next_char() != '{' {
+            self.skip_rule(true);
+            return Vec::new();
+        }
+
+        let (mut syntax, mut inherits, mut initial) = (None, None, None);
+        for declaration in self.parse_declarations() {
+            match (&*declaration.name, declaration.value) {
+                ("syntax", Value::Str(s)) => syntax = Some(s),
+                ("inherits", Value::Keyword(k)) if k == "true" || k == "false" => {
+                    inherits = Some(k == "true")
                 }
-                _ => break,
+                ("initial-value", value) => initial = Some(value),
+                _ => {}
             }
         }
-    }
-    fn parse_value(&mut self) -> Value {
-        match self.next_char() {
-            '0'..='9' => self.parse_length(),
-            '#' => self.parse_color(),
-            _ => Value::Keyword(self.parse_identifier()),
+
+        if let (Some(syntax), Some(inherits)) = (syntax, inherits) {
+            let universal = syntax.trim() == "*";
+            let valid_initial = match initial {
+                Some(ref value) => universal || matches_syntax(&syntax, value),
+                None => universal,
+            };
+            if is_custom_property(&name) && valid_initial {
+                self.registrations.push(PropertyRegistration {
+                    name,
+                    syntax,
+                    inherits,
+                    initial,
+                });
+            }
         }
+        Vec::new()
     }
 
-    fn parse_length(&mut self) -> Value {
-        Value::Length(self.parse_float(), self.parse_unit())
-    }
+    // `@font-face { font-family: "Name"; src: url(name.woff2) format("woff2"), local(Name) }`,
+    // with optional `font-weight`, `font-style` and `unicode-range` descriptors. Faces without
+    // a family or sources are ignored, as are invalid descriptors and those this parser does
+    // not know.
+    fn parse_font_face_rule(&mut self) -> Vec<Rule> {
+        self.consume_whitespace();
+        if self.eof() || self.next_char() != '{' {
+            self.skip_rule(true);
+            return Vec::new();
+        }
+        self.advance();
 
-    fn parse_float(&mut self) -> f32 {
-        let mut s = String::new();
-        while !self.eof() {
-            match self.next_char() {
-                '0'..='9' | '.' => {
-                    s.push(self.next_char());
-                    self.advance();
+        let mut face = FontFace {
+            family: String::new(),
+            sources: Vec::new(),
+            weight: (400, 400),
+            style: FontStyle::Normal,
+            unicode_range: Vec::new(),
+        };
+        loop {
+            self.consume_whitespace();
+            if self.eof() || self.next_char() == '}' {
+                self.close_block();
+                break;
+            }
+            let descriptor = self.parse_identifier().to_ascii_lowercase();
+            self.consume_whitespace();
+            if self.eof() || self.next_char() != ':' {
+                self.skip_declaration();
+                continue;
+            }
+            self.advance();
+            self.consume_whitespace();
+            let valid = match &*descriptor {
+                "font-family" => match self.parse_family_name() {
+                    Some(family) if self.at_declaration_end() => {
+                        face.family = family;
+                        true
+                    }
+                    _ => false,
+                },
+                "src" => match self.parse_font_sources() {
+                    Some(sources) if self.at_declaration_end() => {
+                        face.sources = sources;
+                        true
+                    }
+                    _ => false,
+                },
+                "font-weight" => match self.parse_font_weight_range() {
+                    Some(weight) if self.at_declaration_end() => {
+                        face.weight = weight;
+                        true
+                    }
+                    _ => false,
+                },
+                "font-style" => match self.parse_font_style_range() {
+                    Some(style) => {
+                        face.style = style;
+                        true
+                    }
+                    None => false,
+                },
+                "unicode-range" => {
+                    let text = self.parse_unparsed_value();
+                    match text.split(',').map(parse_unicode_range).collect() {
+                        Some(unicode_range) => {
+                            face.unicode_range = unicode_range;
+                            true
+                        }
+                        None => false,
+                    }
                 }
-                _ => break,
+                _ => {
+                    self.parse_unparsed_value();
+                    true
+                }
+            };
+            match valid {
+                true => self.close_declaration(),
+                false => self.skip_declaration(),
             }
         }
-        s.parse().unwrap()
+
+        if !face.family.is_empty() && !face.sources.is_empty() {
+            self.font_faces.push(face);
+        }
+        Vec::new()
     }
 
-    fn parse_unit(&mut self) -> Unit {
-        match &*self.parse_identifier().to_ascii_lowercase() {
-            "px" => Unit::Px,
-            _ => panic!("unrecognized unit"),
+    // A family name is a string or a sequence of identifiers, e.g. `Open Sans`.
+    fn parse_family_name(&mut self) -> Option<String> {
+        if !self.eof() && matches!(self.next_char(), '"' | '\'') {
+            return Some(self.parse_string());
+        }
+        let start = self.pos;
+        while !self.eof() && !matches!(self.next_char(), ';' | '}' | ')' | ',') {
+            self.advance();
         }
+        let name = &self.chars[start..self.pos];
+        Some(name.split_whitespace().collect::<Vec<_>>().join(" ")).filter(|name| !name.is_empty())
     }
 
-    fn parse_color(&mut self) -> Value {
-        assert_eq!(self.next_char(), '#');
-        self.advance();
-        Value::Color(Color {
-            r: self.parse_hex_pair(),
-            g: self.parse_hex_pair(),
-            b: self.parse_hex_pair(),
-            a: 255,
-        })
+    // The comma separated sources of `src`. Sources that need a font technology named with
+    // `tech()` are left out, as none is supported.
+    fn parse_font_sources(&mut self) -> Option<Vec<FontSource>> {
+        let mut sources = Vec::new();
+        loop {
+            self.consume_whitespace();
+            let start = self.pos;
+            let mut source = if self.parse_identifier().eq_ignore_ascii_case("local") {
+                if self.eof() || self.next_char() != '(' {
+                    return None;
+                }
+                self.advance();
+                self.consume_whitespace();
+                let name = self.parse_family_name()?;
+                self.consume_whitespace();
+                if self.eof() || self.next_char() != ')' {
+                    return None;
+                }
+                self.advance();
+                FontSource::Local(name)
+            } else {
+                self.pos = start;
+                FontSource::Url(self.parse_url()?, Vec::new())
+            };
+            let mut supported = true;
+            loop {
+                self.consume_whitespace();
+                if self.eof() || !self.next_char().is_ascii_alphabetic() {
+                    break;
+                }
+                let function = self.parse_identifier().to_ascii_lowercase();
+                if self.eof() || self.next_char() != '(' {
+                    return None;
+                }
+                let arguments = self.parse_arguments()?;
+                match (&*function, &mut source) {
+                    ("format", FontSource::Url(_, formats)) => {
+                        for format in arguments {
+                            match format {
+                                Value::Str(s) | Value::Keyword(s) => {
+                                    formats.push(s.to_ascii_lowercase())
+                                }
+                                _ => return None,
+                            }
+                        }
+                    }
+                    ("tech", _) => supported = false,
+                    _ => return None,
+                }
+            }
+            if supported {
+                sources.push(source);
+            }
+            if self.eof() || self.next_char() != ',' {
+                break;
+            }
+            self.advance();
+        }
+        Some(sources)
     }
 
-    /// Parse two hexadecimal digits.
-    fn parse_hex_pair(&mut self) -> u8 {
-        let s = &self.chars[self.pos..self.pos + 2];
-        self.pos += 2;
-        u8::from_str_radix(s, 16).unwrap()
+    // `font-weight` of a face: one weight, or the least and greatest of a range.
+    fn parse_font_weight_range(&mut self) -> Option<(u16, u16)> {
+        let weight = |value: &Value| match value {
+            Value::Keyword(k) if k.eq_ignore_ascii_case("normal") => Some(400),
+            Value::Keyword(k) if k.eq_ignore_ascii_case("bold") => Some(700),
+            Value::Number(n) if (1.0..=1000.0).contains(n) => Some(*n as u16),
+            _ => None,
+        };
+        match self.parse_values()? {
+            Value::Keyword(k) if k.eq_ignore_ascii_case("auto") => Some((400, 400)),
+            Value::List(range) if range.len() == 2 => {
+                let (a, b) = (weight(&range[0])?, weight(&range[1])?);
+                Some((a.min(b), a.max(b)))
+            }
+            value => weight(&value).map(|weight| (weight, weight)),
+        }
     }
-    fn eof(&self) -> bool {
-        self.pos >= self.chars.len()
+
+    // `font-style` of a face. The angles that may follow `oblique` are skipped, as oblique
+    // faces are matched without them.
+    fn parse_font_style_range(&mut self) -> Option<FontStyle> {
+        let keyword = self.parse_identifier().to_ascii_lowercase();
+        let angles = self.parse_unparsed_value();
+        match &*keyword {
+            "normal" | "auto" | "italic" if !angles.is_empty() => None,
+            "normal" | "auto" => Some(FontStyle::Normal),
+            "italic" => Some(FontStyle::Italic),
+            "oblique" => Some(FontStyle::Oblique),
+            _ => None,
+        }
     }
-}
 
-pub type Specificity = (usize, usize, usize);
+    // Read the raw text of an at-rule prelude, up to the `{` or `;` that ends it.
+    fn parse_prelude(&mut self) -> String {
+        let start = self.pos;
+        while !self.eof() && !matches!(self.next_char(), '{' | ';') {
+            self.advance();
+        }
+        self.chars[start..self.pos].trim().to_string()
+    }
 
-impl Selector {
-    pub fn specificity(&self) -> Specificity {
-        let Selector::Simple(ref simple) = *self;
-        let a = simple.id.iter().count();
-        let b = simple.class.len();
-        let c = simple.tag.iter().count();
-        (a, b, c)
+    fn parse_layer_name(&mut self) -> String {
+        let mut name = self.parse_identifier();
+        while !self.eof() && self.next_char() == '.' {
+            self.advance();
+            name.push('.');
+            name.push_str(&self.parse_identifier());
+        }
+        name
     }
-}
 
-impl Value {
-    pub fn to_px(&self) -> f32 {
-        match *self {
-            Value::Length(f, Unit::Px) => f,
-            _ => 0.0,
+    /// Register the layer `name`, nested in the layer currently being parsed, and return its
+    /// full name. Enclosing layers are always declared before the layers nested in them.
+    fn declare_layer(&mut self, name: &str) -> String {
+        let mut full_name = self.layer.clone().unwrap_or_default();
+        for part in name.split('.') {
+            if !full_name.is_empty() {
+                full_name.push('.');
+            }
+            full_name.push_str(part);
+            if !self.layers.contains(&full_name) {
+                self.layers.push(full_name.clone());
+            }
+        }
+        full_name
+    }
+    // A selector list, or `None` if any of its selectors is invalid, which invalidates the
+    // whole list.
+    fn parse_selectors(&mut self) -> Option<Vec<Selector>> {
+        let mut selectors = Vec::new();
+        loop {
+            selectors.push(self.parse_selector()?);
+            match self.next_char() {
+                ',' => {
+                    self.advance();
+                    self.consume_whitespace();
+                }
+                '{' => break,
+                _ => return None,
+            }
         }
+        selectors.sort_by(|a, b| b.specificity().cmp(&a.specificity()));
+        Some(selectors)
+    }
+
+    // A complex selector such as `ul > li.item`, up to the `,` or `{` that ends it.
+    fn parse_selector(&mut self) -> Option<Selector> {
+        let mut selector = Selector::Simple(self.parse_simple_selector()?);
+        loop {
+            let start = self.pos;
+            self.consume_whitespace();
+            if self.eof() {
+                return None;
+            }
+            let combinator = match self.next_char() {
+                ',' | '{' => return Some(selector),
+                '>' => Combinator::Child,
+                '+' => Combinator::NextSibling,
+                '~' => Combinator::SubsequentSibling,
+                _ if self.pos > start => Combinator::Descendant,
+                _ => return None,
+            };
+            // Pseudo-elements can only be in the rightmost compound selector.
+            if selector.subject().pseudo_element.is_some() {
+                return None;
+            }
+            if combinator != Combinator::Descendant {
+                self.advance();
+                self.consume_whitespace();
+            }
+            let compound = self.parse_simple_selector()?;
+            selector = Selector::Combined(Box::new(selector), combinator, compound);
+        }
+    }
+
+    // The declarations in a `{ ... }` block. Invalid declarations are left out.
+    fn parse_declarations(&mut self) -> Vec<Declaration> {
+        assert_eq!(self.next_char(), '{');
+        self.advance();
+        let mut declarations = Vec::new();
+        loop {
+            self.consume_whitespace();
+            if self.eof() || self.next_char() == '}' {
+                self.close_block();
+                break;
+            }
+            match self.parse_declaration() {
+                Some(declaration) => declarations.push(declaration),
+                None => self.skip_declaration(),
+            }
+        }
+        declarations
+    }
+
+    // A declaration, or `None` if it is invalid, in which case the parser is left somewhere
+    // inside it for `skip_declaration` to skip the rest.
+    fn parse_declaration(&mut self) -> Option<Declaration> {
+        let property_name = self.parse_identifier();
+        self.consume_whitespace();
+        if property_name.is_empty() || self.eof() || self.next_char() != ':' {
+            return None;
+        }
+        self.advance();
+        self.consume_whitespace();
+        // Custom properties can hold almost any text, and values with `var()` references are
+        // only parsed once they are substituted.
+        let start = self.pos;
+        let text = self.parse_unparsed_value();
+        let value = if is_custom_property(&property_name) || contains_var(&text) {
+            Value::Unparsed(text)
+        } else {
+            self.pos = start;
+            match self.parse_values() {
+                Some(value) => value,
+                None => {
+                    // Go back to where the brackets of the value are balanced, rather than
+                    // leaving the parser inside a function such as `calc(100%-10px)`.
+                    self.pos = start;
+                    return None;
+                }
+            }
+        };
+        let important = self.parse_important()?;
+        let declaration = Declaration {
+            name: property_name,
+            value: value,
+            important,
+        };
+        if !properties::accepts(&declaration) {
+            return None;
+        }
+        // The semicolon after the last declaration in a block is optional.
+        if !self.eof() {
+            match self.next_char() {
+                ';' => self.advance(),
+                '}' => {}
+                _ => return None,
+            }
+        }
+        Some(declaration)
+    }
+
+    // Whether the value of a declaration has been read up to the `;` or `}` that ends it.
+    fn at_declaration_end(&mut self) -> bool {
+        self.consume_whitespace();
+        self.eof() || matches!(self.next_char(), ';' | '}')
+    }
+
+    // Read the `;` that ends a declaration, which is optional before a `}`.
+    fn close_declaration(&mut self) {
+        if !self.eof() && self.next_char() == ';' {
+            self.advance();
+        }
+    }
+
+    // Skip the rest of an invalid declaration, up to and including the `;` that ends it, or up
+    // to the `}` that closes the block.
+    fn skip_declaration(&mut self) {
+        let mut depth = 0;
+        while !self.eof() {
+            match self.next_char() {
+                ';' if depth == 0 => {
+                    self.advance();
+                    return;
+                }
+                '}' if depth == 0 => return,
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => depth -= 1,
+                '"' | '\'' => {
+                    self.parse_string();
+                    continue;
+                }
+                _ => {}
+            }
+            self.advance();
+        }
+    }
+
+    // Read the text of a value up to the `;`, `}` or `!important` that ends it, skipping over
+    // nested blocks and strings.
+    fn parse_unparsed_value(&mut self) -> String {
+        let start = self.pos;
+        let mut depth = 0;
+        while !self.eof() {
+            match self.next_char() {
+                ';' | '}' | '!' if depth == 0 => break,
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => depth -= 1,
+                '"' | '\'' => {
+                    self.parse_string();
+                    continue;
+                }
+                _ => {}
+            }
+            self.advance();
+        }
+        self.chars[start..self.pos].trim().to_string()
+    }
+
+    // `!important`, or `None` if the `!` is followed by anything else.
+    fn parse_important(&mut self) -> Option<bool> {
+        if self.eof() || self.next_char() != '!' {
+            return Some(false);
+        }
+        self.advance();
+        self.consume_whitespace();
+        if !self.parse_identifier().eq_ignore_ascii_case("important") {
+            return None;
+        }
+        self.consume_whitespace();
+        Some(true)
+    }
+
+    // Skip whitespace and `/* ... */` comments.
+    fn consume_whitespace(&mut self) {
+        while !self.eof() {
+            match self.next_char() {
+                '/' if self.chars[self.pos..].starts_with("/*") => {
+                    self.pos = match self.chars[self.pos + 2..].find("*/") {
+                        Some(end) => self.pos + 2 + end + 2,
+                        None => self.chars.len(),
+                    };
+                }
+                s => {
+                    if s.is_whitespace() {
+                        self.advance()
+                    } else {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    /// Parse the space separated component values of a declaration, up to the closing `;`.
+    /// Commas split them into a list of such values. Returns `None` if any of them is invalid
+    /// or a list is empty.
+    fn parse_values(&mut self) -> Option<Value> {
+        let space_separated = |mut values: Vec<Value>| {
+            if values.len() == 1 {
+                values.pop().unwrap()
+            } else {
+                Value::List(values)
+            }
+        };
+        let mut items = Vec::new();
+        let mut values = Vec::new();
+        while !self.eof() && !matches!(self.next_char(), ';' | '}' | '!') {
+            if self.next_char() == ',' {
+                if values.is_empty() {
+                    return None;
+                }
+                self.advance();
+                items.push(space_separated(mem::take(&mut values)));
+            } else {
+                values.push(self.parse_value()?);
+            }
+            self.consume_whitespace();
+        }
+        if values.is_empty() {
+            return None;
+        }
+        if items.is_empty() {
+            return Some(space_separated(values));
+        }
+        items.push(space_separated(values));
+        Some(Value::CommaList(items))
+    }
+
+    // A single component value, or `None` if there is none at the current position.
+    fn parse_value(&mut self) -> Option<Value> {
+        let start = self.pos;
+        let value = match self.next_char() {
+            '0'..='9' | '.' => self.parse_length()?,
+            '-' if self.starts_number() => self.parse_length()?,
+            '#' => self.parse_color()?,
+            '"' | '\'' => Value::Str(self.parse_string()),
+            '/' => {
+                self.advance();
+                Value::Slash
+            }
+            _ => {
+                let name = self.parse_identifier();
+                if !self.eof() && self.next_char() == '(' {
+                    let name = name.to_ascii_lowercase();
+                    match &*name {
+                        // The address in `url()` may be left unquoted, e.g. `url(data:x)`.
+                        "url" => {
+                            self.pos = start;
+                            Value::Function(name, vec![Value::Str(self.parse_url()?)])
+                        }
+                        "calc" | "min" | "max" | "clamp" => self.parse_math_function(&name)?,
+                        _ => Value::Function(name, self.parse_arguments()?),
+                    }
+                } else {
+                    Value::Keyword(name)
+                }
+            }
+        };
+        // A character no value starts with is not read at all. Such values are invalid, which
+        // also makes sure that the loops over values always move forward.
+        if self.pos == start {
+            return None;
+        }
+        Some(value)
+    }
+
+    fn starts_number(&self) -> bool {
+        let mut chars = self.chars[self.pos..].chars().skip(1);
+        matches!(chars.next(), Some('0'..='9' | '.'))
+    }
+
+    // A math function whose name has been read, or `None` if it is malformed or mixes
+    // incompatible types, such as `calc(1px + 2)`.
+    fn parse_math_function(&mut self, name: &str) -> Option<Value> {
+        let calc = self.parse_math_arguments(name)?;
+        calc.check()?;
+        Some(Value::Calc(Box::new(calc)))
+    }
+
+    fn parse_math_arguments(&mut self, name: &str) -> Option<Calc> {
+        assert_eq!(self.next_char(), '(');
+        self.advance();
+        let mut args = Vec::new();
+        loop {
+            self.consume_whitespace();
+            args.push(self.parse_calc_sum()?);
+            self.consume_whitespace();
+            if self.eof() {
+                return None;
+            }
+            match self.next_char() {
+                ',' => self.advance(),
+                ')' => {
+                    self.advance();
+                    break;
+                }
+                _ => return None,
+            }
+        }
+
+        match (name, args.len()) {
+            ("calc", 1) => args.pop(),
+            ("min", _) => Some(Calc::Min(args)),
+            ("max", _) => Some(Calc::Max(args)),
+            ("clamp", 3) => {
+                let max = Box::new(args.pop()?);
+                let value = Box::new(args.pop()?);
+                Some(Calc::Clamp(Box::new(args.pop()?), value, max))
+            }
+            _ => None,
+        }
+    }
+
+    fn parse_calc_sum(&mut self) -> Option<Calc> {
+        let mut sum = self.parse_calc_product()?;
+        loop {
+            // `+` and `-` must be surrounded by whitespace to tell them from signs.
+            let start = self.pos;
+            self.consume_whitespace();
+            let operator = match self.chars[self.pos..].chars().next() {
+                Some(c @ ('+' | '-')) if self.pos > start => c,
+                _ => {
+                    self.pos = start;
+                    return Some(sum);
+                }
+            };
+            self.advance();
+            self.consume_whitespace();
+            let operand = Box::new(self.parse_calc_product()?);
+            sum = match operator {
+                '+' => Calc::Sum(Box::new(sum), operand),
+                _ => Calc::Difference(Box::new(sum), operand),
+            };
+        }
+    }
+
+    fn parse_calc_product(&mut self) -> Option<Calc> {
+        let mut product = self.parse_calc_value()?;
+        loop {
+            let start = self.pos;
+            self.consume_whitespace();
+            let operator = match self.chars[self.pos..].chars().next() {
+                Some(c @ ('*' | '/')) => c,
+                _ => {
+                    self.pos = start;
+                    return Some(product);
+                }
+            };
+            self.advance();
+            self.consume_whitespace();
+            let operand = Box::new(self.parse_calc_value()?);
+            product = match operator {
+                '*' => Calc::Product(Box::new(product), operand),
+                _ => Calc::Quotient(Box::new(product), operand),
+            };
+        }
+    }
+
+    fn parse_calc_value(&mut self) -> Option<Calc> {
+        if self.eof() {
+            return None;
+        }
+        match self.next_char() {
+            '(' => {
+                self.advance();
+                self.consume_whitespace();
+                let calc = self.parse_calc_sum()?;
+                self.consume_whitespace();
+                if self.eof() || self.next_char() != ')' {
+                    return None;
+                }
+                self.advance();
+                Some(calc)
+            }
+            '0'..='9' | '.' => self.parse_calc_number(),
+            '-' if self.starts_number() => self.parse_calc_number(),
+            _ => {
+                let name = self.parse_identifier().to_ascii_lowercase();
+                match &*name {
+                    "calc" | "min" | "max" | "clamp" if !self.eof() && self.next_char() == '(' => {
+                        self.parse_math_arguments(&name)
+                    }
+                    "pi" => Some(Calc::Number(std::f32::consts::PI)),
+                    "e" => Some(Calc::Number(std::f32::consts::E)),
+                    _ => None,
+                }
+            }
+        }
+    }
+
+    fn parse_calc_number(&mut self) -> Option<Calc> {
+        match self.parse_length()? {
+            Value::Length(n, unit) => Some(Calc::Dimension(n, unit)),
+            Value::Number(n) => Some(Calc::Number(n)),
+            _ => None,
+        }
+    }
+
+    /// Parse the comma separated arguments of a functional notation, or `None` if one of them
+    /// is invalid or the closing `)` is missing.
+    fn parse_arguments(&mut self) -> Option<Vec<Value>> {
+        assert_eq!(self.next_char(), '(');
+        self.advance();
+        let mut args = Vec::new();
+        loop {
+            self.consume_whitespace();
+            if self.eof() {
+                return None;
+            }
+            match self.next_char() {
+                ')' => {
+                    self.advance();
+                    break;
+                }
+                ',' => self.advance(),
+                _ => args.push(self.parse_value()?),
+            }
+        }
+        Some(args)
+    }
+
+    /// Parse a quoted string, resolving backslash escapes. A string left open at the end of
+    /// the input ends there.
+    fn parse_string(&mut self) -> String {
+        let quote = self.next_char();
+        self.advance();
+        let mut s = String::new();
+        while !self.eof() {
+            let c = self.next_char();
+            self.advance();
+            match c {
+                c if c == quote => break,
+                '\\' => s.extend(self.parse_escape()),
+                c => s.push(c),
+            }
+        }
+        s
+    }
+
+    /// Parse the part of an escape sequence following the backslash: either up to six hex
+    /// digits (and one optional trailing space) or a single literal character.
+    fn parse_escape(&mut self) -> Option<char> {
+        let mut hex = String::new();
+        while hex.len() < 6 && !self.eof() && self.next_char().is_ascii_hexdigit() {
+            hex.push(self.next_char());
+            self.advance();
+        }
+        if hex.is_empty() {
+            if self.eof() {
+                return None;
+            }
+            let c = self.next_char();
+            self.advance();
+            return if c == '\n' { None } else { Some(c) };
+        }
+        if !self.eof() && self.next_char() == ' ' {
+            self.advance();
+        }
+        Some(char::from_u32(u32::from_str_radix(&hex, 16).unwrap()).unwrap_or('\u{FFFD}'))
+    }
+
+    // A number, percentage or length, or `None` if the number is malformed or the unit is not
+    // one of the supported ones.
+    fn parse_length(&mut self) -> Option<Value> {
+        let value = self.parse_float()?;
+        if !self.eof() && self.next_char() == '%' {
+            self.advance();
+            return Some(Value::Length(value, Unit::Pr));
+        }
+        let unit = match &*self.parse_identifier().to_ascii_lowercase() {
+            "" => return Some(Value::Number(value)),
+            "px" => Unit::Px,
+            "em" => Unit::Em,
+            "rem" => Unit::Rem,
+            "vw" => Unit::Vw,
+            "vh" => Unit::Vh,
+            "vmin" => Unit::Vmin,
+            "vmax" => Unit::Vmax,
+            "pt" => Unit::Pt,
+            "pc" => Unit::Pc,
+            "in" => Unit::In,
+            "cm" => Unit::Cm,
+            "mm" => Unit::Mm,
+            "q" => Unit::Q,
+            _ => return None,
+        };
+        Some(Value::Length(value, unit))
+    }
+
+    fn parse_float(&mut self) -> Option<f32> {
+        let mut s = String::new();
+        if self.next_char() == '-' {
+            s.push('-');
+            self.advance();
+        }
+        while !self.eof() {
+            match self.next_char() {
+                '0'..='9' | '.' => {
+                    s.push(self.next_char());
+                    self.advance();
+                }
+                _ => break,
+            }
+        }
+        s.parse().ok()
+    }
+
+    // A hex color, or `None` if it does not have 3, 4, 6 or 8 hexadecimal digits.
+    fn parse_color(&mut self) -> Option<Value> {
+        assert_eq!(self.next_char(), '#');
+        self.advance();
+        let digits = self.chars[self.pos..]
+            .chars()
+            .take_while(|c| c.is_ascii_hexdigit())
+            .count();
+        // The color ends with its digits, so `#abcz` is not `#abc` followed by `z`.
+        let end = self.chars[self.pos + digits..].chars().next();
+        if end.is_some_and(|c| c.is_alphanumeric() || matches!(c, '-' | '_')) {
+            return None;
+        }
+        let color = match digits {
+            // `#rgb` and `#rgba` repeat each digit, e.g. `#f00` is `#ff0000`.
+            3 | 4 => Value::Color(Color {
+                r: self.parse_hex_digit() * 0x11,
+                g: self.parse_hex_digit() * 0x11,
+                b: self.parse_hex_digit() * 0x11,
+                a: if digits == 4 {
+                    self.parse_hex_digit() * 0x11
+                } else {
+                    255
+                },
+            }),
+            6 | 8 => Value::Color(Color {
+                r: self.parse_hex_pair(),
+                g: self.parse_hex_pair(),
+                b: self.parse_hex_pair(),
+                a: if digits == 8 {
+                    self.parse_hex_pair()
+                } else {
+                    255
+                },
+            }),
+            _ => return None,
+        };
+        Some(color)
+    }
+
+    /// Parse two hexadecimal digits.
+    fn parse_hex_pair(&mut self) -> u8 {
+        let s = &self.chars[self.pos..self.pos + 2];
+        self.pos += 2;
+        u8::from_str_radix(s, 16).unwrap()
+    }
+
+    fn parse_hex_digit(&mut self) -> u8 {
+        let s = &self.chars[self.pos..self.pos + 1];
+        self.pos += 1;
+        u8::from_str_radix(s, 16).unwrap()
+    }
+    fn eof(&self) -> bool {
+        self.pos >= self.chars.len()
+    }
+}
+
+pub type Specificity = (usize, usize, usize);
+
+impl Selector {
+    pub fn specificity(&self) -> Specificity {
+        let (simple, (a, b, c)) = match *self {
+            Selector::Simple(ref simple) => (simple, (0, 0, 0)),
+            Selector::Combined(ref left, _, ref simple) => (simple, left.specificity()),
+        };
+        let (d, e, f) = simple.specificity();
+        (a + d, b + e, c + f)
+    }
+
+    /// The rightmost compound selector, which the element itself must match.
+    pub fn subject(&self) -> &SimpleSelector {
+        match *self {
+            Selector::Simple(ref simple) | Selector::Combined(_, _, ref simple) => simple,
+        }
+    }
+}
+
+impl SimpleSelector {
+    pub fn specificity(&self) -> Specificity {
+        let mut a = self.id.iter().count();
+        let mut b = self.class.len();
+        let mut c = self.tag.iter().count() + self.pseudo_element.iter().count();
+        for pseudo_class in &self.pseudo_classes {
+            match *pseudo_class {
+                // `:not()` counts as its most specific argument.
+                PseudoClass::Not(ref selectors) => {
+                    let (d, e, f) = selectors.iter().map(|s| s.specificity()).max().unwrap();
+                    (a, b, c) = (a + d, b + e, c + f);
+                }
+                _ => b += 1,
+            }
+        }
+        (a, b, c)
+    }
+}
+
+impl Value {
+    pub fn to_px(&self) -> f32 {
+        match *self {
+            Value::Length(f, Unit::Px) => f,
+            _ => 0.0,
+        }
+    }
+
+    /// The length in pixels, with percentages resolved against `basis`, such as the width of
+    /// the containing block.
+    pub fn resolve(&self, basis: f32) -> f32 {
+        match *self {
+            Value::Length(f, Unit::Pr) => f / 100.0 * basis,
+            Value::Calc(ref calc) => calc.evaluate(basis),
+            _ => self.to_px(),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Keyword(ref k) => write!(f, "{}", k),
+            Value::Length(n, ref unit) => write!(f, "{}{}", n, unit),
+            Value::Color(ref color) => write!(f, "{}", color),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::Str(ref s) => {
+                write!(f, "\"")?;
+                for c in s.chars() {
+                    match c {
+                        '"' | '\\' => write!(f, "\\{}", c)?,
+                        '\n' => write!(f, "\\a ")?,
+                        c => write!(f, "{}", c)?,
+                    }
+                }
+                write!(f, "\"")
+            }
+            Value::Function(ref name, ref args) => {
+                write!(f, "{}(", name)?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", arg)?;
+                }
+                write!(f, ")")
+            }
+            Value::List(ref values) => {
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                Ok(())
+            }
+            Value::CommaList(ref values) => {
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                Ok(())
+            }
+            Value::Calc(ref calc) => match **calc {
+                Calc::Min(_) | Calc::Max(_) | Calc::Clamp(..) => write!(f, "{}", calc),
+                ref calc => write!(f, "calc({})", calc),
+            },
+            Value::Slash => write!(f, "/"),
+            Value::Unparsed(ref text) | Value::PendingShorthand(_, ref text) => {
+                write!(f, "{}", text)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Unit::Px => write!(f, "px"),
+            Unit::Em => write!(f, "em"),
+            Unit::Rem => write!(f, "rem"),
+            Unit::Vw => write!(f, "vw"),
+            Unit::Vh => write!(f, "vh"),
+            Unit::Vmin => write!(f, "vmin"),
+            Unit::Vmax => write!(f, "vmax"),
+            Unit::Pt => write!(f, "pt"),
+            Unit::Pc => write!(f, "pc"),
+            Unit::In => write!(f, "in"),
+            Unit::Cm => write!(f, "cm"),
+            Unit::Mm => write!(f, "mm"),
+            Unit::Q => write!(f, "q"),
+            Unit::Pr => write!(f, "%"),
+        }
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)?;
+        if self.a != 255 {
+            write!(f, "{:02x}", self.a)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Declaration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.name, self.value)?;
+        if self.important {
+            write!(f, " !important")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Selector::Simple(ref simple) => write!(f, "{}", simple),
+            Selector::Combined(ref left, combinator, ref simple) => {
+                write!(f, "{}{}{}", left, combinator, simple)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Combinator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Combinator::Descendant => write!(f, " "),
+            Combinator::Child => write!(f, " > "),
+            Combinator::NextSibling => write!(f, " + "),
+            Combinator::SubsequentSibling => write!(f, " ~ "),
+        }
+    }
+}
+
+impl fmt::Display for SimpleSelector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.tag {
+            Some(ref tag) => write!(f, "{}", tag)?,
+            None if self.id.is_none() && self.class.is_empty() => write!(f, "*")?,
+            None => {}
+        }
+        if let Some(ref id) = self.id {
+            write!(f, "#{}", id)?;
+        }
+        for class in &self.class {
+            write!(f, ".{}", class)?;
+        }
+        for pseudo_class in &self.pseudo_classes {
+            write!(f, ":{}", pseudo_class)?;
+        }
+        if let Some(pseudo) = self.pseudo_element {
+            write!(f, "::{}", pseudo)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for PseudoClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PseudoClass::Root => write!(f, "root"),
+            PseudoClass::Empty => write!(f, "empty"),
+            PseudoClass::FirstChild => write!(f, "first-child"),
+            PseudoClass::LastChild => write!(f, "last-child"),
+            PseudoClass::OnlyChild => write!(f, "only-child"),
+            PseudoClass::FirstOfType => write!(f, "first-of-type"),
+            PseudoClass::LastOfType => write!(f, "last-of-type"),
+            PseudoClass::OnlyOfType => write!(f, "only-of-type"),
+            PseudoClass::Link => write!(f, "link"),
+            PseudoClass::Visited => write!(f, "visited"),
+            PseudoClass::Hover => write!(f, "hover"),
+            PseudoClass::Active => write!(f, "active"),
+            PseudoClass::Focus => write!(f, "focus"),
+            PseudoClass::Not(ref selectors) => {
+                write!(f, "not(")?;
+                for (i, selector) in selectors.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", selector)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+impl fmt::Display for PseudoElement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PseudoElement::Before => write!(f, "before"),
+            PseudoElement::After => write!(f, "after"),
+            PseudoElement::Marker => write!(f, "marker"),
+        }
+    }
+}
+
+// A rule is written inside the `@layer` and `@media` blocks it came from, e.g.
+// `@layer base { @media print { p { color: #000000; } } }`.
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let preludes = match self.layer {
+            Some(ref layer) => layer_preludes(layer),
+            None => Vec::new(),
+        };
+        for prelude in &preludes {
+            write!(f, "@layer {}{{ ", prelude)?;
+        }
+        self.fmt_unlayered(f)?;
+        for _ in &preludes {
+            write!(f, " }}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Rule {
+    // The rule inside its `@media` blocks, but outside its layer.
+    fn fmt_unlayered(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for media in &self.media {
+            write!(f, "@media {} {{ ", media)?;
+        }
+        for (i, selector) in self.selector.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", selector)?;
+        }
+        write!(f, " {{ ")?;
+        for declaration in &self.declaration {
+            write!(f, "{}; ", declaration)?;
+        }
+        write!(f, "}}")?;
+        for _ in &self.media {
+            write!(f, " }}")?;
+        }
+        Ok(())
+    }
+}
+
+fn is_anonymous(layer: &str) -> bool {
+    layer.starts_with("<anonymous-")
+}
+
+// The name of a layer followed by a space, or nothing for anonymous layers.
+fn layer_prelude(layer: &str) -> String {
+    match is_anonymous(layer) {
+        true => String::new(),
+        false => format!("{} ", layer),
+    }
+}
+
+// The preludes of the nested `@layer` blocks of the layer named `layer`: `base.<anonymous-1>`
+// is written `@layer base { @layer { ... } }`, since anonymous layers cannot be named.
+fn layer_preludes(layer: &str) -> Vec<String> {
+    let mut preludes = Vec::new();
+    let mut named: Vec<&str> = Vec::new();
+    for part in layer.split('.') {
+        if is_anonymous(part) {
+            if !named.is_empty() {
+                preludes.push(format!("{} ", named.join(".")));
+                named.clear();
+            }
+            preludes.push(String::new());
+        } else {
+            named.push(part);
+        }
+    }
+    if !named.is_empty() {
+        preludes.push(format!("{} ", named.join(".")));
+    }
+    preludes
+}
+
+impl fmt::Display for Import {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "@import url({})", Value::Str(self.url.clone()))?;
+        if let Some(ref layer) = self.layer {
+            match &*layer_prelude(layer) {
+                "" => write!(f, " layer")?,
+                name => write!(f, " layer({})", name.trim_end())?,
+            }
+        }
+        if let Some(ref media) = self.media {
+            write!(f, " {}", media)?;
+        }
+        write!(f, ";")
+    }
+}
+
+impl fmt::Display for PropertyRegistration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "@property {} {{ syntax: {}; inherits: {}; ",
+            self.name,
+            Value::Str(self.syntax.clone()),
+            self.inherits
+        )?;
+        if let Some(ref initial) = self.initial {
+            write!(f, "initial-value: {}; ", initial)?;
+        }
+        write!(f, "}}")
+    }
+}
+
+impl fmt::Display for FontFace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sources: Vec<String> = self
+            .sources
+            .iter()
+            .map(|source| match source {
+                FontSource::Local(name) => format!("local({})", Value::Str(name.clone())),
+                FontSource::Url(url, formats) if formats.is_empty() => {
+                    format!("url({})", Value::Str(url.clone()))
+                }
+                FontSource::Url(url, formats) => {
+                    let formats: Vec<String> = formats
+                        .iter()
+                        .map(|format| Value::Str(format.clone()).to_string())
+                        .collect();
+                    let url = Value::Str(url.clone());
+                    format!("url({}) format({})", url, formats.join(", "))
+                }
+            })
+            .collect();
+        write!(
+            f,
+            "@font-face {{ font-family: {}; src: {}; font-weight: {} {}; font-style: {}; ",
+            Value::Str(self.family.clone()),
+            sources.join(", "),
+            self.weight.0,
+            self.weight.1,
+            match self.style {
+                FontStyle::Normal => "normal",
+                FontStyle::Italic => "italic",
+                FontStyle::Oblique => "oblique",
+            }
+        )?;
+        if !self.unicode_range.is_empty() {
+            let ranges: Vec<String> = self
+                .unicode_range
+                .iter()
+                .map(|range| format!("U+{:X}-{:X}", range.start(), range.end()))
+                .collect();
+            write!(f, "unicode-range: {}; ", ranges.join(", "))?;
+        }
+        write!(f, "}}")
+    }
+}
+
+/// Serializes the sheet as CSS text, one rule or top-level `@layer` block per line. The rules
+/// of each layer are written in one block, nested in the block of its parent layer, and the
+/// layers are declared in their original order.
+impl fmt::Display for Stylesheet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Imports may declare layers too, so the layers declared before them are declared up
+        // front, as far as they come before any anonymous layer.
+        let declared: Vec<&str> = self
+            .layers
+            .iter()
+            .filter(|layer| !layer.contains('.'))
+            .take_while(|layer| !is_anonymous(layer))
+            .map(|layer| &**layer)
+            .collect();
+        if !declared.is_empty() {
+            writeln!(f, "@layer {};", declared.join(", "))?;
+        }
+        for import in &self.imports {
+            writeln!(f, "{}", import)?;
+        }
+        for registration in &self.registrations {
+            writeln!(f, "{}", registration)?;
+        }
+        for font_face in &self.font_faces {
+            writeln!(f, "{}", font_face)?;
+        }
+        self.fmt_layer(f, None, &declared, "\n")?;
+        // Rules of layers the sheet does not declare.
+        for rule in &self.rules {
+            if let Some(ref layer) = rule.layer {
+                if !self.layers.contains(layer) {
+                    writeln!(f, "{}", rule)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Stylesheet {
+    // Write the rules directly in `layer`, or the unlayered rules, and the blocks of the
+    // layers nested in it, each followed by `separator`. A nested layer is written after the
+    // rules before its first rule, or declared in an `@layer` statement if it has no rules
+    // and no anonymous layers inside, unless it is one of `declared`.
+    fn fmt_layer(
+        &self,
+        f: &mut fmt::Formatter,
+        layer: Option<&str>,
+        declared: &[&str],
+        separator: &str,
+    ) -> fmt::Result {
+        let prefix = layer.map_or(String::new(), |layer| format!("{}.", layer));
+        let mut rules = self
+            .rules
+            .iter()
+            .enumerate()
+            .filter(|(_, rule)| rule.layer.as_deref() == layer)
+            .peekable();
+        let mut statement: Vec<&str> = Vec::new();
+        let sublayers = self
+            .layers
+            .iter()
+            .filter(|name| name.rsplit_once('.').map(|(parent, _)| parent) == layer);
+        for sublayer in sublayers {
+            let inside = |name: &str| {
+                name.strip_prefix(&**sublayer)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+            };
+            let first_rule = self
+                .rules
+                .iter()
+                .position(|rule| rule.layer.as_deref().is_some_and(inside));
+            let anonymous = self
+                .layers
+                .iter()
+                .any(|name| inside(name) && name[prefix.len()..].contains("<anonymous-"));
+            if first_rule.is_none() && !anonymous {
+                let names = self.layers.iter().filter(|name| inside(name));
+                statement.extend(
+                    names
+                        .filter(|name| !declared.contains(&&***name))
+                        .map(|name| &name[prefix.len()..]),
+                );
+                continue;
+            }
+
+            if !statement.is_empty() {
+                write!(f, "@layer {};{}", statement.join(", "), separator)?;
+                statement.clear();
+            }
+            let before = |&(i, _): &(usize, &Rule)| first_rule.is_some_and(|first| i < first);
+            while let Some((_, rule)) = rules.next_if(before) {
+                rule.fmt_unlayered(f)?;
+                write!(f, "{}", separator)?;
+            }
+            write!(f, "@layer {}{{ ", layer_prelude(&sublayer[prefix.len()..]))?;
+            self.fmt_layer(f, Some(sublayer), declared, " ")?;
+            write!(f, "}}{}", separator)?;
+        }
+        if !statement.is_empty() {
+            write!(f, "@layer {};{}", statement.join(", "), separator)?;
+        }
+        for (_, rule) in rules {
+            rule.fmt_unlayered(f)?;
+            write!(f, "{}", separator)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The sheet parsed from `css`, serialized, checking that it parses back into the same
+    // rules and layers.
+    fn round_trip(css: &str) -> String {
+        let stylesheet = parse_css(css.to_string());
+        let text = stylesheet.to_string();
+        let reparsed = parse_css(text.clone());
+        assert_eq!(reparsed.to_string(), text);
+        assert_eq!(reparsed.rules().len(), stylesheet.rules().len());
+        assert_eq!(reparsed.layers.len(), stylesheet.layers.len());
+        text
+    }
+
+    #[test]
+    fn rules_of_an_anonymous_layer_are_written_in_one_block() {
+        assert_eq!(
+            round_trip("@layer { #a { } p { } }"),
+            "@layer { #a { } p { } }\n"
+        );
+    }
+
+    #[test]
+    fn anonymous_sublayers_are_nested_in_their_parent() {
+        assert_eq!(
+            round_trip("@layer base { @layer { p { } } }"),
+            "@layer base;\n@layer base { @layer { p { } } }\n"
+        );
+        let stylesheet = parse_css("@layer base { @layer { p { } } }".to_string());
+        assert_eq!(
+            stylesheet.rules()[0].to_string(),
+            "@layer base { @layer { p { } } }"
+        );
+    }
+
+    #[test]
+    fn layers_keep_their_order_around_anonymous_layers() {
+        let text = round_trip("@layer a; @layer { p { } } @layer b; @layer c { q { } } r { }");
+        assert_eq!(
+            text,
+            "@layer a;\n@layer { p { } }\n@layer b;\n@layer c { q { } }\nr { }\n"
+        );
+        assert_eq!(
+            round_trip("@layer x { @layer { p { } } @layer y; q { } }"),
+            "@layer x;\n@layer x { @layer { p { } } @layer y; q { } }\n"
+        );
+    }
+
+    #[test]
+    fn media_blocks_are_written_inside_layers() {
+        assert_eq!(
+            round_trip("@media print { @layer { p { } } } @layer { @media screen { q { } } }"),
+            "@layer { @media print { p { } } }\n@layer { @media screen { q { } } }\n"
+        );
     }
 }
@@ -0,0 +1,163 @@
+use std::{
+    error::Error,
+    fmt, mem,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use crate::css::{parse_declaration_block, parse_stylesheet, Rule, Stylesheet, Value};
+
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// A stylesheet generation that no sheet has had before.
+pub fn next_generation() -> u64 {
+    GENERATION.fetch_add(1, Ordering::Relaxed) + 1
+}
+
+/// The generations of the stylesheets a style tree is computed from. Once a sheet is
+/// modified, added or removed the version differs, and the style tree is out of date and must
+/// be rebuilt with `style_tree`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StyleVersion(Vec<u64>);
+
+impl StyleVersion {
+    pub fn of(stylesheets: &[Stylesheet]) -> StyleVersion {
+        StyleVersion(stylesheets.iter().map(|sheet| sheet.generation).collect())
+    }
+}
+
+/// A rule index past the end of a stylesheet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexSizeError {
+    pub index: usize,
+    // The number of rules in the sheet.
+    pub len: usize,
+}
+
+impl fmt::Display for IndexSizeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Rule index {} out of range for {} rules",
+            self.index, self.len
+        )
+    }
+}
+
+impl Error for IndexSizeError {}
+
+impl Stylesheet {
+    /// Parse `text`, a rule or an `@media` or `@layer` block of rules, and insert its rules
+    /// before the rule at `index`. Returns the number of rules inserted.
+    pub fn insert_rule(&mut self, text: &str, index: usize) -> Result<usize, IndexSizeError> {
+        self.check_index(index, self.rules().len() + 1)?;
+        let mut parsed = parse_stylesheet(text.to_string(), self.origin);
+        let rules = mem::take(parsed.rules_mut());
+        for layer in parsed.layers {
+            if !self.layers.contains(&layer) {
+                self.layers.push(layer);
+            }
+        }
+        self.registrations.extend(parsed.registrations);
+        self.font_faces.extend(parsed.font_faces);
+
+        let inserted = rules.len();
+        self.rules_mut().splice(index..index, rules);
+        Ok(inserted)
+    }
+
+    pub fn delete_rule(&mut self, index: usize) -> Result<Rule, IndexSizeError> {
+        self.check_index(index, self.rules().len())?;
+        Ok(self.rules_mut().remove(index))
+    }
+
+    /// The rule at `index`, to modify its declarations.
+    pub fn rule_mut(&mut self, index: usize) -> Result<&mut Rule, IndexSizeError> {
+        self.check_index(index, self.rules().len())?;
+        Ok(&mut self.rules_mut()[index])
+    }
+
+    // Whether `index` is below `end`.
+    fn check_index(&self, index: usize, end: usize) -> Result<(), IndexSizeError> {
+        match index < end {
+            true => Ok(()),
+            false => Err(IndexSizeError {
+                index,
+                len: self.rules().len(),
+            }),
+        }
+    }
+}
+
+impl Rule {
+    /// The value the rule gives to `name`, if any.
+    pub fn property(&self, name: &str) -> Option<&Value> {
+        self.declaration
+            .iter()
+            .rev()
+            .find(|declaration| declaration.name == name)
+            .map(|declaration| &declaration.value)
+    }
+
+    /// Set a property of the rule, replacing any previous value. Invalid values, including
+    /// ones that would add other declarations, are ignored and leave the rule as it was.
+    pub fn set_property(&mut self, name: &str, value: &str, important: bool) {
+        let priority = if important { " !important" } else { "" };
+        let mut declarations = parse_declaration_block(&format!("{}: {}{}", name, value, priority));
+        match declarations.pop() {
+            Some(declaration) if declarations.is_empty() && declaration.name == name => {
+                self.remove_property(name);
+                self.declaration.push(declaration);
+            }
+            _ => {}
+        }
+    }
+
+    /// Remove a property from the rule. Returns whether the rule had it.
+    pub fn remove_property(&mut self, name: &str) -> bool {
+        let count = self.declaration.len();
+        self.declaration
+            .retain(|declaration| declaration.name != name);
+        self.declaration.len() != count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::css::{parse_css, parse_value};
+
+    fn rule(css: &str) -> Rule {
+        let mut stylesheet = parse_css(css.to_string());
+        stylesheet.delete_rule(0).unwrap()
+    }
+
+    #[test]
+    fn set_property_replaces_the_value() {
+        let mut rule = rule("p { color: #ff0000; margin: 0px; }");
+        rule.set_property("color", "#0000ff", false);
+        assert_eq!(rule.property("color"), parse_value("#0000ff").as_ref());
+        assert_eq!(rule.declaration.len(), 2);
+    }
+
+    #[test]
+    fn set_property_ignores_invalid_values() {
+        let mut rule = rule("p { color: #ff0000; }");
+        rule.set_property("color", "notacolor((", false);
+        assert_eq!(rule.property("color"), parse_value("#ff0000").as_ref());
+        rule.set_property("color", "#0000ff; display: none", false);
+        assert_eq!(rule.property("color"), parse_value("#ff0000").as_ref());
+        assert_eq!(rule.property("display"), None);
+    }
+
+    #[test]
+    fn rule_indices_past_the_end_are_errors() {
+        let mut stylesheet = parse_css("p { }".to_string());
+        assert_eq!(
+            stylesheet.insert_rule("q { }", 2),
+            Err(IndexSizeError { index: 2, len: 1 })
+        );
+        assert!(stylesheet.delete_rule(1).is_err());
+        assert_eq!(stylesheet.insert_rule("q { }", 1), Ok(1));
+        assert_eq!(stylesheet.rules().len(), 2);
+    }
+}
@@ -1,5 +1,10 @@
 use std::collections::{HashMap, HashSet};
 
+use crate::{
+    css::{parse_declaration_block, parse_value, Declaration, Value},
+    properties::accepts,
+};
+
 #[derive(Debug, Clone)]
 pub enum Token {
     OpeningTag(String),
@@ -22,7 +27,7 @@ pub enum BodyTag {
     P,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Node {
     Element(Element),
     Text(String),
@@ -30,11 +35,36 @@ pub enum Node {
 
 pub type AttrMap = HashMap<String, String>;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Element {
     pub name: String,
     pub attributes: AttrMap,
     pub children: Vec<Node>,
+    // Changed since the element was last styled. Set by the methods that modify elements;
+    // changing the fields directly goes unnoticed by `Styler::restyle`.
+    pub dirty: Dirty,
+}
+
+/// What changed about an element since `Styler::restyle` last styled it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Dirty {
+    // An attribute changed, which may change which rules match the element and its
+    // descendants.
+    pub attributes: bool,
+    // Children were inserted or removed, or a text child became empty or not, which may change
+    // which rules with sibling combinators or pseudo-classes such as `:first-child` and
+    // `:empty` match them and the element.
+    pub children: bool,
+    // The insertions and removals in the order they were made, which tell the children that
+    // were there before apart from the new ones.
+    pub child_changes: Vec<ChildChange>,
+}
+
+/// A child inserted at, or removed from, an index among the children of an element.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChildChange {
+    Inserted(usize),
+    Removed(usize),
 }
 
 impl Element {
@@ -43,10 +73,47 @@ impl Element {
             name: name,
             children: Vec::new(),
             attributes: HashMap::new(),
+            dirty: Dirty::default(),
         }
     }
     pub fn add_child(&mut self, child: Node) {
-        self.children.push(child);
+        self.insert_child(self.children.len(), child);
+    }
+
+    pub fn insert_child(&mut self, index: usize, child: Node) {
+        self.children.insert(index, child);
+        self.dirty.children = true;
+        self.dirty.child_changes.push(ChildChange::Inserted(index));
+    }
+
+    pub fn remove_child(&mut self, index: usize) -> Node {
+        self.dirty.children = true;
+        self.dirty.child_changes.push(ChildChange::Removed(index));
+        self.children.remove(index)
+    }
+
+    /// Replace the text of the child at `index`. Selectors only depend on whether there is
+    /// any text, through `:empty`, so this rarely requires a restyle.
+    pub fn set_text(&mut self, index: usize, text: &str) {
+        match self.children[index] {
+            Node::Text(ref mut old) => {
+                if old.is_empty() != text.is_empty() {
+                    self.dirty.children = true;
+                }
+                *old = text.to_string();
+            }
+            Node::Element(_) => panic!("Child {} is not a text node", index),
+        }
+    }
+
+    pub fn set_attribute(&mut self, name: &str, value: &str) {
+        self.attributes.insert(name.to_string(), value.to_string());
+        self.dirty.attributes = true;
+    }
+
+    pub fn remove_attribute(&mut self, name: &str) -> Option<String> {
+        self.dirty.attributes = true;
+        self.attributes.remove(name)
     }
 
     pub fn print(&self, indent: usize) {
@@ -69,4 +136,125 @@ impl Element {
             None => HashSet::new(),
         }
     }
+
+    /// The declarations of the inline `style` attribute.
+    pub fn style(&self) -> Vec<Declaration> {
+        match self.attributes.get("style") {
+            Some(style) => parse_declaration_block(style),
+            None => Vec::new(),
+        }
+    }
+
+    /// Declarations that HTML attributes other than `style` stand for, which apply at the
+    /// user-agent origin like the rules of the user-agent stylesheet.
+    pub fn presentational_hints(&self) -> Vec<Declaration> {
+        let hint = |name: &str, value: &str| Declaration {
+            name: name.to_string(),
+            value: Value::Keyword(value.to_string()),
+            important: false,
+        };
+        // `<bdo>` overrides the direction of its text; with `dir` it is also isolated.
+        let isolate = match &*self.name {
+            "bdo" => "isolate-override",
+            _ => "isolate",
+        };
+        match self
+            .attributes
+            .get("dir")
+            .map(|dir| dir.to_ascii_lowercase())
+        {
+            Some(dir) if dir == "ltr" || dir == "rtl" => {
+                vec![hint("direction", &dir), hint("unicode-bidi", isolate)]
+            }
+            // The direction of the element's text is found from its content.
+            Some(dir) if dir == "auto" => vec![hint("unicode-bidi", "plaintext")],
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn style_property(&self, name: &str) -> Option<Value> {
+        self.style()
+            .into_iter()
+            .rev()
+            .find(|declaration| declaration.name == name)
+            .map(|declaration| declaration.value)
+    }
+
+    /// Set a property in the inline `style` attribute, replacing any previous value. Invalid
+    /// values are ignored and leave the attribute as it was.
+    pub fn set_style_property(&mut self, name: &str, value: &str, important: bool) {
+        let Some(value) = parse_value(value) else {
+            return;
+        };
+        let declaration = Declaration {
+            name: name.to_string(),
+            value,
+            important,
+        };
+        if !accepts(&declaration) {
+            return;
+        }
+        let mut declarations = self.style();
+        declarations.retain(|declaration| declaration.name != name);
+        declarations.push(declaration);
+        self.set_style(&declarations);
+    }
+
+    pub fn remove_style_property(&mut self, name: &str) {
+        let mut declarations = self.style();
+        declarations.retain(|declaration| declaration.name != name);
+        self.set_style(&declarations);
+    }
+
+    fn set_style(&mut self, declarations: &[Declaration]) {
+        let style = declarations
+            .iter()
+            .map(|declaration| format!("{};", declaration))
+            .collect::<Vec<_>>()
+            .join(" ");
+        self.set_attribute("style", &style);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn styled(style: &str) -> Element {
+        let mut element = Element::new("p".to_string());
+        element.set_attribute("style", style);
+        element
+    }
+
+    #[test]
+    fn style_properties_are_read_from_the_style_attribute() {
+        let element = styled("color: #ff0000; margin-left: 4px; color: #00ff00");
+        assert_eq!(element.style().len(), 3);
+        assert_eq!(element.style_property("color"), parse_value("#00ff00"));
+        assert_eq!(element.style_property("margin-left"), parse_value("4px"));
+        assert_eq!(element.style_property("margin-right"), None);
+        assert!(Element::new("p".to_string()).style().is_empty());
+    }
+
+    #[test]
+    fn set_style_property_replaces_the_value() {
+        let mut element = styled("color: #ff0000; margin-left: 4px");
+        element.set_style_property("color", "#00ff00", true);
+        assert_eq!(
+            element.attributes["style"],
+            "margin-left: 4px; color: #00ff00 !important;"
+        );
+        element.remove_style_property("margin-left");
+        assert_eq!(element.attributes["style"], "color: #00ff00 !important;");
+        assert!(element.dirty.attributes);
+    }
+
+    #[test]
+    fn set_style_property_ignores_invalid_values() {
+        let mut element = styled("color: #ff0000");
+        element.set_style_property("color", "", false);
+        element.set_style_property("color", "#00ff00; margin-left: 4px", false);
+        element.set_style_property("display", "garbage", false);
+        assert_eq!(element.attributes["style"], "color: #ff0000");
+    }
 }
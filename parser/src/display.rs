@@ -1,7 +1,10 @@
 use crate::{
-    boxmodel::{BoxType, LayoutBox, Rect},
-    css::{Color, Value},
-    datatypes::Node,
+    boxmodel::{BoxType, Dimension, Fragment, LayoutBox, Rect},
+    computed::{LengthPercentageOrAuto, TextDecorationStyle, TextDecorationThickness},
+    css::Color,
+    font::MAX_BLUR_RADIUS,
+    properties::ComputedStyle,
+    shaping::GlyphRun,
 };
 
 type DisplayList = Vec<DisplayCommand>;
@@ -9,135 +12,424 @@ type DisplayList = Vec<DisplayCommand>;
 #[derive(Debug)]
 pub enum DisplayCommand {
     SolidColor(Color, Rect),
-    Text(Color, Rect, String),
+    // Shaped text, with its glyphs placed from the top left of the rect.
+    Text(Color, Rect, GlyphRun),
+    // The shadow of shaped text, blurred by a radius of the given pixels, which is at most
+    // `MAX_BLUR_RADIUS`.
+    TextShadow(Color, Rect, GlyphRun, f32),
+    // A decoration line along the rect, whose height is the thickness of the line. Double
+    // lines have their second line below the rect, and wavy lines wave up and down across it.
+    Line(Color, Rect, TextDecorationStyle),
+}
+
+// A line an element draws under, over or through the text in it and its descendants.
+#[derive(Clone)]
+struct Decoration {
+    line: DecorationLine,
+    color: Color,
+    style: TextDecorationStyle,
+    thickness: TextDecorationThickness,
+    underline_offset: LengthPercentageOrAuto,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum DecorationLine {
+    Underline,
+    Overline,
+    LineThrough,
 }
 
 pub fn build_display_list(layout_root: &LayoutBox) -> DisplayList {
     let mut list = Vec::new();
-    render_layout_box(&mut list, layout_root);
+    render_layout_box(&mut list, layout_root, &[]);
     return list;
 }
 
-fn render_layout_box(list: &mut DisplayList, layout_box: &LayoutBox) {
-    render_background(list, layout_box);
-    render_borders(list, layout_box);
-    render_text(list, layout_box);
+// Render a box and its descendants. `decorations` are the lines that the elements the box is
+// in draw across its text.
+fn render_layout_box(list: &mut DisplayList, layout_box: &LayoutBox, decorations: &[Decoration]) {
+    // Text has no background or borders of its own.
+    if !layout_box.is_text() {
+        render_background(list, layout_box);
+        render_borders(list, layout_box);
+    }
+    let mut decorations = decorations.to_vec();
+    decorations.extend(own_decorations(layout_box));
+    render_text(list, layout_box, &decorations);
 
     for child in &layout_box.children {
-        render_layout_box(list, child);
+        render_layout_box(list, child, &decorations);
     }
 }
 
-// Return the specified color for CSS property `name`, or None if no color was specified.
-fn get_color(layout_box: &LayoutBox, name: &str) -> Option<Color> {
+// The lines the box's `text-decoration` asks for.
+fn own_decorations(layout_box: &LayoutBox) -> Vec<Decoration> {
+    let style = match layout_box.box_type {
+        BoxType::BlockNode(style) | BoxType::InlineNode(style) => &style.style,
+        BoxType::AnonymousBlock => return Vec::new(),
+    };
+    let line = style.decoration.text_decoration_line;
+    let lines = [
+        (line.underline, DecorationLine::Underline),
+        (line.overline, DecorationLine::Overline),
+        (line.line_through, DecorationLine::LineThrough),
+    ];
+    lines
+        .into_iter()
+        .filter(|&(set, _)| set && style.decoration.text_decoration_color.a != 0)
+        .map(|(_, line)| Decoration {
+            line,
+            color: style.decoration.text_decoration_color.clone(),
+            style: style.decoration.text_decoration_style,
+            thickness: style.decoration.text_decoration_thickness,
+            underline_offset: style.text.text_underline_offset.clone(),
+        })
+        .collect()
+}
+
+// Return the computed color `color` picks from the box's style, unless it is transparent.
+fn get_color(layout_box: &LayoutBox, color: impl Fn(&ComputedStyle) -> &Color) -> Option<Color> {
     match layout_box.box_type {
-        BoxType::BlockNode(style) | BoxType::InlineNode(style) => match style.value(name) {
-            Some(Value::Color(color)) => Some(color),
-            _ => None,
-        },
+        BoxType::BlockNode(style) | BoxType::InlineNode(style) => {
+            Some(color(&style.style).clone()).filter(|color| color.a != 0)
+        }
         BoxType::AnonymousBlock => None,
     }
 }
 
 fn render_background(list: &mut DisplayList, layout_box: &LayoutBox) {
-    get_color(layout_box, "background").map(|color| {
-        list.push(DisplayCommand::SolidColor(
-            color,
-            layout_box.dimensions.border_box(),
-        ))
+    get_color(layout_box, |s| &s.background.background_color).map(|color| {
+        for d in layout_box.fragment_dimensions() {
+            list.push(DisplayCommand::SolidColor(color.clone(), d.border_box()))
+        }
     });
 }
 
 fn render_borders(list: &mut DisplayList, layout_box: &LayoutBox) {
-    let color = match get_color(layout_box, "border-color") {
-        Some(color) => color,
-        _ => return, // bail out if no border-color is specified
-    };
+    for d in layout_box.fragment_dimensions() {
+        render_fragment_borders(list, layout_box, &d);
+    }
+}
 
-    let d = &layout_box.dimensions;
+fn render_fragment_borders(list: &mut DisplayList, layout_box: &LayoutBox, d: &Dimension) {
     let border_box = d.border_box();
 
     // Left border
-    list.push(DisplayCommand::SolidColor(
-        color.clone(),
-        Rect {
-            x: border_box.x,
-            y: border_box.y,
-            width: d.border.left,
-            height: border_box.height,
-        },
-    ));
+    if let Some(color) = get_color(layout_box, |s| &s.border.border_left_color) {
+        list.push(DisplayCommand::SolidColor(
+            color,
+            Rect {
+                x: border_box.x,
+                y: border_box.y,
+                width: d.border.left,
+                height: border_box.height,
+            },
+        ));
+    }
 
     // Right border
-    list.push(DisplayCommand::SolidColor(
-        color.clone(),
-        Rect {
-            x: border_box.x + border_box.width - d.border.right,
-            y: border_box.y,
-            width: d.border.right,
-            height: border_box.height,
-        },
-    ));
+    if let Some(color) = get_color(layout_box, |s| &s.border.border_right_color) {
+        list.push(DisplayCommand::SolidColor(
+            color,
+            Rect {
+                x: border_box.x + border_box.width - d.border.right,
+                y: border_box.y,
+                width: d.border.right,
+                height: border_box.height,
+            },
+        ));
+    }
 
     // Top border
-    list.push(DisplayCommand::SolidColor(
-        color.clone(),
-        Rect {
-            x: border_box.x,
-            y: border_box.y,
-            width: border_box.width,
-            height: d.border.top,
-        },
-    ));
+    if let Some(color) = get_color(layout_box, |s| &s.border.border_top_color) {
+        list.push(DisplayCommand::SolidColor(
+            color,
+            Rect {
+                x: border_box.x,
+                y: border_box.y,
+                width: border_box.width,
+                height: d.border.top,
+            },
+        ));
+    }
 
     // Bottom border
-    list.push(DisplayCommand::SolidColor(
-        color.clone(),
-        Rect {
-            x: border_box.x,
-            y: border_box.y + border_box.height - d.border.bottom,
-            width: border_box.width,
-            height: d.border.bottom,
-        },
-    ));
+    if let Some(color) = get_color(layout_box, |s| &s.border.border_bottom_color) {
+        list.push(DisplayCommand::SolidColor(
+            color,
+            Rect {
+                x: border_box.x,
+                y: border_box.y + border_box.height - d.border.bottom,
+                width: border_box.width,
+                height: d.border.bottom,
+            },
+        ));
+    }
 }
 
-fn render_text(list: &mut DisplayList, layout_box: &LayoutBox) {
-    let color = match get_color(layout_box, "color") {
-        Some(color) => color,
-        _ => return, // bail out if no color is specified
+// Render the text of the box with its shadows and `decorations`. Shadows go below the text,
+// the first one on top, then underlines and overlines, the text and lines through it.
+fn render_text(list: &mut DisplayList, layout_box: &LayoutBox, decorations: &[Decoration]) {
+    let style = match layout_box.box_type {
+        BoxType::BlockNode(style) | BoxType::InlineNode(style) => &style.style,
+        BoxType::AnonymousBlock => return,
     };
+    let color = &style.text.color;
 
-    let text = match layout_box.box_type {
-        BoxType::BlockNode(style) | BoxType::InlineNode(style) => match style.children.first() {
-            s => match s {
-                Some(st) => match st.node {
-                    Node::Text(t) => Some(t),
-                    _ => None,
-                },
-                _ => None,
-            },
-            _ => None,
+    for fragment in &layout_box.fragments {
+        let run = match fragment.text {
+            Some(ref run) => run,
+            None => continue,
+        };
+        for shadow in style.text.text_shadow.0.iter().rev() {
+            let color = shadow.color.as_ref().unwrap_or(color);
+            if color.a == 0 {
+                continue;
+            }
+            let rect = Rect {
+                x: fragment.rect.x + shadow.x,
+                y: fragment.rect.y + shadow.y,
+                ..fragment.rect
+            };
+            for decoration in decorations {
+                let line = decoration_rect(decoration, fragment, rect, run.font_size);
+                list.push(DisplayCommand::Line(color.clone(), line, decoration.style));
+            }
+            list.push(DisplayCommand::TextShadow(
+                color.clone(),
+                rect,
+                run.clone(),
+                shadow.blur.min(MAX_BLUR_RADIUS),
+            ));
+        }
+
+        let lines = |through: bool| {
+            decorations
+                .iter()
+                .filter(move |decoration| {
+                    (decoration.line == DecorationLine::LineThrough) == through
+                })
+                .map(|decoration| {
+                    let line = decoration_rect(decoration, fragment, fragment.rect, run.font_size);
+                    DisplayCommand::Line(decoration.color.clone(), line, decoration.style)
+                })
+        };
+        list.extend(lines(false));
+        if color.a != 0 {
+            list.push(DisplayCommand::Text(
+                color.clone(),
+                fragment.rect,
+                run.clone(),
+            ));
+        }
+        list.extend(lines(true));
+    }
+}
+
+// Where the line of `decoration` goes across the text of `fragment`, drawn at `rect` in a font
+// of `font_size`. Lines are at least a pixel thick.
+fn decoration_rect(
+    decoration: &Decoration,
+    fragment: &Fragment,
+    rect: Rect,
+    font_size: f32,
+) -> Rect {
+    let metrics = match decoration.line {
+        DecorationLine::LineThrough => fragment.strikeout,
+        DecorationLine::Underline | DecorationLine::Overline => fragment.underline,
+    };
+    let thickness = match decoration.thickness {
+        TextDecorationThickness::Auto | TextDecorationThickness::FromFont => metrics.thickness,
+        TextDecorationThickness::Length(n) => n,
+    };
+    let baseline = rect.y + fragment.baseline;
+    let y = match decoration.line {
+        // An underline offset is from the baseline.
+        DecorationLine::Underline => match decoration.underline_offset.resolve(font_size) {
+            Some(offset) => baseline + offset,
+            None => baseline + metrics.offset,
         },
-        BoxType::AnonymousBlock => None,
+        DecorationLine::Overline => rect.y,
+        DecorationLine::LineThrough => baseline + metrics.offset,
     };
+    Rect {
+        y,
+        height: thickness.max(1.0),
+        ..rect
+    }
+}
 
-    let d = &layout_box.dimensions;
-    let border_box = d.border_box();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        boxmodel::layout_tree, computed::FontStyle, css::parse_css, datatypes::Node,
+        dom::parse_element, font::FixedMetrics, fontdb::FontDatabase, media::Viewport,
+        styles::style_tree, tokenizer::Tokenizer,
+    };
 
-    match text {
-        Some(t) => {
-            list.push(DisplayCommand::Text(
-                color,
-                Rect {
-                    x: border_box.x + d.border.left + d.padding.left,
-                    y: border_box.y + d.border.top + d.padding.top,
-                    height: d.content.height,
-                    width: d.content.width,
-                },
-                t.to_string(),
-            ));
+    const BLACK: Color = Color {
+        r: 0,
+        g: 0,
+        b: 0,
+        a: 255,
+    };
+    const RED: Color = Color {
+        r: 255,
+        g: 0,
+        b: 0,
+        a: 255,
+    };
+    const BLUE: Color = Color {
+        r: 0,
+        g: 0,
+        b: 255,
+        a: 255,
+    };
+
+    // The display list of `body` styled with `css`. Text is 16 pixels high with its baseline
+    // 12.8 pixels down, and every character is 8 pixels wide.
+    fn display_list(body: &str, css: &str) -> DisplayList {
+        let html = format!("<html><body>{}</body></html>", body);
+        let mut tokenizer = Tokenizer::new(&html);
+        let mut tokens = Vec::new();
+        while let Some(token) = tokenizer.next_token() {
+            tokens.push(token);
         }
-        None => (),
+        let root = Node::Element(parse_element(&tokens).0);
+        let stylesheets = [parse_css(format!("body, p {{ margin: 0px }} {}", css))];
+        let style = style_tree(&root, &stylesheets, &Viewport::new(800.0, 600.0));
+        let mut fonts = FontDatabase::new();
+        let metrics = Box::new(FixedMetrics::default());
+        fonts.add_face("Fixed", 400, FontStyle::Normal, metrics);
+        build_display_list(&layout_tree(800.0, 600.0, &style, &fonts))
+    }
+
+    // What is drawn for the text of `body`, without backgrounds and borders: the kind of each
+    // command, its color and the top, height and left of its rect.
+    fn draw(body: &str, css: &str) -> Vec<(&'static str, Color, f32, f32, f32)> {
+        let round = |n: f32| (n * 10.0).round() / 10.0;
+        display_list(body, css)
+            .into_iter()
+            .filter_map(|command| {
+                let (kind, color, rect) = match command {
+                    DisplayCommand::SolidColor(..) => return None,
+                    DisplayCommand::Text(color, rect, _) => ("text", color, rect),
+                    DisplayCommand::TextShadow(color, rect, _, _) => ("shadow", color, rect),
+                    DisplayCommand::Line(color, rect, _) => ("line", color, rect),
+                };
+                Some((
+                    kind,
+                    color,
+                    round(rect.y),
+                    round(rect.height),
+                    round(rect.x),
+                ))
+            })
+            .collect()
+    }
+
+    // The blur radius of each text shadow drawn for `body`.
+    fn blurs(body: &str, css: &str) -> Vec<f32> {
+        display_list(body, css)
+            .into_iter()
+            .filter_map(|command| match command {
+                DisplayCommand::TextShadow(_, _, _, blur) => Some(blur),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn shadows_are_drawn_below_the_text_with_the_first_on_top() {
+        let css = "p { text-shadow: 1px 2px #ff0000, 3px 4px #0000ff, 5px 6px transparent }";
+        assert_eq!(
+            draw("<p>ab</p>", css),
+            [
+                ("shadow", BLUE, 4.0, 16.0, 3.0),
+                ("shadow", RED, 2.0, 16.0, 1.0),
+                ("text", BLACK, 0.0, 16.0, 0.0),
+            ]
+        );
+        // Shadows without a color have the color of the text.
+        assert_eq!(
+            draw("<p>ab</p>", "p { color: #0000ff; text-shadow: 1px 1px }"),
+            [
+                ("shadow", BLUE, 1.0, 16.0, 1.0),
+                ("text", BLUE, 0.0, 16.0, 0.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn shadows_are_blurred_by_at_most_the_largest_radius() {
+        assert_eq!(blurs("<p>ab</p>", "p { text-shadow: 0 0 4px }"), [4.0]);
+        assert_eq!(
+            blurs("<p>ab</p>", "p { text-shadow: 0 0 10000px }"),
+            [MAX_BLUR_RADIUS]
+        );
+    }
+
+    #[test]
+    fn lines_through_text_go_over_it_and_other_lines_below_it() {
+        let css = "p { text-decoration: underline overline line-through #ff0000 }";
+        assert_eq!(
+            draw("<p>ab</p>", css),
+            [
+                ("line", RED, 14.4, 1.0, 0.0),
+                ("line", RED, 0.0, 1.0, 0.0),
+                ("text", BLACK, 0.0, 16.0, 0.0),
+                ("line", RED, 8.0, 1.0, 0.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn underlines_follow_their_offset_and_thickness() {
+        let css = "p { text-decoration: underline; text-underline-offset: 4px; \
+                   text-decoration-thickness: 3px }";
+        assert_eq!(draw("<p>ab</p>", css)[0], ("line", BLACK, 16.8, 3.0, 0.0));
+        // Lines are at least a pixel thick.
+        let css = "p { text-decoration: underline; text-decoration-thickness: 0.2px }";
+        assert_eq!(draw("<p>ab</p>", css)[0], ("line", BLACK, 14.4, 1.0, 0.0));
+    }
+
+    #[test]
+    fn shadows_have_the_lines_of_the_text() {
+        let css = "p { text-decoration: underline; text-shadow: 2px 2px #ff0000 }";
+        assert_eq!(
+            draw("<p>ab</p>", css),
+            [
+                ("line", RED, 16.4, 1.0, 2.0),
+                ("shadow", RED, 2.0, 16.0, 2.0),
+                ("line", BLACK, 14.4, 1.0, 0.0),
+                ("text", BLACK, 0.0, 16.0, 0.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn decorations_are_drawn_across_descendants_in_the_color_of_their_element() {
+        let css = "p { text-decoration: underline #ff0000 } em { color: #0000ff }";
+        assert_eq!(
+            draw("<p>ab <em>cd</em></p>", css),
+            [
+                ("line", RED, 14.4, 1.0, 0.0),
+                ("text", BLACK, 0.0, 16.0, 0.0),
+                ("line", RED, 14.4, 1.0, 24.0),
+                ("text", BLUE, 0.0, 16.0, 24.0),
+            ]
+        );
+        // Descendants add their own lines, but cannot take away those of their ancestors.
+        let css =
+            "p { text-decoration: underline #ff0000 } em { text-decoration: overline #0000ff }";
+        assert_eq!(
+            draw("<p>ab <em>cd</em></p>", css)[2..],
+            [
+                ("line", RED, 14.4, 1.0, 24.0),
+                ("line", BLUE, 0.0, 1.0, 24.0),
+                ("text", BLACK, 0.0, 16.0, 24.0),
+            ]
+        );
     }
 }
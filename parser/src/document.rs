@@ -0,0 +1,224 @@
+use crate::{
+    css::{parse_css, Stylesheet},
+    cssom::next_generation,
+    datatypes::{Element, Node},
+    import::resolve_imports,
+    loader::{resolve_url, ResourceLoader},
+    media::parse_media_query_list,
+};
+
+/// Collect the stylesheets of a document from its `<style>` and `<link rel=stylesheet>`
+/// elements, in document order, with their `@import` rules resolved. Linked and imported
+/// stylesheets are fetched through `loader`; those that cannot be loaded are ignored, like a
+/// browser ignores a failed request.
+///
+/// The preferred style set is enabled, i.e. persistent sheets plus the sheets titled like the
+/// first titled, non-alternate sheet. Use `select_style_set` to switch to an alternate set.
+pub fn document_stylesheets(root: &Node, loader: &dyn ResourceLoader) -> Vec<Stylesheet> {
+    let mut stylesheets = Vec::new();
+    collect_stylesheets(root, loader, &mut stylesheets);
+
+    let preferred = stylesheets
+        .iter()
+        .find(|sheet| sheet.title.is_some() && !sheet.alternate)
+        .and_then(|sheet| sheet.title.clone());
+    select_style_set(&mut stylesheets, preferred.as_deref());
+
+    stylesheets
+}
+
+/// Enable the sheets of the style set `name` and disable those of every other set. Sheets
+/// without a title are persistent and stay enabled.
+pub fn select_style_set(stylesheets: &mut [Stylesheet], name: Option<&str>) {
+    for sheet in stylesheets {
+        let disabled = match sheet.title {
+            Some(ref title) => Some(&**title) != name,
+            // An alternate sheet must have a title to be selectable.
+            None => sheet.alternate,
+        };
+        if disabled != sheet.disabled {
+            sheet.disabled = disabled;
+            sheet.generation = next_generation();
+        }
+    }
+}
+
+fn collect_stylesheets(
+    node: &Node,
+    loader: &dyn ResourceLoader,
+    stylesheets: &mut Vec<Stylesheet>,
+) {
+    let elem = match node {
+        Node::Element(elem) => elem,
+        Node::Text(_) => return,
+    };
+
+    match &*elem.name {
+        "style" => {
+            let text: String = elem
+                .children
+                .iter()
+                .filter_map(|child| match child {
+                    Node::Text(text) => Some(&**text),
+                    Node::Element(_) => None,
+                })
+                .collect();
+            let mut sheet = parse_css(text);
+            resolve_imports(&mut sheet, "", loader);
+            stylesheets.push(with_attributes(sheet, elem, false));
+        }
+        "link" => {
+            let rel = elem.attributes.get("rel").map(|r| r.to_ascii_lowercase());
+            let rel: Vec<&str> = rel.iter().flat_map(|r| r.split_whitespace()).collect();
+            if !rel.contains(&"stylesheet") {
+                return;
+            }
+            let href = match elem.attributes.get("href") {
+                Some(href) => href,
+                None => return,
+            };
+            let url = resolve_url("", href);
+            if let Some(text) = loader.load_text(&url) {
+                let alternate = rel.contains(&"alternate");
+                let mut sheet = parse_css(text);
+                resolve_imports(&mut sheet, &url, loader);
+                stylesheets.push(with_attributes(sheet, elem, alternate));
+            }
+        }
+        _ => {
+            for child in &elem.children {
+                collect_stylesheets(child, loader, stylesheets);
+            }
+        }
+    }
+}
+
+fn with_attributes(mut sheet: Stylesheet, elem: &Element, alternate: bool) -> Stylesheet {
+    sheet.media = elem
+        .attributes
+        .get("media")
+        .map(|media| parse_media_query_list(media));
+    sheet.title = elem
+        .attributes
+        .get("title")
+        .cloned()
+        .filter(|t| !t.is_empty());
+    sheet.alternate = alternate;
+    sheet
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        css::Color, dom::parse_element, loader::MemoryLoader, media::Viewport, styles::style_tree,
+        tokenizer::Tokenizer,
+    };
+
+    fn parse_page(html: &str) -> Node {
+        let mut tokenizer = Tokenizer::new(html);
+        let mut tokens = Vec::new();
+        while let Some(token) = tokenizer.next_token() {
+            tokens.push(token);
+        }
+        Node::Element(parse_element(&tokens).0)
+    }
+
+    // The first selector of each sheet, its title and whether it is disabled.
+    fn summary(stylesheets: &[Stylesheet]) -> Vec<(String, Option<&str>, bool)> {
+        stylesheets
+            .iter()
+            .map(|sheet| {
+                let selector = sheet.rules()[0].selector[0].to_string();
+                (selector, sheet.title.as_deref(), sheet.disabled)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn style_and_link_elements_are_collected_in_document_order() {
+        let mut loader = MemoryLoader::new();
+        loader.insert("css/a.css", "@import 'b.css'; a { }");
+        loader.insert("css/b.css", "b { }");
+        let root = parse_page(
+            "<html><head><style>c { }</style><link rel=\"stylesheet\" href=\"css/a.css\">\
+             <link rel=\"icon\" href=\"css/b.css\"><link rel=\"stylesheet\" href=\"missing.css\">\
+             </head><body><style>d { }</style></body></html>",
+        );
+        let stylesheets = document_stylesheets(&root, &loader);
+        assert_eq!(
+            summary(&stylesheets),
+            [
+                ("c".to_string(), None, false),
+                ("b".to_string(), None, false),
+                ("d".to_string(), None, false),
+            ]
+        );
+        assert_eq!(stylesheets[1].rules()[1].selector[0].to_string(), "a");
+    }
+
+    #[test]
+    fn media_attributes_limit_where_sheets_apply() {
+        let root = parse_page(
+            "<html><head><style media=\"print\">p { color: #ff0000 }</style>\
+             <style media=\"(min-width: 600px)\">p { margin-left: 10px }</style></head>\
+             <body><p>a</p></body></html>",
+        );
+        let stylesheets = document_stylesheets(&root, &MemoryLoader::new());
+        assert!(stylesheets.iter().all(|sheet| sheet.media.is_some()));
+        let tree = style_tree(&root, &stylesheets, &Viewport::new(800.0, 600.0));
+        let style = &tree.children[1].children[0].style;
+        assert_eq!(
+            style.text.color,
+            Color {
+                r: 0,
+                g: 0,
+                b: 0,
+                a: 255
+            }
+        );
+        assert_eq!(style.box_model.margin_left.resolve(0.0), Some(10.0));
+    }
+
+    #[test]
+    fn the_preferred_style_set_is_enabled() {
+        let root = parse_page(
+            "<html><head><style>persistent { }</style>\
+             <style title=\"Dark\">dark { }</style>\
+             <style title=\"Light\">light { }</style>\
+             <style title=\"Dark\">dark2 { }</style>\
+             <link rel=\"alternate stylesheet\" title=\"Contrast\" href=\"contrast.css\">\
+             <link rel=\"alternate stylesheet\" href=\"untitled.css\">\
+             </head></html>",
+        );
+        let mut loader = MemoryLoader::new();
+        loader.insert("contrast.css", "contrast { }");
+        loader.insert("untitled.css", "untitled { }");
+        let mut stylesheets = document_stylesheets(&root, &loader);
+        let expected = |enabled: &[&str]| -> Vec<(String, Option<&str>, bool)> {
+            let sheets = [
+                ("persistent", None),
+                ("dark", Some("Dark")),
+                ("light", Some("Light")),
+                ("dark2", Some("Dark")),
+                ("contrast", Some("Contrast")),
+                ("untitled", None),
+            ];
+            sheets
+                .into_iter()
+                .map(|(name, title)| (name.to_string(), title, !enabled.contains(&name)))
+                .collect()
+        };
+        assert_eq!(
+            summary(&stylesheets),
+            expected(&["persistent", "dark", "dark2"])
+        );
+
+        let generation = stylesheets[1].generation;
+        select_style_set(&mut stylesheets, Some("Contrast"));
+        assert_eq!(summary(&stylesheets), expected(&["persistent", "contrast"]));
+        assert_ne!(stylesheets[1].generation, generation);
+        select_style_set(&mut stylesheets, None);
+        assert_eq!(summary(&stylesheets), expected(&["persistent"]));
+    }
+}
@@ -2,31 +2,39 @@ use std::{collections::HashMap, println};
 
 use crate::{datatypes::AttrMap, Element, Node, Token};
 
+const VOID_ELEMENTS: [&str; 13] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
 pub fn parse_element(tokens: &[Token]) -> (Element, &[Token]) {
     let mut element = Element::new("".to_string());
 
+    let self_closing;
     if let Some(Token::OpeningTag(tag_name)) = tokens.first() {
-        match tag_name.split(' ').next().clone() {
+        let tag_name = tag_name.trim_end();
+        self_closing = tag_name.ends_with('/');
+        let tag_name = tag_name.trim_end_matches('/');
+
+        match tag_name.split_whitespace().next() {
             Some(s) => element.name = s.to_string(),
             None => {
                 panic!("No tagname");
             }
         }
 
-        for pair in tag_name.split_whitespace() {
-            let mut keyval = pair.split("=");
-            if let (Some(key), Some(value)) = (keyval.next(), keyval.next()) {
-                element
-                    .attributes
-                    .insert(key.to_string(), value.to_string());
-            }
-        }
+        element.attributes = parse_attributes(&tag_name[element.name.len()..]);
     } else {
         panic!("Expected open tag");
     }
 
     let mut rest = &tokens[1..];
 
+    // Void elements such as `<link>` and `<br>` never have children or a closing tag.
+    if self_closing || VOID_ELEMENTS.contains(&&*element.name) {
+        return (element, rest);
+    }
+
     while let Some(token) = rest.first() {
         match token {
             Token::OpeningTag(_) => {
@@ -35,7 +43,8 @@ pub fn parse_element(tokens: &[Token]) -> (Element, &[Token]) {
                 rest = new_rest;
             }
             Token::ClosingTag(tag_name) => {
-                if tag_name == &element.name {
+                // End tags may differ in case and have trailing whitespace, e.g. `</SCRIPT >`.
+                if tag_name.trim_end().eq_ignore_ascii_case(&element.name) {
                     return (element, &rest[1..]);
                 } else {
                     panic!("Mismatched tags");
@@ -50,3 +59,40 @@ pub fn parse_element(tokens: &[Token]) -> (Element, &[Token]) {
 
     panic!("Expected close tag");
 }
+
+// Parse the attributes following the tag name, e.g. `id=main class="a b" hidden`. Values may
+// be unquoted or quoted with single or double quotes; attributes without a value are empty.
+fn parse_attributes(input: &str) -> AttrMap {
+    let mut attributes = HashMap::new();
+    let mut chars = input.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace() || *c == '/').is_some() {}
+        let mut key = String::new();
+        while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != '=') {
+            key.push(c);
+        }
+        if key.is_empty() {
+            break;
+        }
+
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let mut value = String::new();
+        if chars.next_if_eq(&'=').is_some() {
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            match chars.next_if(|c| *c == '"' || *c == '\'') {
+                Some(quote) => {
+                    value.extend(chars.by_ref().take_while(|c| *c != quote));
+                }
+                None => {
+                    while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                        value.push(c);
+                    }
+                }
+            }
+        }
+        attributes.insert(key.to_ascii_lowercase(), value);
+    }
+
+    attributes
+}
@@ -0,0 +1,488 @@
+use std::collections::HashMap;
+
+use ab_glyph_rasterizer::{point, Point, Rasterizer};
+use self_cell::self_cell;
+use ttf_parser::{GlyphId, OutlineBuilder};
+
+use crate::shaping::{place_characters, shape, GlyphRun};
+
+// Glyphs of synthesized bold are drawn twice, this fraction of the em apart.
+const EMBOLDEN: f32 = 1.0 / 24.0;
+// Synthesized oblique slants glyphs by about 14 degrees.
+const OBLIQUE_SKEW: f32 = 0.25;
+
+/// Measures text for layout. All measures are in pixels for a font of `font_size` pixels.
+pub trait FontMetrics {
+    /// How far the pen moves after drawing `c`.
+    fn advance(&self, c: char, font_size: f32) -> f32;
+
+    /// Distance from the baseline to the top of the line box.
+    fn ascent(&self, font_size: f32) -> f32;
+
+    /// Distance from the baseline to the bottom of the line box, as a positive number.
+    fn descent(&self, font_size: f32) -> f32;
+
+    /// Extra space the font asks for between lines.
+    fn line_gap(&self, font_size: f32) -> f32;
+
+    fn text_width(&self, text: &str, font_size: f32) -> f32 {
+        text.chars().map(|c| self.advance(c, font_size)).sum()
+    }
+
+    /// The height of a line of text, which `line-height: normal` uses.
+    fn line_height(&self, font_size: f32) -> f32 {
+        self.ascent(font_size) + self.descent(font_size) + self.line_gap(font_size)
+    }
+
+    /// Where the font puts underlines, or `None` if it does not say.
+    fn underline(&self, _font_size: f32) -> Option<LineMetrics> {
+        None
+    }
+
+    /// Where the font puts lines through text, or `None` if it does not say.
+    fn strikeout(&self, _font_size: f32) -> Option<LineMetrics> {
+        None
+    }
+
+    /// Whether the font has a glyph for `c`, rather than drawing it as a missing glyph.
+    fn has_glyph(&self, _c: char) -> bool {
+        true
+    }
+
+    /// The glyphs that draw `text`, right to left if `rtl`. Without shaping, every character
+    /// is drawn with the missing glyph.
+    fn shape(&self, text: &str, font_size: f32, rtl: bool) -> GlyphRun {
+        let ascent = self.ascent(font_size);
+        let advance = |c| self.advance(c, font_size);
+        place_characters(text, font_size, ascent, rtl, |_| 0, advance)
+    }
+
+    /// The coverage of `glyph` drawn at `font_size`, made bolder or slanted as `synthesis`
+    /// asks, or `None` if there is nothing to draw.
+    fn rasterize(&self, _glyph: u16, _font_size: f32, _synthesis: Synthesis) -> Option<GlyphImage> {
+        None
+    }
+}
+
+/// Where a line under or through text goes: how far below the baseline its top edge is,
+/// negative above the baseline, and how thick it is.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LineMetrics {
+    pub offset: f32,
+    pub thickness: f32,
+}
+
+/// Bold and oblique made up from a face of normal weight or style, for families that have no
+/// bold or italic face.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Synthesis {
+    pub bold: bool,
+    pub oblique: bool,
+}
+
+impl Synthesis {
+    /// How much wider each glyph gets.
+    pub fn extra_advance(&self, font_size: f32) -> f32 {
+        match self.bold {
+            true => font_size * EMBOLDEN,
+            false => 0.0,
+        }
+    }
+}
+
+type Face<'a> = rustybuzz::Face<'a>;
+
+self_cell!(
+    // The data of a font file with the tables of one of its fonts, parsed once for shaping
+    // and drawing glyphs.
+    struct FontData {
+        owner: Vec<u8>,
+
+        #[covariant]
+        dependent: Face,
+    }
+);
+
+/// Metrics read from a TrueType or OpenType font file, which also shapes text and draws
+/// glyphs with the font.
+pub struct TrueTypeFont {
+    data: FontData,
+    units_per_em: f32,
+    ascender: f32,
+    descender: f32,
+    line_gap: f32,
+    // Top edge and thickness of underlines and lines through text, in font units upwards from
+    // the baseline, if the font has them.
+    underline: Option<ttf_parser::LineMetrics>,
+    strikeout: Option<ttf_parser::LineMetrics>,
+    // Glyph of each character the font covers.
+    glyphs: HashMap<char, u16>,
+    // Horizontal advance of each glyph, in font units.
+    advances: Vec<u16>,
+}
+
+impl TrueTypeFont {
+    /// The font at `index` in `data`, which is 0 unless `data` is a font collection, or
+    /// `None` if there is no such font.
+    pub fn parse(data: &[u8], index: u32) -> Option<TrueTypeFont> {
+        let data = FontData::try_new(data.to_vec(), |data| {
+            Face::from_slice(data, index).ok_or(())
+        })
+        .ok()?;
+        let face = data.borrow_dependent();
+
+        let mut glyphs = HashMap::new();
+        for subtable in face.tables().cmap?.subtables {
+            if !subtable.is_unicode() {
+                continue;
+            }
+            subtable.codepoints(|code_point| {
+                let glyph = char::from_u32(code_point)
+                    .and_then(|c| Some((c, subtable.glyph_index(code_point)?)));
+                if let Some((c, glyph)) = glyph {
+                    glyphs.entry(c).or_insert(glyph.0);
+                }
+            });
+        }
+        let advances = (0..face.number_of_glyphs())
+            .map(|glyph| {
+                face.glyph_hor_advance(ttf_parser::GlyphId(glyph))
+                    .unwrap_or(0)
+            })
+            .collect();
+
+        Some(TrueTypeFont {
+            units_per_em: face.units_per_em() as f32,
+            ascender: face.ascender() as f32,
+            descender: -(face.descender() as f32),
+            line_gap: face.line_gap() as f32,
+            underline: face.underline_metrics(),
+            strikeout: face.strikeout_metrics(),
+            glyphs,
+            advances,
+            data,
+        })
+    }
+
+    /// The Open Sans font bundled with the browser, used for all text for now.
+    pub fn open_sans() -> TrueTypeFont {
+        TrueTypeFont::parse(include_bytes!("../../assets/OpenSans-Regular.ttf"), 0)
+            .expect("bundled font is invalid")
+    }
+
+    /// The glyph drawn for `c`, which is the missing glyph 0 if the font does not cover it.
+    pub fn glyph(&self, c: char) -> u16 {
+        self.glyphs.get(&c).copied().unwrap_or(0)
+    }
+
+    fn scale(&self, units: f32, font_size: f32) -> f32 {
+        units * font_size / self.units_per_em
+    }
+
+    // Line metrics in pixels, or `None` for lines without thickness.
+    fn line_metrics(
+        &self,
+        metrics: Option<ttf_parser::LineMetrics>,
+        font_size: f32,
+    ) -> Option<LineMetrics> {
+        metrics
+            .filter(|metrics| metrics.thickness > 0)
+            .map(|metrics| LineMetrics {
+                offset: -self.scale(metrics.position as f32, font_size),
+                thickness: self.scale(metrics.thickness as f32, font_size),
+            })
+    }
+}
+
+/// The largest blur radius of text shadows, in pixels. Blurs grow glyph images by one and a
+/// half times the radius on every side, so larger radii are drawn with this one.
+pub const MAX_BLUR_RADIUS: f32 = 100.0;
+
+/// A glyph drawn into pixels, as the coverage of each pixel row by row.
+pub struct GlyphImage {
+    /// Position of the top left pixel from the glyph origin, upwards being negative.
+    pub left: i32,
+    pub top: i32,
+    pub width: u32,
+    pub height: u32,
+    pub coverage: Vec<u8>,
+}
+
+impl GlyphImage {
+    /// The image blurred as text shadows are, by a Gaussian blur whose standard deviation is
+    /// half of `radius`, at most [`MAX_BLUR_RADIUS`]. The image grows by the reach of the
+    /// blur on every side.
+    pub fn blurred(&self, radius: f32) -> GlyphImage {
+        let sigma = radius.clamp(f32::EPSILON, MAX_BLUR_RADIUS) / 2.0;
+        let reach = (sigma * 3.0).ceil().max(1.0) as usize;
+        let kernel: Vec<f32> = (0..=2 * reach)
+            .map(|i| {
+                let d = i as f32 - reach as f32;
+                (-d * d / (2.0 * sigma * sigma)).exp()
+            })
+            .collect();
+        let sum: f32 = kernel.iter().sum();
+        let kernel: Vec<f32> = kernel.iter().map(|weight| weight / sum).collect();
+
+        // Blur the rows, then the columns.
+        let (old_width, old_height) = (self.width as usize, self.height as usize);
+        let (width, height) = (old_width + 2 * reach, old_height + 2 * reach);
+        let mut rows = vec![0.0; width * old_height];
+        for (i, &alpha) in self.coverage.iter().enumerate() {
+            let start = i / old_width * width + i % old_width;
+            for (k, weight) in kernel.iter().enumerate() {
+                rows[start + k] += alpha as f32 * weight;
+            }
+        }
+        let mut blurred = vec![0.0; width * height];
+        for (i, &alpha) in rows.iter().enumerate() {
+            for (k, weight) in kernel.iter().enumerate() {
+                blurred[i + k * width] += alpha * weight;
+            }
+        }
+
+        GlyphImage {
+            left: self.left - reach as i32,
+            top: self.top - reach as i32,
+            width: width as u32,
+            height: height as u32,
+            coverage: blurred
+                .into_iter()
+                .map(|alpha| alpha.round().min(255.0) as u8)
+                .collect(),
+        }
+    }
+}
+
+// The outline of a glyph as the segments of its contours, in font units.
+#[derive(Default)]
+struct Outline {
+    segments: Vec<Segment>,
+    start: Option<Point>,
+    last: Option<Point>,
+}
+
+#[derive(Clone, Copy)]
+enum Segment {
+    Line(Point, Point),
+    Quad(Point, Point, Point),
+    Cubic(Point, Point, Point, Point),
+}
+
+impl Segment {
+    fn map(&self, f: impl Fn(Point) -> Point) -> Segment {
+        match *self {
+            Segment::Line(p0, p1) => Segment::Line(f(p0), f(p1)),
+            Segment::Quad(p0, p1, p2) => Segment::Quad(f(p0), f(p1), f(p2)),
+            Segment::Cubic(p0, p1, p2, p3) => Segment::Cubic(f(p0), f(p1), f(p2), f(p3)),
+        }
+    }
+
+    // The end and control points, whose bounding box contains the segment.
+    fn points(&self) -> Vec<Point> {
+        match *self {
+            Segment::Line(p0, p1) => vec![p0, p1],
+            Segment::Quad(p0, p1, p2) => vec![p0, p1, p2],
+            Segment::Cubic(p0, p1, p2, p3) => vec![p0, p1, p2, p3],
+        }
+    }
+}
+
+impl OutlineBuilder for Outline {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.start = Some(point(x, y));
+        self.last = self.start;
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        if let Some(last) = self.last.replace(point(x, y)) {
+            self.segments.push(Segment::Line(last, point(x, y)));
+        }
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        if let Some(last) = self.last.replace(point(x, y)) {
+            self.segments
+                .push(Segment::Quad(last, point(x1, y1), point(x, y)));
+        }
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        if let Some(last) = self.last.replace(point(x, y)) {
+            self.segments.push(Segment::Cubic(
+                last,
+                point(x1, y1),
+                point(x2, y2),
+                point(x, y),
+            ));
+        }
+    }
+
+    fn close(&mut self) {
+        if let (Some(last), Some(start)) = (self.last, self.start) {
+            self.segments.push(Segment::Line(last, start));
+        }
+        self.last = self.start;
+    }
+}
+
+impl FontMetrics for TrueTypeFont {
+    fn advance(&self, c: char, font_size: f32) -> f32 {
+        let units = self.advances.get(self.glyph(c) as usize).copied();
+        self.scale(units.unwrap_or(0) as f32, font_size)
+    }
+
+    fn ascent(&self, font_size: f32) -> f32 {
+        self.scale(self.ascender, font_size)
+    }
+
+    fn descent(&self, font_size: f32) -> f32 {
+        self.scale(self.descender, font_size)
+    }
+
+    fn line_gap(&self, font_size: f32) -> f32 {
+        self.scale(self.line_gap, font_size)
+    }
+
+    // Measure shaped text, so kerning and ligatures are taken into account.
+    fn text_width(&self, text: &str, font_size: f32) -> f32 {
+        self.shape(text, font_size, false).width
+    }
+
+    fn shape(&self, text: &str, font_size: f32, rtl: bool) -> GlyphRun {
+        shape(
+            self.data.borrow_dependent(),
+            text,
+            font_size,
+            self.ascent(font_size),
+            rtl,
+        )
+    }
+
+    fn underline(&self, font_size: f32) -> Option<LineMetrics> {
+        self.line_metrics(self.underline, font_size)
+    }
+
+    fn strikeout(&self, font_size: f32) -> Option<LineMetrics> {
+        self.line_metrics(self.strikeout, font_size)
+    }
+
+    fn has_glyph(&self, c: char) -> bool {
+        self.glyphs.contains_key(&c)
+    }
+
+    fn rasterize(&self, glyph: u16, font_size: f32, synthesis: Synthesis) -> Option<GlyphImage> {
+        let face = self.data.borrow_dependent();
+        let mut outline = Outline::default();
+        face.outline_glyph(GlyphId(glyph), &mut outline)?;
+
+        // Points in pixels from the glyph origin, flipped to grow downwards.
+        let scale = self.scale(1.0, font_size);
+        let skew = if synthesis.oblique { OBLIQUE_SKEW } else { 0.0 };
+        let segments: Vec<Segment> = outline
+            .segments
+            .iter()
+            .map(|segment| segment.map(|p| point((p.x + p.y * skew) * scale, -p.y * scale)))
+            .collect();
+        let bold = synthesis.extra_advance(font_size);
+
+        // The image starts at the top left of the bounding box of the points.
+        let points = segments.iter().flat_map(Segment::points);
+        let (mut min, mut max) = (point(f32::MAX, f32::MAX), point(f32::MIN, f32::MIN));
+        for p in points {
+            (min.x, min.y) = (min.x.min(p.x), min.y.min(p.y));
+            (max.x, max.y) = (max.x.max(p.x), max.y.max(p.y));
+        }
+        let (left, top) = (min.x.floor(), min.y.floor());
+        let width = ((max.x + bold - left).ceil() as usize).max(1);
+        let height = ((max.y - top).ceil() as usize).max(1);
+
+        // Bold is drawn as the union of the outline and a copy moved to the right.
+        let mut rasterizer = Rasterizer::new(width, height);
+        let copies: &[f32] = if synthesis.bold { &[0.0, bold] } else { &[0.0] };
+        for &shift in copies {
+            for segment in &segments {
+                match segment.map(|p| point(p.x - left + shift, p.y - top)) {
+                    Segment::Line(p0, p1) => rasterizer.draw_line(p0, p1),
+                    Segment::Quad(p0, p1, p2) => rasterizer.draw_quad(p0, p1, p2),
+                    Segment::Cubic(p0, p1, p2, p3) => rasterizer.draw_cubic(p0, p1, p2, p3),
+                }
+            }
+        }
+        let mut coverage = vec![0; width * height];
+        rasterizer.for_each_pixel(|i, alpha| coverage[i] = (alpha.min(1.0) * 255.0) as u8);
+
+        Some(GlyphImage {
+            left: left as i32,
+            top: top as i32,
+            width: width as u32,
+            height: height as u32,
+            coverage,
+        })
+    }
+}
+
+/// Metrics that are the same fractions of the font size for every character, so layout can
+/// be checked without a font file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FixedMetrics {
+    pub advance: f32,
+    pub ascent: f32,
+    pub descent: f32,
+    pub line_gap: f32,
+}
+
+impl Default for FixedMetrics {
+    fn default() -> Self {
+        FixedMetrics {
+            advance: 0.5,
+            ascent: 0.8,
+            descent: 0.2,
+            line_gap: 0.0,
+        }
+    }
+}
+
+impl FontMetrics for FixedMetrics {
+    fn advance(&self, _c: char, font_size: f32) -> f32 {
+        self.advance * font_size
+    }
+
+    fn ascent(&self, font_size: f32) -> f32 {
+        self.ascent * font_size
+    }
+
+    fn descent(&self, font_size: f32) -> f32 {
+        self.descent * font_size
+    }
+
+    fn line_gap(&self, font_size: f32) -> f32 {
+        self.line_gap * font_size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blurs_spread_the_image_up_to_the_largest_radius() {
+        let image = GlyphImage {
+            left: 0,
+            top: -1,
+            width: 1,
+            height: 1,
+            coverage: vec![255],
+        };
+        let blurred = image.blurred(2.0);
+        assert_eq!((blurred.left, blurred.top), (-3, -4));
+        assert_eq!((blurred.width, blurred.height), (7, 7));
+        // The coverage is spread out, rounded to whole steps.
+        let total: u32 = blurred.coverage.iter().map(|&c| c as u32).sum();
+        assert!((245..=265).contains(&total));
+        assert_eq!(blurred.coverage.iter().max(), Some(&blurred.coverage[24]));
+
+        let reach = (MAX_BLUR_RADIUS * 1.5) as u32;
+        assert_eq!(image.blurred(10000.0).width, 1 + 2 * reach);
+    }
+}
@@ -0,0 +1,665 @@
+//! The fonts text can be drawn with, and the choice among them per the font matching algorithm
+//! of CSS Fonts 4: the families of `font-family` in order, the face of a family nearest to
+//! `font-style` and `font-weight`, and other fonts for the characters the first ones lack.
+
+use std::{
+    cell::{OnceCell, RefCell},
+    collections::HashMap,
+    env, fs, iter,
+    ops::{Range, RangeInclusive},
+    path::{Path, PathBuf},
+};
+
+use ttf_parser::{name_id, Face};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+    computed::{FontFamily, FontStyle, GenericFamily},
+    css::{FontSource, Stylesheet},
+    font::{FontMetrics, LineMetrics, Synthesis, TrueTypeFont},
+    loader::ResourceLoader,
+    properties::FontProperties,
+    shaping::{Glyph, GlyphRun},
+    woff,
+};
+
+// The formats of `@font-face` sources that can be loaded. `-variations` may follow them.
+const SUPPORTED_FORMATS: &[&str] = &["truetype", "opentype", "woff", "woff2", "collection"];
+
+// Where fonts are installed on Linux, macOS and Windows. `~` is the home directory.
+const SYSTEM_FONT_DIRECTORIES: &[&str] = &[
+    "/usr/share/fonts",
+    "/usr/local/share/fonts",
+    "~/.local/share/fonts",
+    "~/.fonts",
+    "/System/Library/Fonts",
+    "/Library/Fonts",
+    "~/Library/Fonts",
+    "C:\\Windows\\Fonts",
+];
+
+// The families a generic family stands for, most preferred first.
+fn generic_families(generic: GenericFamily) -> &'static [&'static str] {
+    match generic {
+        GenericFamily::Serif => &[
+            "Times New Roman",
+            "Times",
+            "Liberation Serif",
+            "DejaVu Serif",
+            "Noto Serif",
+        ],
+        GenericFamily::SansSerif => &[
+            "Open Sans",
+            "Arial",
+            "Helvetica",
+            "Liberation Sans",
+            "DejaVu Sans",
+            "Noto Sans",
+        ],
+        GenericFamily::Monospace => &[
+            "Courier New",
+            "Menlo",
+            "Consolas",
+            "Liberation Mono",
+            "DejaVu Sans Mono",
+            "Noto Sans Mono",
+        ],
+        GenericFamily::Cursive => &["Comic Sans MS", "Apple Chancery", "URW Chancery L"],
+        GenericFamily::Fantasy => &["Impact", "Papyrus", "Luminari"],
+        GenericFamily::SystemUi => &[
+            "Segoe UI",
+            "San Francisco",
+            "Cantarell",
+            "Ubuntu",
+            "Noto Sans",
+        ],
+    }
+}
+
+/// A face of the database with the bold or oblique it has to make up for an element.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct FontInstance {
+    pub face: usize,
+    pub synthesis: Synthesis,
+}
+
+/// The fonts chosen for an element: the faces matched in each family of its `font-family`
+/// that the database has. A family split into faces for different characters with
+/// `unicode-range` contributes all of them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Font {
+    pub size: f32,
+    pub weight: u16,
+    pub style: FontStyle,
+    pub faces: Vec<FontInstance>,
+    // The first face with a space, whose metrics lay out the lines.
+    pub primary: FontInstance,
+}
+
+// One face of a family.
+struct FaceEntry {
+    family: String,
+    // Least and greatest weight the face is used for, the same unless it is variable.
+    weight: (u16, u16),
+    style: FontStyle,
+    // The characters the face is used for, or all it has if empty.
+    unicode_range: Vec<RangeInclusive<u32>>,
+    // The file and index in it of a face found on disk, which is read when first used.
+    file: Option<(PathBuf, u32)>,
+    // `None` once the file turned out to be unreadable or not a font.
+    font: OnceCell<Option<Box<dyn FontMetrics>>>,
+}
+
+#[derive(Default)]
+pub struct FontDatabase {
+    faces: Vec<FaceEntry>,
+    // Faces of each family, by lowercase family name.
+    families: HashMap<String, Vec<usize>>,
+    // Faces found on disk by lowercase full name and PostScript name, for `local()`.
+    local_names: HashMap<String, usize>,
+    // The family that draws each character none of the families of its text have.
+    fallbacks: RefCell<HashMap<char, Option<String>>>,
+    // The bundled font, for faces whose file cannot be loaded when no other face can be.
+    bundled: OnceCell<TrueTypeFont>,
+}
+
+impl FontDatabase {
+    pub fn new() -> FontDatabase {
+        FontDatabase::default()
+    }
+
+    /// A database of the fonts bundled with the browser. The family of its first face is the
+    /// default for text none of whose families are available.
+    pub fn bundled() -> FontDatabase {
+        let mut database = FontDatabase::new();
+        let open_sans = Box::new(TrueTypeFont::open_sans());
+        database.add_face("Open Sans", 400, FontStyle::Normal, open_sans);
+        database
+    }
+
+    /// Add a face of `family` with the given weight and style. Returns its index.
+    pub fn add_face(
+        &mut self,
+        family: &str,
+        weight: u16,
+        style: FontStyle,
+        font: Box<dyn FontMetrics>,
+    ) -> usize {
+        self.push_face(FaceEntry {
+            family: family.to_string(),
+            weight: (weight, weight),
+            style,
+            unicode_range: Vec::new(),
+            file: None,
+            font: OnceCell::from(Some(font)),
+        })
+    }
+
+    /// Add the fonts of the `@font-face` rules of `stylesheets`, fetching their files through
+    /// `loader`. A face takes the first of its sources that loads, and is left out if none
+    /// does.
+    pub fn add_font_faces(&mut self, stylesheets: &[Stylesheet], loader: &dyn ResourceLoader) {
+        let font_faces = stylesheets
+            .iter()
+            .filter(|sheet| !sheet.disabled)
+            .flat_map(|sheet| &sheet.font_faces);
+        for font_face in font_faces {
+            let loaded = font_face.sources.iter().find_map(|source| match source {
+                FontSource::Local(name) => {
+                    let installed = &self.faces[*self.local_names.get(&name.to_lowercase())?];
+                    Some((installed.file.clone(), OnceCell::new()))
+                }
+                FontSource::Url(url, formats) => {
+                    let supported = formats.is_empty()
+                        || formats.iter().any(|format| {
+                            let format = format.trim_end_matches("-variations");
+                            SUPPORTED_FORMATS.contains(&format)
+                        });
+                    if !supported {
+                        return None;
+                    }
+                    let data = woff::decode(loader.load(url)?)?;
+                    let font: Box<dyn FontMetrics> = Box::new(TrueTypeFont::parse(&data, 0)?);
+                    Some((None, OnceCell::from(Some(font))))
+                }
+            });
+            if let Some((file, font)) = loaded {
+                self.push_face(FaceEntry {
+                    family: font_face.family.clone(),
+                    weight: font_face.weight,
+                    style: font_face.style,
+                    unicode_range: font_face.unicode_range.clone(),
+                    file,
+                    font,
+                });
+            }
+        }
+    }
+
+    /// Add the font files in `directory` and its subdirectories. Files are read to find the
+    /// family, weight and style of their faces, and read again when a face is first used.
+    pub fn load_directory(&mut self, directory: &Path) {
+        let entries = match fs::read_dir(directory) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+                self.load_directory(&path);
+                continue;
+            }
+            let is_font = path
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .is_some_and(|extension| {
+                    matches!(
+                        &*extension.to_ascii_lowercase(),
+                        "ttf" | "otf" | "ttc" | "otc"
+                    )
+                });
+            let data = match is_font.then(|| fs::read(&path)) {
+                Some(Ok(data)) => data,
+                _ => continue,
+            };
+            for index in 0..ttf_parser::fonts_in_collection(&data).unwrap_or(1) {
+                let face = match Face::parse(&data, index) {
+                    Ok(face) => face,
+                    Err(_) => continue,
+                };
+                // The typographic family groups more weights and styles than the legacy family
+                // name some fonts also have.
+                let family = match name(&face, name_id::TYPOGRAPHIC_FAMILY)
+                    .or_else(|| name(&face, name_id::FAMILY))
+                {
+                    Some(family) => family,
+                    None => continue,
+                };
+                let weight = face.weight().to_number();
+                let added = self.push_face(FaceEntry {
+                    family,
+                    weight: (weight, weight),
+                    style: match face.style() {
+                        ttf_parser::Style::Normal => FontStyle::Normal,
+                        ttf_parser::Style::Italic => FontStyle::Italic,
+                        ttf_parser::Style::Oblique => FontStyle::Oblique,
+                    },
+                    unicode_range: Vec::new(),
+                    file: Some((path.clone(), index)),
+                    font: OnceCell::new(),
+                });
+                for id in [name_id::FULL_NAME, name_id::POST_SCRIPT_NAME] {
+                    if let Some(local_name) = name(&face, id) {
+                        self.local_names.insert(local_name.to_lowercase(), added);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Add the fonts installed on the system.
+    pub fn load_system_fonts(&mut self) {
+        let home = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE"));
+        for directory in SYSTEM_FONT_DIRECTORIES {
+            match (directory.strip_prefix("~/"), &home) {
+                (Some(path), Some(home)) => self.load_directory(&Path::new(home).join(path)),
+                (Some(_), None) => {}
+                (None, _) => self.load_directory(Path::new(directory)),
+            }
+        }
+    }
+
+    fn push_face(&mut self, entry: FaceEntry) -> usize {
+        let index = self.faces.len();
+        self.families
+            .entry(entry.family.to_lowercase())
+            .or_default()
+            .push(index);
+        self.faces.push(entry);
+        self.fallbacks.borrow_mut().clear();
+        index
+    }
+
+    /// The font of face `face`, read from its file if this is its first use. A face whose
+    /// file cannot be read or parsed is no longer chosen for text, and the next face that
+    /// loads, or else the bundled font, stands in for it.
+    pub fn face(&self, face: usize) -> &dyn FontMetrics {
+        let next = (face + 1..self.faces.len()).chain(0..face);
+        iter::once(face)
+            .chain(next)
+            .find_map(|face| self.load(face))
+            .unwrap_or_else(|| self.bundled.get_or_init(TrueTypeFont::open_sans))
+    }
+
+    // The font of face `face`, or `None` if its file cannot be read or is not a font.
+    fn load(&self, face: usize) -> Option<&dyn FontMetrics> {
+        let entry = &self.faces[face];
+        let font = entry.font.get_or_init(|| {
+            let (path, index) = entry.file.as_ref()?;
+            let data = fs::read(path).ok()?;
+            let font: Box<dyn FontMetrics> = Box::new(TrueTypeFont::parse(&data, *index)?);
+            Some(font)
+        });
+        font.as_deref()
+    }
+
+    // Whether face `face` was found not to load.
+    fn failed(&self, face: usize) -> bool {
+        matches!(self.faces[face].font.get(), Some(None))
+    }
+
+    /// Choose the fonts for an element with the font properties `properties`.
+    pub fn select(&self, properties: &FontProperties) -> Font {
+        let default = FontFamily::Named(
+            self.faces
+                .first()
+                .expect("font database has no fonts")
+                .family
+                .clone(),
+        );
+        let mut font = Font {
+            size: properties.font_size,
+            weight: properties.font_weight,
+            style: properties.font_style,
+            faces: Vec::new(),
+            primary: FontInstance::default(),
+        };
+        for family in properties.font_family.0.iter().chain([&default]) {
+            let instances = match family {
+                FontFamily::Named(name) => self.match_family(name, &font),
+                FontFamily::Generic(generic) => generic_families(*generic)
+                    .iter()
+                    .find_map(|name| self.match_family(name, &font)),
+            };
+            for instance in instances.into_iter().flatten() {
+                if !font.faces.contains(&instance) {
+                    font.faces.push(instance);
+                }
+            }
+        }
+        // If none of the families has a face that loads, `face` finds a stand-in for the
+        // first face of the database.
+        font.primary = font
+            .faces
+            .iter()
+            .find(|instance| self.covers(instance.face, ' '))
+            .or(font.faces.first())
+            .copied()
+            .unwrap_or_default();
+        font
+    }
+
+    // The faces of family `name` nearest to the style and weight of `font`, with bold and
+    // oblique synthesized if the family has no such face. There are several if the family
+    // is split by `unicode-range`, the one declared last first.
+    fn match_family(&self, name: &str, font: &Font) -> Option<Vec<FontInstance>> {
+        let faces = self.families.get(&name.to_lowercase())?;
+
+        // Italic text takes an oblique face if there is no italic one, and the other way
+        // around. Both take a normal one if the family has no other.
+        let preference = match font.style {
+            FontStyle::Normal => [FontStyle::Normal, FontStyle::Oblique, FontStyle::Italic],
+            FontStyle::Italic => [FontStyle::Italic, FontStyle::Oblique, FontStyle::Normal],
+            FontStyle::Oblique => [FontStyle::Oblique, FontStyle::Italic, FontStyle::Normal],
+        };
+        let styled = preference
+            .iter()
+            .map(|&style| {
+                let styled: Vec<usize> = faces
+                    .iter()
+                    .copied()
+                    .filter(|&face| self.faces[face].style == style && !self.failed(face))
+                    .collect();
+                styled
+            })
+            .find(|styled| !styled.is_empty())?;
+
+        let weight = self.nearest_weight(&styled, font.weight);
+        let instances = styled
+            .iter()
+            .rev()
+            .filter(|&&face| self.weight_for(face, font.weight) == weight)
+            .map(|&face| {
+                let entry = &self.faces[face];
+                FontInstance {
+                    face,
+                    synthesis: Synthesis {
+                        bold: font.weight >= 600 && entry.weight.1 < 600,
+                        oblique: font.style != FontStyle::Normal
+                            && entry.style == FontStyle::Normal,
+                    },
+                }
+            })
+            .collect();
+        Some(instances)
+    }
+
+    // The weight of `faces` nearest to `weight`. Weights from 400 to 500 take a heavier face
+    // up to 500 first, then a lighter one, then a heavier one. Lighter weights prefer lighter
+    // faces and bolder weights bolder faces.
+    fn nearest_weight(&self, faces: &[usize], weight: u16) -> u16 {
+        let weights = faces.iter().map(|&face| self.weight_for(face, weight));
+        let lighter = weights.clone().filter(|&w| w <= weight).max();
+        let heavier = weights.clone().filter(|&w| w >= weight).min();
+        let nearest = match weight {
+            400..=500 => weights
+                .filter(|w| (weight..=500).contains(w))
+                .min()
+                .or(lighter)
+                .or(heavier),
+            0..=399 => lighter.or(heavier),
+            _ => heavier.or(lighter),
+        };
+        nearest.expect("no faces to choose from")
+    }
+
+    // The weight of its range that face `face` draws text of `weight` with.
+    fn weight_for(&self, face: usize, weight: u16) -> u16 {
+        let (least, greatest) = self.faces[face].weight;
+        weight.clamp(least, greatest)
+    }
+
+    // Whether face `face` is used for `c` and has a glyph for it.
+    fn covers(&self, face: usize, c: char) -> bool {
+        let entry = &self.faces[face];
+        in_range(&entry.unicode_range, c) && self.load(face).is_some_and(|font| font.has_glyph(c))
+    }
+
+    // The face that draws `c` in `font`: the first of its faces with a glyph for it, else
+    // the nearest face of the first family in the database that has one, else the primary
+    // font, which draws the missing glyph.
+    fn instance_for(&self, c: char, font: &Font) -> FontInstance {
+        let covering = font
+            .faces
+            .iter()
+            .find(|instance| self.covers(instance.face, c));
+        if let Some(&instance) = covering {
+            return instance;
+        }
+        let family = self
+            .fallbacks
+            .borrow_mut()
+            .entry(c)
+            .or_insert_with(|| self.fallback_family(c))
+            .clone();
+        family
+            .and_then(|family| self.match_family(&family, font))
+            .and_then(|instances| {
+                let mut instances = instances.into_iter();
+                instances.find(|instance| self.covers(instance.face, c))
+            })
+            .unwrap_or(font.primary)
+    }
+
+    // The family of the first face with a glyph for `c`. Faces in use are looked at first,
+    // so that the files of the others are only read if none of those has one. Faces read
+    // here stay loaded, so no file is read twice.
+    fn fallback_family(&self, c: char) -> Option<String> {
+        let loaded = self.faces.iter().find(|entry| {
+            let font = entry.font.get().and_then(Option::as_ref);
+            in_range(&entry.unicode_range, c) && font.is_some_and(|font| font.has_glyph(c))
+        });
+        let covering = loaded.or_else(|| {
+            let mut unloaded = self.faces.iter().enumerate().filter(|(_, entry)| {
+                entry.file.is_some()
+                    && entry.font.get().is_none()
+                    && in_range(&entry.unicode_range, c)
+            });
+            unloaded
+                .find(|&(face, _)| self.load(face).is_some_and(|font| font.has_glyph(c)))
+                .map(|(_, entry)| entry)
+        });
+        covering.map(|entry| entry.family.clone())
+    }
+
+    /// Distance from the baseline to the top of the line box, in the primary font.
+    pub fn ascent(&self, font: &Font) -> f32 {
+        self.face(font.primary.face).ascent(font.size)
+    }
+
+    /// Distance from the baseline to the bottom of the line box, in the primary font.
+    pub fn descent(&self, font: &Font) -> f32 {
+        self.face(font.primary.face).descent(font.size)
+    }
+
+    /// The height of a line of text in the primary font, which `line-height: normal` uses.
+    pub fn line_height(&self, font: &Font) -> f32 {
+        self.face(font.primary.face).line_height(font.size)
+    }
+
+    /// Where underlines go in the primary font. Fonts that do not say get a thin line just
+    /// below the baseline.
+    pub fn underline(&self, font: &Font) -> LineMetrics {
+        let face = self.face(font.primary.face);
+        face.underline(font.size).unwrap_or(LineMetrics {
+            offset: font.size / 10.0,
+            thickness: font.size / 16.0,
+        })
+    }
+
+    /// Where lines through text go in the primary font. Fonts that do not say get a thin
+    /// line about halfway up lowercase letters.
+    pub fn strikeout(&self, font: &Font) -> LineMetrics {
+        let face = self.face(font.primary.face);
+        face.strikeout(font.size).unwrap_or(LineMetrics {
+            offset: -font.size * 0.3,
+            thickness: font.size / 16.0,
+        })
+    }
+
+    /// How far the pen moves after drawing `c`.
+    pub fn advance(&self, c: char, font: &Font) -> f32 {
+        let instance = self.instance_for(c, font);
+        let advance = self.face(instance.face).advance(c, font.size);
+        advance + instance.synthesis.extra_advance(font.size)
+    }
+
+    pub fn text_width(&self, text: &str, font: &Font) -> f32 {
+        self.shape(text, font, false).width
+    }
+
+    /// The glyphs that draw `text`, right to left if `rtl`. Each run of characters that one
+    /// face draws is shaped with that face, and the glyphs are placed on the baseline of the
+    /// primary font.
+    pub fn shape(&self, text: &str, font: &Font, rtl: bool) -> GlyphRun {
+        // White space stays in the face of the text before it, and grapheme clusters in the
+        // face of their first character.
+        let mut runs: Vec<(Range<usize>, FontInstance)> = Vec::new();
+        for (start, grapheme) in text.grapheme_indices(true) {
+            let c = grapheme.chars().next().unwrap();
+            let instance = match runs.last() {
+                Some(&(_, last)) if c.is_whitespace() => last,
+                _ => self.instance_for(c, font),
+            };
+            let end = start + grapheme.len();
+            match runs.last_mut() {
+                Some((range, last)) if *last == instance => range.end = end,
+                _ => runs.push((start..end, instance)),
+            }
+        }
+        if rtl {
+            runs.reverse();
+        }
+
+        let ascent = self.ascent(font);
+        let mut width = 0.0;
+        let mut glyphs = Vec::new();
+        for (range, instance) in runs {
+            let face = self.face(instance.face);
+            let run = face.shape(&text[range.clone()], font.size, rtl);
+            let baseline = ascent - face.ascent(font.size);
+            let extra = instance.synthesis.extra_advance(font.size);
+            for (i, glyph) in run.glyphs.iter().enumerate() {
+                glyphs.push(Glyph {
+                    x: width + glyph.x + i as f32 * extra,
+                    y: glyph.y + baseline,
+                    cluster: range.start + glyph.cluster,
+                    font: instance,
+                    ..*glyph
+                });
+            }
+            width += run.width + run.glyphs.len() as f32 * extra;
+        }
+        GlyphRun {
+            text: text.to_string(),
+            font_size: font.size,
+            rtl,
+            width,
+            glyphs,
+        }
+    }
+}
+
+// Whether `c` is in one of the ranges of a `unicode-range`, which all characters are if it
+// is empty.
+fn in_range(unicode_range: &[RangeInclusive<u32>], c: char) -> bool {
+    unicode_range.is_empty()
+        || unicode_range
+            .iter()
+            .any(|range| range.contains(&(c as u32)))
+}
+
+// The name of a face with the given ID, such as its family or full name.
+fn name(face: &Face, id: u16) -> Option<String> {
+    face.names()
+        .into_iter()
+        .filter(|name| name.name_id == id && name.is_unicode())
+        .find_map(|name| name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{computed::FontFamilyList, font::FixedMetrics, properties::ComputedStyle};
+
+    // Add a face of "Open Sans" whose file is deleted after it is found.
+    fn add_missing_file(fonts: &mut FontDatabase, name: &str) {
+        let directory = env::temp_dir().join(format!("fontdb-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let font = include_bytes!("../../assets/OpenSans-Regular.ttf");
+        fs::write(directory.join("OpenSans-Regular.ttf"), font).unwrap();
+        fonts.load_directory(&directory);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    fn with_fixed_font() -> FontDatabase {
+        let mut fonts = FontDatabase::new();
+        let fixed = Box::new(FixedMetrics::default());
+        fonts.add_face("Fixed", 400, FontStyle::Normal, fixed);
+        fonts
+    }
+
+    fn properties(family: &str) -> FontProperties {
+        FontProperties {
+            font_family: FontFamilyList(vec![FontFamily::Named(family.to_string())]),
+            ..ComputedStyle::initial().font.clone()
+        }
+    }
+
+    #[test]
+    fn faces_whose_file_cannot_be_read_are_not_chosen() {
+        let mut fonts = with_fixed_font();
+        add_missing_file(&mut fonts, "unchosen");
+        assert_eq!(fonts.faces.len(), 2);
+
+        let font = fonts.select(&properties("Open Sans"));
+        assert_eq!(font.primary.face, 0);
+        assert_eq!(fonts.advance('a', &font), 8.0);
+        let font = fonts.select(&properties("Open Sans"));
+        assert_eq!(font.faces, [FontInstance::default()]);
+    }
+
+    #[test]
+    fn faces_whose_file_cannot_be_read_use_another_face() {
+        let mut fonts = with_fixed_font();
+        add_missing_file(&mut fonts, "replaced");
+        assert_eq!(fonts.face(1).advance('a', 16.0), 8.0);
+    }
+
+    #[test]
+    fn without_a_face_that_loads_the_bundled_font_is_used() {
+        let mut fonts = FontDatabase::new();
+        add_missing_file(&mut fonts, "bundled");
+
+        let font = fonts.select(&properties("Open Sans"));
+        assert!(fonts.face(font.primary.face).has_glyph('a'));
+        assert!(fonts.text_width("abc", &font) > 0.0);
+    }
+
+    #[test]
+    fn fallback_faces_are_read_once() {
+        let mut fonts = FontDatabase::new();
+        let directory = env::temp_dir().join(format!("fontdb-fallback-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let font = include_bytes!("../../assets/OpenSans-Regular.ttf");
+        fs::write(directory.join("OpenSans-Regular.ttf"), font).unwrap();
+        fonts.load_directory(&directory);
+
+        assert_eq!(fonts.fallback_family('a').as_deref(), Some("Open Sans"));
+        assert!(fonts.faces[0].font.get().is_some());
+        // The face is not read again for other characters.
+        fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(fonts.fallback_family('b').as_deref(), Some("Open Sans"));
+        assert_eq!(fonts.fallback_family('\u{4e00}'), None);
+    }
+}
@@ -0,0 +1,231 @@
+use std::mem;
+
+use crate::{
+    css::{parse_stylesheet, FontSource, Import, Rule, Stylesheet},
+    cssom::next_generation,
+    loader::{resolve_url, ResourceLoader},
+};
+
+/// Load the sheets imported by `stylesheet`, found at `url`, and insert their rules in front
+/// of its own, as if the `@import` rules were replaced by the imported sheets.
+///
+/// Imported rules are put in the import's layer and only apply when its media queries match.
+/// Imports are resolved recursively; a sheet that (indirectly) imports itself is skipped, as
+/// is any sheet the loader cannot provide.
+pub fn resolve_imports(stylesheet: &mut Stylesheet, url: &str, loader: &dyn ResourceLoader) {
+    let mut loading = vec![url.to_string()];
+    resolve(stylesheet, url, loader, &mut loading);
+}
+
+// `loading` holds the URLs of the sheets whose imports are being resolved, from the outermost
+// sheet in.
+fn resolve(
+    stylesheet: &mut Stylesheet,
+    url: &str,
+    loader: &dyn ResourceLoader,
+    loading: &mut Vec<String>,
+) {
+    // Font files are fetched relative to the sheet that declares them.
+    let sources = stylesheet
+        .font_faces
+        .iter_mut()
+        .flat_map(|face| &mut face.sources);
+    for source in sources {
+        if let FontSource::Url(font_url, _) = source {
+            *font_url = resolve_url(url, font_url);
+        }
+    }
+
+    let mut rules = Vec::new();
+    let mut registrations = Vec::new();
+    let mut font_faces = Vec::new();
+    let mut inserted_layers = 0;
+
+    for import in std::mem::take(&mut stylesheet.imports) {
+        let import_url = resolve_url(url, &import.url);
+        if loading.contains(&import_url) {
+            continue;
+        }
+        let text = match loader.load_text(&import_url) {
+            Some(text) => text,
+            None => continue,
+        };
+
+        let mut imported = parse_stylesheet(text, stylesheet.origin);
+        loading.push(import_url.clone());
+        resolve(&mut imported, &import_url, loader, loading);
+        loading.pop();
+
+        // The imported layers are declared where the `@import` is, nested in its layer.
+        for name in &imported.layers {
+            let name = layer_name(&import, Some(name)).unwrap();
+            let position = import.layer_position + inserted_layers;
+            match stylesheet.layers.iter().position(|layer| *layer == name) {
+                Some(declared) if declared < position => continue,
+                // Declared again later in the importing sheet, which does not change its order.
+                Some(declared) => {
+                    stylesheet.layers.remove(declared);
+                }
+                None => {}
+            }
+            stylesheet.layers.insert(position, name);
+            inserted_layers += 1;
+        }
+        registrations.append(&mut imported.registrations);
+        font_faces.append(&mut imported.font_faces);
+        rules.extend(
+            mem::take(imported.rules_mut())
+                .into_iter()
+                .map(|rule| Rule {
+                    layer: layer_name(&import, rule.layer.as_deref()),
+                    media: import.media.iter().cloned().chain(rule.media).collect(),
+                    ..rule
+                }),
+        );
+    }
+
+    let own = stylesheet.rules_mut();
+    rules.append(own);
+    *own = rules;
+    registrations.append(&mut stylesheet.registrations);
+    stylesheet.registrations = registrations;
+    font_faces.append(&mut stylesheet.font_faces);
+    stylesheet.font_faces = font_faces;
+    stylesheet.generation = next_generation();
+}
+
+// The full name of the imported layer `name` once nested in the layer of `import`.
+fn layer_name(import: &Import, name: Option<&str>) -> Option<String> {
+    match (&import.layer, name) {
+        (Some(layer), Some(name)) => Some(format!("{}.{}", layer, name)),
+        (Some(layer), None) => Some(layer.clone()),
+        (None, name) => name.map(str::to_string),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{css::parse_css, loader::MemoryLoader};
+
+    fn load(loader: &MemoryLoader, url: &str) -> Stylesheet {
+        let mut stylesheet = parse_css(loader.load_text(url).unwrap());
+        resolve_imports(&mut stylesheet, url, loader);
+        stylesheet
+    }
+
+    fn selectors(stylesheet: &Stylesheet) -> Vec<String> {
+        stylesheet
+            .rules()
+            .iter()
+            .map(|rule| rule.selector[0].to_string())
+            .collect()
+    }
+
+    #[test]
+    fn imported_rules_come_before_the_importing_sheets_rules() {
+        let mut loader = MemoryLoader::new();
+        loader.insert("site.css", "@import url(css/a.css); main { }");
+        loader.insert("css/a.css", "@import 'b.css'; a { }");
+        loader.insert("css/b.css", "b { }");
+
+        let stylesheet = load(&loader, "site.css");
+        assert_eq!(selectors(&stylesheet), ["b", "a", "main"]);
+        assert!(stylesheet.imports.is_empty());
+    }
+
+    #[test]
+    fn import_cycles_are_cut_where_a_sheet_imports_one_being_loaded() {
+        let mut loader = MemoryLoader::new();
+        loader.insert("site.css", "@import 'a.css'; main { }");
+        loader.insert("a.css", "@import 'b.css'; a { }");
+        loader.insert(
+            "b.css",
+            "@import 'a.css'; @import 'site.css'; @import 'b.css'; b { }",
+        );
+
+        assert_eq!(selectors(&load(&loader, "site.css")), ["b", "a", "main"]);
+    }
+
+    #[test]
+    fn a_sheet_imported_twice_without_a_cycle_is_included_twice() {
+        let mut loader = MemoryLoader::new();
+        loader.insert("site.css", "@import 'a.css'; @import 'b.css'; main { }");
+        loader.insert("a.css", "@import 'common.css'; a { }");
+        loader.insert("b.css", "@import 'common.css'; b { }");
+        loader.insert("common.css", "common { }");
+
+        assert_eq!(
+            selectors(&load(&loader, "site.css")),
+            ["common", "a", "common", "b", "main"]
+        );
+    }
+
+    #[test]
+    fn imported_layers_are_nested_in_the_import_layer_where_the_import_is() {
+        let mut loader = MemoryLoader::new();
+        loader.insert(
+            "site.css",
+            "@layer reset; @import 'theme.css' layer(theme); @layer base; main { }",
+        );
+        loader.insert(
+            "theme.css",
+            "@layer colors, fonts; @layer fonts { f { } } c { }",
+        );
+
+        let stylesheet = load(&loader, "site.css");
+        assert_eq!(
+            stylesheet.layers,
+            ["reset", "theme", "theme.colors", "theme.fonts", "base"]
+        );
+        let layers: Vec<Option<&str>> = stylesheet
+            .rules()
+            .iter()
+            .map(|rule| rule.layer.as_deref())
+            .collect();
+        assert_eq!(layers, [Some("theme.fonts"), Some("theme"), None]);
+    }
+
+    #[test]
+    fn an_imported_layer_declared_earlier_keeps_its_place() {
+        let mut loader = MemoryLoader::new();
+        loader.insert("site.css", "@layer b, a; @import 'layers.css';");
+        loader.insert("layers.css", "@layer a, b, c;");
+
+        assert_eq!(load(&loader, "site.css").layers, ["b", "a", "c"]);
+    }
+
+    #[test]
+    fn imports_of_missing_sheets_are_skipped() {
+        let mut loader = MemoryLoader::new();
+        loader.insert(
+            "site.css",
+            "@import 'missing.css'; @import 'a.css' print; main { }",
+        );
+        loader.insert("a.css", "a { }");
+
+        let stylesheet = load(&loader, "site.css");
+        assert_eq!(selectors(&stylesheet), ["a", "main"]);
+        assert_eq!(stylesheet.rules()[0].media.len(), 1);
+        assert!(stylesheet.rules()[1].media.is_empty());
+    }
+
+    #[test]
+    fn font_urls_are_relative_to_the_sheet_that_declares_them() {
+        let mut loader = MemoryLoader::new();
+        loader.insert("site.css", "@import 'css/fonts.css';");
+        loader.insert(
+            "css/fonts.css",
+            "@font-face { font-family: A; src: url(../fonts/a.woff2) format(woff2) }",
+        );
+
+        let stylesheet = load(&loader, "site.css");
+        assert_eq!(
+            stylesheet.font_faces[0].sources,
+            [FontSource::Url(
+                "fonts/a.woff2".to_string(),
+                vec!["woff2".to_string()]
+            )]
+        );
+    }
+}
@@ -0,0 +1,1198 @@
+//! Inline formatting: the inline boxes of an anonymous block are laid out into line boxes,
+//! breaking lines where `linebreak` finds break opportunities in their text, and the content
+//! of each line is put in visual order with the bidirectional algorithm of UAX #9, aligned
+//! and its text shaped into glyphs.
+
+use std::{iter, mem, ops::Range};
+
+use unicode_bidi::{Level, ParagraphBidiInfo};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+    boxmodel::{BoxType, Dimension, EdgeSize, Fragment, LayoutBox, Rect},
+    computed::{
+        Direction, LineBreak, LineHeight, OverflowWrap, TextAlign, TextTransform, UnicodeBidi,
+        WhiteSpace, WordBreak,
+    },
+    datatypes::Node,
+    fontdb::{Font, FontDatabase},
+    linebreak::{break_opportunities, BreakOpportunity, SOFT_HYPHEN},
+    properties::ComputedStyle,
+    shaping::{is_word_separator, space_glyphs, GlyphRun},
+};
+
+// Drawn at the end of a line that breaks at a soft hyphen.
+const HYPHEN: &str = "-";
+
+// The content of an inline formatting context, in document order, as found in the tree.
+// Boxes are referred to by their index in a preorder walk of the context.
+enum Content {
+    Start(usize),
+    End(usize),
+    // A run of the context's text, with white space processed, by byte range.
+    Text(usize, Range<usize>),
+}
+
+// The content split at the places where lines may break.
+enum InlineItem {
+    // Start and end of an inline element.
+    Start(usize),
+    End(usize),
+    // Text without spaces, break opportunities or changes of embedding level.
+    Text(usize, String, Level),
+    // A space in a text box, which hangs at the end of a line.
+    Space(usize, Level),
+    // A line may break here, or must if `mandatory`. Breaking at a soft hyphen shows a
+    // hyphen in the font of the text box in `hyphen`, at the level of the soft hyphen.
+    Break {
+        mandatory: bool,
+        hyphen: Option<(usize, Level)>,
+    },
+}
+
+// Items that have to stay on one line, and how the line may break after them.
+#[derive(Default)]
+struct Atom {
+    items: Vec<InlineItem>,
+    mandatory: bool,
+    hyphen: Option<(usize, Level)>,
+}
+
+// What line layout needs to know about each box of the context, and what it finds out.
+#[derive(Default)]
+struct InlineBox {
+    font: Font,
+    line_height: LineHeight,
+    letter_spacing: f32,
+    word_spacing: f32,
+    white_space: WhiteSpace,
+    word_break: WordBreak,
+    line_break: LineBreak,
+    // Whether text that is too wide for a line may break between any two characters.
+    wrap_anywhere: bool,
+    direction: Direction,
+    unicode_bidi: UnicodeBidi,
+    // The inline element this box is in, and the last of its descendants, so that this box
+    // and its descendants are the boxes `index..=last`.
+    parent: Option<usize>,
+    last: usize,
+    // The margins, borders and padding of an inline element. Only the horizontal ones take
+    // up room on the line.
+    edges: Dimension,
+    fragments: Vec<Fragment>,
+}
+
+impl InlineBox {
+    // The room the edges take up where the element starts and where it ends. These are on
+    // the left and on the right, the other way around for right-to-left elements.
+    fn start_edge(&self) -> f32 {
+        match self.direction {
+            Direction::Ltr => left_edge(&self.edges),
+            Direction::Rtl => right_edge(&self.edges),
+        }
+    }
+
+    fn end_edge(&self) -> f32 {
+        match self.direction {
+            Direction::Ltr => right_edge(&self.edges),
+            Direction::Rtl => left_edge(&self.edges),
+        }
+    }
+}
+
+fn left_edge(edges: &Dimension) -> f32 {
+    edges.margin.left + edges.border.left + edges.padding.left
+}
+
+fn right_edge(edges: &Dimension) -> f32 {
+    edges.padding.right + edges.border.right + edges.margin.right
+}
+
+// Something placed on the current line, in logical order.
+struct Unit {
+    index: usize,
+    kind: UnitKind,
+    width: f32,
+    level: Level,
+}
+
+enum UnitKind {
+    // The start and end edges of an inline element.
+    Start,
+    End,
+    Text(String),
+}
+
+struct LineBuilder<'f> {
+    fonts: &'f FontDatabase,
+    boxes: Vec<InlineBox>,
+    // Left edge and width of the lines, and top of the next line.
+    x: f32,
+    width: f32,
+    y: f32,
+    // The font and line height of the block, whose strut every line contains.
+    strut: InlineBox,
+    // Embedding level of the paragraph, which `start` and `end` alignment follow.
+    level: Level,
+    text_align: TextAlign,
+    // Room left empty at the start of the current line, for the `text-indent` of the first
+    // line of the block.
+    indent: f32,
+    // Width of what has been placed on the current line.
+    offset: f32,
+    empty: bool,
+    units: Vec<Unit>,
+    // Spaces after the last item, which only take up room if more content follows on the
+    // line.
+    spaces: Vec<(usize, Level)>,
+    // The soft hyphen the line may break at, after the last atom.
+    hyphen: Option<(usize, Level)>,
+}
+
+impl<'a> LayoutBox<'a> {
+    /// Lay out the inline boxes of an anonymous block into lines filling the width of the
+    /// containing block, below the content laid out so far. `style` is the style of the
+    /// block the anonymous block is in, which gives the strut of the lines, the base
+    /// direction and the alignment of the text. `first` is whether the anonymous block starts
+    /// the block, so that its first line is indented by `text-indent`.
+    pub fn layout_anonymous_block(
+        &mut self,
+        containing_block: Dimension,
+        style: &ComputedStyle,
+        fonts: &FontDatabase,
+        first: bool,
+    ) {
+        let d = &mut self.dimensions;
+        d.content.x = containing_block.content.x;
+        d.content.y = containing_block.content.y + containing_block.content.height;
+        d.content.width = containing_block.content.width;
+
+        let mut boxes = Vec::new();
+        let mut content = Vec::new();
+        let mut text = String::new();
+        for child in &self.children {
+            collect_content(
+                child,
+                d.content.width,
+                None,
+                fonts,
+                &mut boxes,
+                &mut content,
+                &mut text,
+            );
+        }
+        let (levels, level) = bidi_levels(&content, &text, &boxes, style);
+
+        let mut lines = LineBuilder {
+            fonts,
+            boxes,
+            x: d.content.x,
+            width: d.content.width,
+            y: d.content.y,
+            strut: InlineBox {
+                font: fonts.select(&style.font),
+                line_height: style.text.line_height,
+                ..Default::default()
+            },
+            level,
+            text_align: style.text.text_align,
+            indent: match first {
+                true => style.text.text_indent.resolve(d.content.width),
+                false => 0.0,
+            },
+            offset: 0.0,
+            empty: true,
+            units: Vec::new(),
+            spaces: Vec::new(),
+            hyphen: None,
+        };
+        for atom in atoms(inline_items(content, &text, &levels, &lines.boxes)) {
+            lines.place_atom(atom);
+        }
+        lines.finish_line(false);
+        d.content.height = lines.y - d.content.y;
+
+        let mut boxes = lines.boxes.into_iter();
+        for child in &mut self.children {
+            child.assign_fragments(&mut boxes);
+        }
+    }
+
+    // Store the fragments found for this box and its inline descendants, in the order
+    // `collect_content` visited them.
+    fn assign_fragments(&mut self, boxes: &mut impl Iterator<Item = InlineBox>) {
+        let inline_box = boxes.next().unwrap_or_default();
+        if !matches!(self.box_type, BoxType::InlineNode(_)) {
+            return;
+        }
+
+        self.dimensions = inline_box.edges;
+        self.dimensions.content = bounding_rect(&inline_box.fragments);
+        self.fragments = inline_box.fragments;
+        for child in &mut self.children {
+            child.assign_fragments(boxes);
+        }
+    }
+}
+
+// Append the content of `layout_box` and its descendants, and their text to `text`. `basis`
+// is the width percentages refer to, and `parent` the element the box is in. Unless
+// `white-space` preserves them, runs of white space collapse to a single space, which goes
+// away at the start of the context and next to newlines.
+fn collect_content(
+    layout_box: &LayoutBox,
+    basis: f32,
+    parent: Option<usize>,
+    fonts: &FontDatabase,
+    boxes: &mut Vec<InlineBox>,
+    content: &mut Vec<Content>,
+    text: &mut String,
+) {
+    let index = boxes.len();
+    let style_node = match layout_box.box_type {
+        BoxType::InlineNode(style_node) => style_node,
+        // Blocks inside inline elements are not laid out.
+        BoxType::BlockNode(_) | BoxType::AnonymousBlock => {
+            boxes.push(InlineBox {
+                parent,
+                last: index,
+                ..Default::default()
+            });
+            return;
+        }
+    };
+    let style = &style_node.style;
+    boxes.push(InlineBox {
+        font: fonts.select(&style.font),
+        line_height: style.text.line_height,
+        letter_spacing: style.text.letter_spacing.0,
+        word_spacing: style.text.word_spacing.0,
+        white_space: style.text.white_space,
+        word_break: style.text.word_break,
+        line_break: style.text.line_break,
+        wrap_anywhere: (style.text.overflow_wrap != OverflowWrap::Normal
+            || style.text.word_break == WordBreak::BreakWord)
+            && style.text.white_space.wraps(),
+        direction: style.text.direction,
+        unicode_bidi: style.bidi.unicode_bidi,
+        parent,
+        last: index,
+        ..Default::default()
+    });
+
+    match *style_node.node {
+        Node::Text(ref data) => {
+            let start = text.len();
+            let white_space = style.text.white_space;
+            for c in data.chars() {
+                match c {
+                    '\r' => {}
+                    '\n' if white_space.preserves_newlines() => {
+                        if white_space.collapses_spaces() && text.len() > start {
+                            text.truncate(text.trim_end_matches(' ').len().max(start));
+                        }
+                        text.push('\n');
+                    }
+                    c if !c.is_ascii_whitespace() => text.push(c),
+                    _ if white_space.collapses_spaces() => {
+                        if !text.is_empty() && !text.ends_with([' ', '\n']) {
+                            text.push(' ');
+                        }
+                    }
+                    // Tabs reach to the next multiple of eight columns.
+                    '\t' => {
+                        let column = text.rsplit('\n').next().unwrap_or("").chars().count();
+                        text.extend(iter::repeat_n(' ', 8 - column % 8));
+                    }
+                    _ => text.push(' '),
+                }
+            }
+            let previous = text[..start].chars().next_back();
+            let transformed = transform_text(&text[start..], style.text.text_transform, previous);
+            text.replace_range(start.., &transformed);
+            content.push(Content::Text(index, start..text.len()));
+        }
+        Node::Element(_) => {
+            let box_model = &style.box_model;
+            let edges = &mut boxes[index].edges;
+            edges.margin.left = box_model.margin_left.resolve(basis).unwrap_or(0.0);
+            edges.margin.right = box_model.margin_right.resolve(basis).unwrap_or(0.0);
+            edges.border = EdgeSize {
+                left: style.border.border_left_width,
+                right: style.border.border_right_width,
+                top: style.border.border_top_width,
+                bottom: style.border.border_bottom_width,
+            };
+            edges.padding = EdgeSize {
+                left: box_model.padding_left.resolve(basis),
+                right: box_model.padding_right.resolve(basis),
+                top: box_model.padding_top.resolve(basis),
+                bottom: box_model.padding_bottom.resolve(basis),
+            };
+
+            content.push(Content::Start(index));
+            for child in &layout_box.children {
+                collect_content(child, basis, Some(index), fonts, boxes, content, text);
+            }
+            content.push(Content::End(index));
+            boxes[index].last = boxes.len() - 1;
+        }
+    }
+}
+
+// The text of a text box as `text-transform` changes it. `previous` is the character before
+// it in the context, which tells whether it starts in the middle of a word.
+fn transform_text(text: &str, transform: TextTransform, mut previous: Option<char>) -> String {
+    match transform {
+        TextTransform::None => text.to_string(),
+        TextTransform::Uppercase => text.to_uppercase(),
+        TextTransform::Lowercase => text.to_lowercase(),
+        TextTransform::Capitalize => {
+            let mut capitalized = String::with_capacity(text.len());
+            for c in text.chars() {
+                match previous.is_some_and(|p| p.is_alphanumeric() || p == '\'' || p == '’') {
+                    true => capitalized.push(c),
+                    false => capitalized.extend(c.to_uppercase()),
+                }
+                previous = Some(c);
+            }
+            capitalized
+        }
+        // Printable ASCII has full-width forms at U+FF01 to U+FF5E, and the space at U+3000.
+        TextTransform::FullWidth => text
+            .chars()
+            .map(|c| match c {
+                ' ' => '\u{3000}',
+                '!'..='~' => char::from_u32(c as u32 - 0x21 + 0xFF01).unwrap_or(c),
+                c => c,
+            })
+            .collect(),
+    }
+}
+
+// The embedding level of each byte of `text`, and the level of the paragraph, which is in a
+// block with style `style`. The algorithm runs on a copy of the text with the explicit
+// formatting characters that the `unicode-bidi` values of the elements stand for.
+fn bidi_levels(
+    content: &[Content],
+    text: &str,
+    boxes: &[InlineBox],
+    style: &ComputedStyle,
+) -> (Vec<Level>, Level) {
+    let direction = style.text.direction;
+    let level = match direction {
+        Direction::Ltr => Level::ltr(),
+        Direction::Rtl => Level::rtl(),
+    };
+    if text.is_empty() {
+        return (Vec::new(), level);
+    }
+
+    let mut marked = String::new();
+    // Byte ranges of the runs of text, and where each starts in `marked`.
+    let mut runs = Vec::new();
+    match style.bidi.unicode_bidi {
+        // The block itself never embeds or isolates, but it can override.
+        UnicodeBidi::BidiOverride | UnicodeBidi::IsolateOverride => {
+            marked.push_str(opening_marks(UnicodeBidi::BidiOverride, direction))
+        }
+        _ => {}
+    }
+    for content in content {
+        match *content {
+            Content::Start(index) => {
+                let inline_box = &boxes[index];
+                marked.push_str(opening_marks(inline_box.unicode_bidi, inline_box.direction));
+            }
+            Content::End(index) => marked.push_str(closing_marks(boxes[index].unicode_bidi)),
+            Content::Text(_, ref range) => {
+                runs.push((range.clone(), marked.len()));
+                marked.push_str(&text[range.clone()]);
+            }
+        }
+    }
+
+    // `plaintext` takes the direction from the first strong character instead.
+    let default_level = match style.bidi.unicode_bidi {
+        UnicodeBidi::Plaintext => None,
+        _ => Some(level),
+    };
+    let info = ParagraphBidiInfo::new(&marked, default_level);
+    let mut levels = Vec::with_capacity(text.len());
+    for (range, start) in runs {
+        levels.extend_from_slice(&info.levels[start..start + range.len()]);
+    }
+    (levels, info.paragraph_level)
+}
+
+// The formatting characters that open the embedding, isolation or override of an element.
+fn opening_marks(unicode_bidi: UnicodeBidi, direction: Direction) -> &'static str {
+    match (unicode_bidi, direction) {
+        (UnicodeBidi::Normal, _) => "",
+        // LRE and RLE
+        (UnicodeBidi::Embed, Direction::Ltr) => "\u{202A}",
+        (UnicodeBidi::Embed, Direction::Rtl) => "\u{202B}",
+        // LRI and RLI
+        (UnicodeBidi::Isolate, Direction::Ltr) => "\u{2066}",
+        (UnicodeBidi::Isolate, Direction::Rtl) => "\u{2067}",
+        // LRO and RLO
+        (UnicodeBidi::BidiOverride, Direction::Ltr) => "\u{202D}",
+        (UnicodeBidi::BidiOverride, Direction::Rtl) => "\u{202E}",
+        (UnicodeBidi::IsolateOverride, Direction::Ltr) => "\u{2066}\u{202D}",
+        (UnicodeBidi::IsolateOverride, Direction::Rtl) => "\u{2067}\u{202E}",
+        // FSI
+        (UnicodeBidi::Plaintext, _) => "\u{2068}",
+    }
+}
+
+// The formatting characters that close what `opening_marks` opened: PDF and PDI.
+fn closing_marks(unicode_bidi: UnicodeBidi) -> &'static str {
+    match unicode_bidi {
+        UnicodeBidi::Normal => "",
+        UnicodeBidi::Embed | UnicodeBidi::BidiOverride => "\u{202C}",
+        UnicodeBidi::Isolate | UnicodeBidi::Plaintext => "\u{2069}",
+        UnicodeBidi::IsolateOverride => "\u{202C}\u{2069}",
+    }
+}
+
+// Split the text of the content at its spaces, break opportunities and changes of embedding
+// level. Where a break falls between elements, the ones that end there stay before it and
+// the ones that start there go after it. Text that does not wrap only breaks at forced breaks,
+// which preserved newlines are.
+fn inline_items(
+    content: Vec<Content>,
+    text: &str,
+    levels: &[Level],
+    boxes: &[InlineBox],
+) -> Vec<InlineItem> {
+    // Text box of each run of text, by start offset.
+    let runs: Vec<(usize, usize)> = content
+        .iter()
+        .filter_map(|content| match *content {
+            Content::Text(index, ref range) if !range.is_empty() => Some((range.start, index)),
+            _ => None,
+        })
+        .collect();
+    let owner = |offset: usize| runs[runs.partition_point(|&(start, _)| start <= offset) - 1].1;
+    let breaks: Vec<_> = break_opportunities(text, |offset| {
+        let text_box = &boxes[owner(offset)];
+        (text_box.word_break, text_box.line_break)
+    })
+    .into_iter()
+    .filter(|&(at, opportunity)| {
+        opportunity == BreakOpportunity::Mandatory
+            || (boxes[owner(at - 1)].white_space.wraps() && boxes[owner(at)].white_space.wraps())
+    })
+    .collect();
+
+    let mut items = Vec::new();
+    // Add the breaks from `next` up to `offset`, that one included.
+    let push_breaks = |items: &mut Vec<InlineItem>, next: &mut usize, offset: usize| {
+        while let Some(&(at, opportunity)) = breaks.get(*next).filter(|&&(at, _)| at <= offset) {
+            *next += 1;
+            let hyphen = text[..at].ends_with(SOFT_HYPHEN).then(|| {
+                let offset = at - SOFT_HYPHEN.len_utf8();
+                (owner(offset), levels[offset])
+            });
+            items.push(InlineItem::Break {
+                mandatory: opportunity == BreakOpportunity::Mandatory,
+                hyphen,
+            });
+        }
+    };
+
+    let mut next = 0;
+    let mut offset = 0;
+    for content in content {
+        match content {
+            Content::Start(index) => {
+                push_breaks(&mut items, &mut next, offset);
+                items.push(InlineItem::Start(index));
+            }
+            Content::End(index) => items.push(InlineItem::End(index)),
+            Content::Text(index, range) => {
+                let mut start = range.start;
+                push_breaks(&mut items, &mut next, start);
+                while start < range.end {
+                    let stop = match breaks.get(next) {
+                        Some(&(at, _)) if at < range.end => at,
+                        _ => range.end,
+                    };
+                    let mut word = start;
+                    for (i, c) in text[start..stop].char_indices() {
+                        if c != ' ' && c != '\n' {
+                            continue;
+                        }
+                        push_words(&mut items, index, text, word..start + i, levels);
+                        word = start + i + 1;
+                        let level = levels[start + i];
+                        match c {
+                            // Spaces that take up room at the end of a line, which may break
+                            // after each of them.
+                            ' ' if boxes[index].white_space == WhiteSpace::BreakSpaces => {
+                                items.push(InlineItem::Text(index, " ".to_string(), level));
+                                items.push(InlineItem::Break {
+                                    mandatory: false,
+                                    hyphen: None,
+                                });
+                            }
+                            ' ' => items.push(InlineItem::Space(index, level)),
+                            // The forced break after a newline is among the breaks.
+                            _ => {}
+                        }
+                    }
+                    push_words(&mut items, index, text, word..stop, levels);
+                    if stop < range.end {
+                        push_breaks(&mut items, &mut next, stop);
+                    }
+                    start = stop;
+                }
+                offset = range.end;
+            }
+        }
+    }
+    items
+}
+
+// Add the text of `range`, without soft hyphens, as a text item for each run of the same
+// embedding level.
+fn push_words(
+    items: &mut Vec<InlineItem>,
+    index: usize,
+    text: &str,
+    range: Range<usize>,
+    levels: &[Level],
+) {
+    let mut push = |run: Range<usize>| {
+        let word = text[run.clone()].replace(SOFT_HYPHEN, "");
+        if !word.is_empty() {
+            items.push(InlineItem::Text(index, word, levels[run.start]));
+        }
+    };
+    let mut start = range.start;
+    for (i, _) in text[range.clone()].char_indices() {
+        if levels[range.start + i] != levels[start] {
+            push(start..range.start + i);
+            start = range.start + i;
+        }
+    }
+    push(start..range.end);
+}
+
+// Split the items at the break opportunities.
+fn atoms(items: Vec<InlineItem>) -> Vec<Atom> {
+    let mut atoms = Vec::new();
+    let mut atom = Atom::default();
+    for item in items {
+        match item {
+            InlineItem::Break { mandatory, hyphen } => {
+                atom.mandatory = mandatory;
+                atom.hyphen = hyphen;
+                atoms.push(mem::take(&mut atom));
+            }
+            item => atom.items.push(item),
+        }
+    }
+    if !atom.items.is_empty() {
+        atoms.push(atom);
+    }
+    atoms
+}
+
+impl LineBuilder<'_> {
+    fn place_atom(&mut self, atom: Atom) {
+        let spaces: f32 = self
+            .spaces
+            .iter()
+            .map(|&(index, _)| self.space_width(index))
+            .sum();
+        if !self.empty && self.offset + spaces + self.atom_width(&atom) > self.available() {
+            self.break_line();
+        }
+        atom.items.into_iter().for_each(|item| self.place(item));
+        self.hyphen = atom.hyphen;
+        if atom.mandatory {
+            // Even a line with nothing on it ends at a forced break.
+            self.empty = false;
+            self.finish_line(false);
+        }
+    }
+
+    // The room the atom takes up on the line. Spaces at its end hang past the end of the
+    // line if it breaks there.
+    fn atom_width(&self, atom: &Atom) -> f32 {
+        let last = atom
+            .items
+            .iter()
+            .rposition(|item| matches!(item, InlineItem::Start(_) | InlineItem::Text(..)));
+        atom.items
+            .iter()
+            .enumerate()
+            .filter(|&(i, item)| {
+                !matches!(item, InlineItem::Space(..)) || last.is_some_and(|last| i < last)
+            })
+            .map(|(_, item)| self.item_width(item))
+            .sum()
+    }
+
+    // The room for content on the current line.
+    fn available(&self) -> f32 {
+        self.width - self.indent
+    }
+
+    // The glyphs of `text` in box `index`, spaced by its letter and word spacing, with
+    // `expansion` more after each word separator to justify the line.
+    fn shape(&self, index: usize, text: &str, rtl: bool, expansion: f32) -> GlyphRun {
+        let inline_box = &self.boxes[index];
+        let mut run = self.fonts.shape(text, &inline_box.font, rtl);
+        space_glyphs(
+            &mut run,
+            inline_box.letter_spacing,
+            inline_box.word_spacing + expansion,
+        );
+        run
+    }
+
+    fn text_width(&self, index: usize, text: &str) -> f32 {
+        self.shape(index, text, false, 0.0).width
+    }
+
+    fn space_width(&self, index: usize) -> f32 {
+        let inline_box = &self.boxes[index];
+        self.fonts.advance(' ', &inline_box.font)
+            + inline_box.letter_spacing
+            + inline_box.word_spacing
+    }
+
+    fn item_width(&self, item: &InlineItem) -> f32 {
+        match *item {
+            InlineItem::Start(index) => self.boxes[index].start_edge(),
+            InlineItem::End(index) => self.boxes[index].end_edge(),
+            InlineItem::Text(index, ref text, _) => self.text_width(index, text),
+            InlineItem::Space(index, _) => self.space_width(index),
+            InlineItem::Break { .. } => 0.0,
+        }
+    }
+
+    fn place(&mut self, item: InlineItem) {
+        let width = self.item_width(&item);
+        match item {
+            InlineItem::Start(index) => {
+                self.place_spaces();
+                self.place_edge(index, UnitKind::Start, width);
+            }
+            InlineItem::End(index) => self.place_edge(index, UnitKind::End, width),
+            InlineItem::Text(index, text, level) => {
+                self.place_spaces();
+                if self.boxes[index].wrap_anywhere && self.offset + width > self.available() {
+                    // Too long for any line, so break it wherever it overflows.
+                    for grapheme in text.graphemes(true) {
+                        let width = self.text_width(index, grapheme);
+                        if !self.empty && self.offset + width > self.available() {
+                            self.finish_line(true);
+                        }
+                        self.place_text(index, grapheme, width, level);
+                    }
+                } else {
+                    self.place_text(index, &text, width, level);
+                }
+            }
+            InlineItem::Space(index, level) => self.spaces.push((index, level)),
+            InlineItem::Break { .. } => {}
+        }
+    }
+
+    // Place an edge of an element. Its level depends on the content next to it, which is
+    // not known until the line is finished.
+    fn place_edge(&mut self, index: usize, kind: UnitKind, width: f32) {
+        self.units.push(Unit {
+            index,
+            kind,
+            width,
+            level: self.level,
+        });
+        self.offset += width;
+        self.empty = false;
+    }
+
+    fn place_text(&mut self, index: usize, text: &str, width: f32, level: Level) {
+        match self.units.last_mut() {
+            Some(Unit {
+                index: last,
+                kind: UnitKind::Text(last_text),
+                width: last_width,
+                level: last_level,
+            }) if *last == index && *last_level == level => {
+                last_text.push_str(text);
+                *last_width += width;
+            }
+            _ => self.units.push(Unit {
+                index,
+                kind: UnitKind::Text(text.to_string()),
+                width,
+                level,
+            }),
+        }
+        self.offset += width;
+        self.empty = false;
+    }
+
+    // Place the spaces before content that follows them on the line.
+    fn place_spaces(&mut self) {
+        for (index, level) in mem::take(&mut self.spaces) {
+            let width = self.space_width(index);
+            self.place_text(index, " ", width, level);
+        }
+    }
+
+    // End the line at the last break opportunity, with a hyphen if it is a soft hyphen.
+    fn break_line(&mut self) {
+        if let Some((index, level)) = self.hyphen.take() {
+            let width = self.text_width(index, HYPHEN);
+            self.place_text(index, HYPHEN, width, level);
+        }
+        self.finish_line(true);
+    }
+
+    // Space above and below the baseline that text of `inline_box` needs: its line height,
+    // with the leading split evenly between the two.
+    fn extents(&self, inline_box: &InlineBox) -> (f32, f32) {
+        let font = &inline_box.font;
+        let ascent = self.fonts.ascent(font);
+        let descent = self.fonts.descent(font);
+        let line_height = inline_box
+            .line_height
+            .resolve(font.size, self.fonts.line_height(font));
+        let half_leading = (line_height - ascent - descent) / 2.0;
+        (ascent + half_leading, descent + half_leading)
+    }
+
+    // Whether box `index` is `element` or inside it.
+    fn contains(&self, element: usize, index: usize) -> bool {
+        (element..=self.boxes[element].last).contains(&index)
+    }
+
+    // Give the edges of each element the level of the text next to them inside the element,
+    // so they are reordered along with it, or the level below that if it is not in the
+    // direction of the element, so they stay on its start and end sides. Text on the other
+    // side of the edge at the same level or higher is in the same run, which a lower edge
+    // would split in two, so then the edge keeps the level of the text. Edges of elements
+    // without text on the line stay at the level of the paragraph.
+    fn resolve_edge_levels(&self, units: &mut [Unit]) {
+        for i in 0..units.len() {
+            let index = units[i].index;
+            let text = |inside: bool| {
+                move |unit: &&Unit| {
+                    matches!(unit.kind, UnitKind::Text(_))
+                        && self.contains(index, unit.index) == inside
+                }
+            };
+            let (inner, outer) = match units[i].kind {
+                UnitKind::Start => (
+                    units[i + 1..].iter().find(text(true)),
+                    units[..i].iter().rev().find(text(false)),
+                ),
+                UnitKind::End => (
+                    units[..i].iter().rev().find(text(true)),
+                    units[i + 1..].iter().find(text(false)),
+                ),
+                UnitKind::Text(_) => continue,
+            };
+            let level = inner.map_or(self.level, |text| text.level);
+            let rtl = self.boxes[index].direction == Direction::Rtl;
+            let split = outer.is_some_and(|text| text.level >= level);
+            units[i].level = match Level::new(level.number().wrapping_sub(1)) {
+                Ok(lower) if level.is_rtl() != rtl && !split => lower,
+                _ => level,
+            };
+        }
+    }
+
+    // End the current line. `wrapped` is whether it ends because the content goes on past its
+    // end, rather than at a forced break or the end of the paragraph; only such lines are
+    // justified.
+    fn finish_line(&mut self, wrapped: bool) {
+        self.spaces.clear();
+        self.hyphen = None;
+        if self.empty {
+            return;
+        }
+        let mut units = mem::take(&mut self.units);
+        self.resolve_edge_levels(&mut units);
+
+        // Room left on the line, which a justified line shares out among its word separators
+        // and other lines are aligned in.
+        let indent = mem::take(&mut self.indent);
+        let mut free = self.width - indent - self.offset;
+        let mut expansion = 0.0;
+        if wrapped && self.text_align == TextAlign::Justify && free > 0.0 {
+            let separators = |unit: &Unit| match unit.kind {
+                UnitKind::Text(ref text) => text.chars().filter(|&c| is_word_separator(c)).count(),
+                UnitKind::Start | UnitKind::End => 0,
+            };
+            let count: usize = units.iter().map(separators).sum();
+            if count > 0 {
+                expansion = free / count as f32;
+                for unit in &mut units {
+                    unit.width += expansion * separators(unit) as f32;
+                }
+                free = 0.0;
+            }
+        }
+        let rtl = self.level.is_rtl();
+        let shift = match (self.text_align, rtl) {
+            (TextAlign::Left, _) | (TextAlign::Start | TextAlign::Justify, false) => 0.0,
+            (TextAlign::End, true) => 0.0,
+            (TextAlign::Right, _) | (TextAlign::Start | TextAlign::Justify, true) => free,
+            (TextAlign::End, false) => free,
+            (TextAlign::Center, _) => free / 2.0,
+        };
+
+        // Left and right of each unit once the line is in visual order. The indent is on the
+        // start side of the paragraph.
+        let levels: Vec<Level> = units.iter().map(|unit| unit.level).collect();
+        let mut x = self.x + shift + if rtl { 0.0 } else { indent };
+        let mut spans = vec![(0.0, 0.0); units.len()];
+        for i in ParagraphBidiInfo::reorder_visual(&levels) {
+            spans[i] = (x, x + units[i].width);
+            x += units[i].width;
+        }
+
+        // The elements with content on the line, and the elements around them.
+        let mut elements = Vec::new();
+        for unit in &units {
+            let mut next = match unit.kind {
+                UnitKind::Text(_) => self.boxes[unit.index].parent,
+                UnitKind::Start | UnitKind::End => Some(unit.index),
+            };
+            while let Some(index) = next {
+                elements.push(index);
+                next = self.boxes[index].parent;
+            }
+        }
+        elements.sort_unstable();
+        elements.dedup();
+
+        // Everything on the line sits on the same baseline.
+        let (mut above, mut below) = self.extents(&self.strut);
+        for &index in units.iter().map(|unit| &unit.index).chain(&elements) {
+            let (ascent, descent) = self.extents(&self.boxes[index]);
+            above = above.max(ascent);
+            below = below.max(descent);
+        }
+
+        // The content area of an element spans its content on the line, between its edges.
+        let mut fragments = Vec::new();
+        for &element in &elements {
+            let ltr = self.boxes[element].direction == Direction::Ltr;
+            let (mut left, mut right) = (f32::INFINITY, f32::NEG_INFINITY);
+            let mut edge = None;
+            for (unit, &(x0, x1)) in units.iter().zip(&spans) {
+                if unit.index == element {
+                    // The side of the edge that faces the content.
+                    let facing = match unit.kind {
+                        UnitKind::Start if ltr => x1,
+                        UnitKind::End if !ltr => x1,
+                        _ => x0,
+                    };
+                    edge.get_or_insert(facing);
+                } else if self.contains(element, unit.index) {
+                    left = left.min(x0);
+                    right = right.max(x1);
+                }
+            }
+            if left > right {
+                (left, right) = (edge.unwrap_or(0.0), edge.unwrap_or(0.0));
+            }
+            fragments.push((element, left, right, None));
+        }
+        for (unit, &(left, right)) in units.into_iter().zip(&spans) {
+            if let UnitKind::Text(text) = unit.kind {
+                let run = self.shape(unit.index, &text, unit.level.is_rtl(), expansion);
+                fragments.push((unit.index, left, right, Some(run)));
+            }
+        }
+
+        for (index, left, right, text) in fragments {
+            let inline_box = &mut self.boxes[index];
+            let ascent = self.fonts.ascent(&inline_box.font);
+            let descent = self.fonts.descent(&inline_box.font);
+            inline_box.fragments.push(Fragment {
+                rect: Rect {
+                    x: left,
+                    y: self.y + above - ascent,
+                    width: right - left,
+                    height: ascent + descent,
+                },
+                text,
+                baseline: ascent,
+                underline: self.fonts.underline(&inline_box.font),
+                strikeout: self.fonts.strikeout(&inline_box.font),
+            });
+        }
+
+        self.y += above + below;
+        self.offset = 0.0;
+        self.empty = true;
+    }
+}
+
+fn bounding_rect(fragments: &[Fragment]) -> Rect {
+    let mut rects = fragments.iter().map(|fragment| fragment.rect);
+    let first = match rects.next() {
+        Some(rect) => rect,
+        None => return Rect::default(),
+    };
+    rects.fold(first, |a, b| {
+        let x = a.x.min(b.x);
+        let y = a.y.min(b.y);
+        Rect {
+            x,
+            y,
+            width: (a.x + a.width).max(b.x + b.width) - x,
+            height: (a.y + a.height).max(b.y + b.height) - y,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        boxmodel::{layout_tree, LayoutBox},
+        computed::FontStyle,
+        css::parse_css,
+        datatypes::Node,
+        dom::parse_element,
+        font::FixedMetrics,
+        fontdb::FontDatabase,
+        media::Viewport,
+        styles::style_tree,
+        tokenizer::Tokenizer,
+    };
+
+    // The text fragments of `body` laid out `width` pixels wide, as their text, left, top and
+    // width. Every character of the font is 8 pixels wide and lines are 16 pixels high.
+    fn layout(body: &str, css: &str, width: f32) -> Vec<(String, f32, f32, f32)> {
+        let html = format!("<html><body>{}</body></html>", body);
+        let mut tokenizer = Tokenizer::new(&html);
+        let mut tokens = Vec::new();
+        while let Some(token) = tokenizer.next_token() {
+            tokens.push(token);
+        }
+        let root = Node::Element(parse_element(&tokens).0);
+        let stylesheets = [parse_css(format!("body, p {{ margin: 0px }} {}", css))];
+        let style = style_tree(&root, &stylesheets, &Viewport::new(width, 600.0));
+        let mut fonts = FontDatabase::new();
+        let metrics = Box::new(FixedMetrics::default());
+        fonts.add_face("Fixed", 400, FontStyle::Normal, metrics);
+
+        let mut fragments = Vec::new();
+        collect_text(&layout_tree(width, 600.0, &style, &fonts), &mut fragments);
+        fragments
+    }
+
+    fn collect_text(layout_box: &LayoutBox, fragments: &mut Vec<(String, f32, f32, f32)>) {
+        for fragment in &layout_box.fragments {
+            if let Some(ref run) = fragment.text {
+                let rect = fragment.rect;
+                let (x, y, width) = (rect.x.round(), rect.y.round(), rect.width.round());
+                fragments.push((run.text.clone(), x, y, width));
+            }
+        }
+        for child in &layout_box.children {
+            collect_text(child, fragments);
+        }
+    }
+
+    fn fragment(text: &str, x: f32, y: f32, width: f32) -> (String, f32, f32, f32) {
+        (text.to_string(), x, y, width)
+    }
+
+    #[test]
+    fn lines_break_between_words_that_do_not_fit() {
+        assert_eq!(
+            layout("<p>aaa bbb ccc ddd</p>", "", 80.0),
+            [
+                fragment("aaa bbb", 0.0, 0.0, 56.0),
+                fragment("ccc ddd", 0.0, 16.0, 56.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn words_too_wide_for_a_line_only_break_if_they_may_wrap_anywhere() {
+        assert_eq!(
+            layout("<p>aaaaaaaaaaaa</p>", "", 80.0),
+            [fragment("aaaaaaaaaaaa", 0.0, 0.0, 96.0)]
+        );
+        assert_eq!(
+            layout("<p>aaaaaaaaaaaa</p>", "p { overflow-wrap: anywhere }", 80.0),
+            [
+                fragment("aaaaaaaaaa", 0.0, 0.0, 80.0),
+                fragment("aa", 0.0, 16.0, 16.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_line_breaking_at_a_soft_hyphen_ends_with_a_hyphen() {
+        assert_eq!(
+            layout("<p>hyphen\u{AD}ation</p>", "", 80.0),
+            [
+                fragment("hyphen-", 0.0, 0.0, 56.0),
+                fragment("ation", 0.0, 16.0, 40.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn inline_elements_continue_the_line() {
+        assert_eq!(
+            layout("<p>ab <em>cd</em> ef</p>", "", 80.0),
+            [
+                fragment("ab ", 0.0, 0.0, 24.0),
+                fragment("cd", 24.0, 0.0, 16.0),
+                fragment(" ef", 40.0, 0.0, 24.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn lines_are_aligned_in_the_room_left() {
+        let aligned = |text_align| {
+            let css = format!("p {{ text-align: {} }}", text_align);
+            layout("<p>ab cd</p>", &css, 80.0)[0].1
+        };
+        assert_eq!(aligned("left"), 0.0);
+        assert_eq!(aligned("center"), 20.0);
+        assert_eq!(aligned("right"), 40.0);
+        assert_eq!(aligned("end"), 40.0);
+    }
+
+    #[test]
+    fn justified_lines_widen_the_spaces_except_on_the_last_line() {
+        let fragments = layout("<p>aaa bb cc ddd</p>", "p { text-align: justify }", 80.0);
+        assert_eq!(
+            fragments,
+            [
+                fragment("aaa bb cc", 0.0, 0.0, 80.0),
+                fragment("ddd", 0.0, 16.0, 24.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn the_first_line_is_indented() {
+        assert_eq!(
+            layout("<p>aaa bbb ccc</p>", "p { text-indent: 24px }", 80.0),
+            [
+                fragment("aaa bbb", 24.0, 0.0, 56.0),
+                fragment("ccc", 0.0, 16.0, 24.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn letter_spacing_follows_every_character() {
+        assert_eq!(
+            layout("<p>ab cd</p>", "p { letter-spacing: 2px }", 80.0),
+            [fragment("ab cd", 0.0, 0.0, 50.0)]
+        );
+    }
+
+    #[test]
+    fn right_to_left_lines_start_on_the_right() {
+        assert_eq!(
+            layout("<p dir=\"rtl\">aaa bbb ccc</p>", "", 80.0),
+            [
+                fragment("aaa bbb", 24.0, 0.0, 56.0),
+                fragment("ccc", 56.0, 16.0, 24.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn text_in_the_other_direction_is_reversed() {
+        assert_eq!(
+            layout("<p dir=\"rtl\">ab \u{5d0}\u{5d1} cd</p>", "", 120.0),
+            [
+                fragment("ab", 104.0, 0.0, 16.0),
+                fragment(" \u{5d0}\u{5d1} ", 72.0, 0.0, 32.0),
+                fragment("cd", 56.0, 0.0, 16.0),
+            ]
+        );
+        // Elements in the middle of a run do not split it.
+        assert_eq!(
+            layout(
+                "<p>ab \u{5d0}\u{5d1} <em>\u{5d2}\u{5d3}</em> cd</p>",
+                "",
+                120.0
+            ),
+            [
+                fragment("ab ", 0.0, 0.0, 24.0),
+                fragment("\u{5d0}\u{5d1} ", 40.0, 0.0, 24.0),
+                fragment("\u{5d2}\u{5d3}", 24.0, 0.0, 16.0),
+                fragment(" cd", 64.0, 0.0, 24.0),
+            ]
+        );
+        assert_eq!(
+            layout(
+                "<p dir=\"rtl\">ab <em>cd</em> \u{5d0}\u{5d1}</p>",
+                "",
+                120.0
+            ),
+            [
+                fragment("ab ", 80.0, 0.0, 24.0),
+                fragment("cd", 104.0, 0.0, 16.0),
+                fragment(" \u{5d0}\u{5d1}", 56.0, 0.0, 24.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn the_edges_of_elements_stay_on_their_sides() {
+        let css = "em { padding: 0px 4px }";
+        assert_eq!(
+            layout("<p>ab <em>\u{5d0}\u{5d1}</em> cd</p>", css, 120.0),
+            [
+                fragment("ab ", 0.0, 0.0, 24.0),
+                fragment("\u{5d0}\u{5d1}", 28.0, 0.0, 16.0),
+                fragment(" cd", 48.0, 0.0, 24.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn bdo_overrides_the_direction_of_its_characters() {
+        assert_eq!(
+            layout(
+                "<p>x <bdo dir=\"rtl\">ab <em>cd</em></bdo> y</p>",
+                "",
+                120.0
+            ),
+            [
+                fragment("x ", 0.0, 0.0, 16.0),
+                fragment("ab ", 32.0, 0.0, 24.0),
+                fragment("cd", 16.0, 0.0, 16.0),
+                fragment(" y", 56.0, 0.0, 16.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn over_constrained_blocks_give_way_at_their_end_margin() {
+        let css = "div { width: 40px; margin: 0px 10px }";
+        assert_eq!(
+            layout("<div>abcde</div>", css, 120.0),
+            [fragment("abcde", 10.0, 0.0, 40.0)]
+        );
+        assert_eq!(
+            layout(
+                "<section dir=\"rtl\"><div>abcde</div></section>",
+                css,
+                120.0
+            ),
+            [fragment("abcde", 70.0, 0.0, 40.0)]
+        );
+    }
+}
@@ -1,11 +1,30 @@
 pub mod boxmodel;
+pub mod calc;
+pub mod computed;
+pub mod content;
 pub mod css;
+pub mod cssom;
 pub mod datatypes;
 pub mod display;
+pub mod document;
 pub mod dom;
+pub mod font;
+pub mod fontdb;
+pub mod import;
+pub mod inline;
+pub mod linebreak;
+pub mod loader;
+pub mod matching;
+pub mod media;
+pub mod properties;
+pub mod shaping;
+pub mod sharing;
 pub mod styles;
 pub mod tokenizer;
+pub mod useragent;
+pub mod variables;
 pub mod window;
+pub mod woff;
 
 use datatypes::Element;
 use datatypes::Node;
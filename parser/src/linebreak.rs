@@ -0,0 +1,129 @@
+//! Where lines of text may break: the line breaking algorithm of UAX #14, adjusted for the
+//! `word-break` and `line-break` properties with the grapheme clusters and word boundaries of
+//! UAX #29.
+
+use std::collections::{BTreeMap, HashSet};
+
+pub use unicode_linebreak::BreakOpportunity;
+use unicode_linebreak::{break_property, linebreaks, BreakClass};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::computed::{LineBreak, WordBreak};
+
+/// Marks a place where a word may be hyphenated. It is only drawn, as a hyphen, if the line
+/// breaks there.
+pub const SOFT_HYPHEN: char = '\u{AD}';
+
+/// Positions in `text` where a line may or must break, as byte offsets of the first character
+/// after the break, in order. `rules` gives the `word-break` and `line-break` values for the
+/// character at a byte offset. The end of the text is not a break.
+///
+/// Breaks only ever fall between grapheme clusters, so combining marks stay with their base.
+pub fn break_opportunities(
+    text: &str,
+    rules: impl Fn(usize) -> (WordBreak, LineBreak),
+) -> Vec<(usize, BreakOpportunity)> {
+    let mut opportunities: BTreeMap<usize, BreakOpportunity> =
+        linebreaks(text).filter(|&(i, _)| i < text.len()).collect();
+    let word_bounds: HashSet<usize> = text.split_word_bound_indices().map(|(i, _)| i).collect();
+    let graphemes: HashSet<usize> = text.grapheme_indices(true).map(|(i, _)| i).collect();
+    opportunities.retain(|i, _| graphemes.contains(i));
+
+    for (i, grapheme) in text.grapheme_indices(true).skip(1) {
+        if opportunities.get(&i) == Some(&BreakOpportunity::Mandatory) {
+            continue;
+        }
+        let before = text[..i].chars().next_back().unwrap();
+        let after = grapheme.chars().next().unwrap();
+        let (word_break, line_break) = rules(i - before.len_utf8());
+
+        let allowed = match line_break {
+            LineBreak::Anywhere => true,
+            _ if word_break == WordBreak::KeepAll
+                && before.is_alphanumeric()
+                && after.is_alphanumeric() =>
+            {
+                false
+            }
+            _ if word_break == WordBreak::BreakAll && !word_bounds.contains(&i) => true,
+            _ if before.is_whitespace() => opportunities.contains_key(&i),
+            // UAX #14 never breaks before small kana and the prolonged sound mark, which is
+            // the strict behavior.
+            LineBreak::Auto | LineBreak::Normal | LineBreak::Loose
+                if break_property(after as u32) == BreakClass::ConditionalJapaneseStarter =>
+            {
+                true
+            }
+            LineBreak::Loose if is_loose_break_before(after) => true,
+            _ => opportunities.contains_key(&i),
+        };
+
+        if allowed {
+            opportunities.insert(i, BreakOpportunity::Allowed);
+        } else {
+            opportunities.remove(&i);
+        }
+    }
+    opportunities.into_iter().collect()
+}
+
+// Characters that `line-break: loose` allows a break before: iteration marks, the katakana
+// middle dot and the inseparable ellipses.
+fn is_loose_break_before(c: char) -> bool {
+    matches!(
+        c,
+        '\u{3005}'
+            | '\u{303B}'
+            | '\u{309D}'
+            | '\u{309E}'
+            | '\u{30FD}'
+            | '\u{30FE}'
+            | '\u{30FB}'
+            | '\u{2025}'
+            | '\u{2026}'
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaks(text: &str, word_break: WordBreak, line_break: LineBreak) -> Vec<usize> {
+        break_opportunities(text, |_| (word_break, line_break))
+            .into_iter()
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    #[test]
+    fn lines_break_after_spaces_and_hyphens() {
+        assert_eq!(breaks("one two", WordBreak::Normal, LineBreak::Auto), [4]);
+        assert_eq!(
+            breaks("well-known", WordBreak::Normal, LineBreak::Auto),
+            [5]
+        );
+        assert!(breaks("word", WordBreak::Normal, LineBreak::Auto).is_empty());
+    }
+
+    #[test]
+    fn newlines_are_mandatory_breaks() {
+        let opportunities = break_opportunities("a\nb", |_| (WordBreak::Normal, LineBreak::Auto));
+        assert_eq!(opportunities, [(2, BreakOpportunity::Mandatory)]);
+    }
+
+    #[test]
+    fn break_all_breaks_inside_words_and_keep_all_does_not_break_cjk() {
+        assert_eq!(breaks("abc", WordBreak::BreakAll, LineBreak::Auto), [1, 2]);
+        assert_eq!(breaks("漢字", WordBreak::Normal, LineBreak::Auto), [3]);
+        assert!(breaks("漢字", WordBreak::KeepAll, LineBreak::Auto).is_empty());
+    }
+
+    #[test]
+    fn line_break_anywhere_breaks_between_grapheme_clusters() {
+        // The combining acute accent stays with its `e`.
+        assert_eq!(
+            breaks("e\u{301}a", WordBreak::Normal, LineBreak::Anywhere),
+            [3]
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use std::{collections::HashMap, fs, path::PathBuf};
+
+/// Fetches the resources a document refers to, such as linked and imported stylesheets.
+///
+/// URLs are relative to the document, with `/` separated path segments; `resolve_url` turns
+/// a reference found in a resource into such a URL.
+pub trait ResourceLoader {
+    /// The contents of the resource at `url`, or `None` if it cannot be loaded.
+    fn load(&self, url: &str) -> Option<Vec<u8>>;
+
+    fn load_text(&self, url: &str) -> Option<String> {
+        self.load(url)
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+    }
+}
+
+/// Loads resources from the files under a root directory.
+pub struct FileLoader {
+    root: PathBuf,
+}
+
+impl FileLoader {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        FileLoader { root: root.into() }
+    }
+}
+
+impl ResourceLoader for FileLoader {
+    fn load(&self, url: &str) -> Option<Vec<u8>> {
+        fs::read(self.root.join(url.trim_start_matches('/'))).ok()
+    }
+}
+
+/// Serves resources from memory, e.g. to style a document without touching the filesystem.
+#[derive(Default)]
+pub struct MemoryLoader {
+    resources: HashMap<String, Vec<u8>>,
+}
+
+impl MemoryLoader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, url: &str, contents: impl Into<Vec<u8>>) {
+        self.resources.insert(resolve_url("", url), contents.into());
+    }
+}
+
+impl ResourceLoader for MemoryLoader {
+    fn load(&self, url: &str) -> Option<Vec<u8>> {
+        self.resources.get(url).cloned()
+    }
+}
+
+/// Resolve `reference`, found in the resource at `base`, to a URL relative to the document.
+/// Absolute references such as `/css/site.css` start from the document's root instead.
+pub fn resolve_url(base: &str, reference: &str) -> String {
+    let mut segments: Vec<&str> = Vec::new();
+    if !reference.starts_with('/') {
+        // Drop the file name of the base to get its directory.
+        segments.extend(base.split('/').filter(|s| !s.is_empty()));
+        segments.pop();
+    }
+    for segment in reference.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    segments.join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_references_resolve_against_the_directory_of_the_base() {
+        assert_eq!(resolve_url("css/site.css", "base.css"), "css/base.css");
+        assert_eq!(
+            resolve_url("css/site.css", "./theme/dark.css"),
+            "css/theme/dark.css"
+        );
+        assert_eq!(
+            resolve_url("css/site.css", "../fonts/a.woff2"),
+            "fonts/a.woff2"
+        );
+        assert_eq!(resolve_url("site.css", "base.css"), "base.css");
+        assert_eq!(resolve_url("", "css/base.css"), "css/base.css");
+    }
+
+    #[test]
+    fn absolute_references_resolve_against_the_root() {
+        assert_eq!(resolve_url("css/deep/site.css", "/base.css"), "base.css");
+        assert_eq!(
+            resolve_url("css/site.css", "/css/../fonts/a.ttf"),
+            "fonts/a.ttf"
+        );
+    }
+
+    #[test]
+    fn references_cannot_leave_the_root() {
+        assert_eq!(resolve_url("css/site.css", "../../../base.css"), "base.css");
+    }
+
+    #[test]
+    fn memory_loader_serves_what_was_inserted() {
+        let mut loader = MemoryLoader::new();
+        loader.insert("/css/site.css", "p { color: red }");
+        loader.insert("binary.bin", vec![0xff, 0xfe]);
+
+        assert_eq!(
+            loader.load_text("css/site.css").as_deref(),
+            Some("p { color: red }")
+        );
+        assert_eq!(loader.load("css/missing.css"), None);
+        // Resources that are not UTF-8 cannot be loaded as text.
+        assert_eq!(loader.load("binary.bin"), Some(vec![0xff, 0xfe]));
+        assert_eq!(loader.load_text("binary.bin"), None);
+    }
+}
@@ -0,0 +1,512 @@
+use std::{cmp::Reverse, collections::HashMap};
+
+use crate::{
+    css::{
+        Combinator, PseudoClass, PseudoElement, Rule, Selector, SimpleSelector, Specificity,
+        Stylesheet,
+    },
+    datatypes::{Element, Node},
+    media::Viewport,
+};
+
+/// A rule matching an element, with the specificity of its most specific matching selector.
+pub type MatchedRule<'a> = (Specificity, &'a Stylesheet, &'a Rule);
+
+/// An element together with its place in the document, which combinators need to look at.
+#[derive(Clone, Copy)]
+pub struct ElementContext<'a> {
+    pub element: &'a Element,
+    pub parent: Option<&'a ElementContext<'a>>,
+    // The nodes before the element among its parent's children.
+    pub preceding_siblings: &'a [Node],
+}
+
+impl<'a> ElementContext<'a> {
+    pub fn root(element: &'a Element) -> ElementContext<'a> {
+        ElementContext {
+            element,
+            parent: None,
+            preceding_siblings: &[],
+        }
+    }
+
+    fn ancestors(&self) -> impl Iterator<Item = &'a ElementContext<'a>> {
+        std::iter::successors(self.parent, |context| context.parent)
+    }
+
+    // The element siblings before this element, closest first.
+    fn previous_siblings(&self) -> impl Iterator<Item = ElementContext<'a>> {
+        let parent = self.parent;
+        let siblings = self.preceding_siblings;
+        siblings
+            .iter()
+            .enumerate()
+            .rev()
+            .filter_map(move |(i, node)| match node {
+                Node::Element(element) => Some(ElementContext {
+                    element,
+                    parent,
+                    preceding_siblings: &siblings[..i],
+                }),
+                Node::Text(_) => None,
+            })
+    }
+
+    // The element siblings after this element. The preceding siblings are the start of the
+    // parent's children, so the rest of them follow the element.
+    fn next_siblings(&self) -> impl Iterator<Item = &'a Element> {
+        let following = match self.parent {
+            Some(parent) => &parent.element.children[self.preceding_siblings.len() + 1..],
+            None => &[],
+        };
+        following.iter().filter_map(|node| match node {
+            Node::Element(element) => Some(element),
+            Node::Text(_) => None,
+        })
+    }
+}
+
+/// Whether `selector` matches the element, or its pseudo-element `pseudo`.
+pub fn matches(
+    context: &ElementContext,
+    selector: &Selector,
+    pseudo: Option<PseudoElement>,
+) -> bool {
+    selector.subject().pseudo_element == pseudo && matches_complex(context, selector)
+}
+
+// Selectors are matched from right to left, starting with the element itself.
+fn matches_complex(context: &ElementContext, selector: &Selector) -> bool {
+    match *selector {
+        Selector::Simple(ref simple) => matches_compound(context, simple),
+        Selector::Combined(ref left, combinator, ref simple) => {
+            if !matches_compound(context, simple) {
+                return false;
+            }
+            match combinator {
+                Combinator::Child => context
+                    .parent
+                    .map_or(false, |parent| matches_complex(parent, left)),
+                Combinator::Descendant => context
+                    .ancestors()
+                    .any(|ancestor| matches_complex(ancestor, left)),
+                Combinator::NextSibling => context
+                    .previous_siblings()
+                    .next()
+                    .map_or(false, |sibling| matches_complex(&sibling, left)),
+                Combinator::SubsequentSibling => context
+                    .previous_siblings()
+                    .any(|sibling| matches_complex(&sibling, left)),
+            }
+        }
+    }
+}
+
+fn matches_compound(context: &ElementContext, selector: &SimpleSelector) -> bool {
+    let elem = context.element;
+    if selector.tag.iter().any(|name| elem.name != *name) {
+        return false;
+    }
+
+    if selector.id.iter().any(|id| elem.id() != Some(id)) {
+        return false;
+    }
+
+    let elem_classes = elem.classes();
+    if selector
+        .class
+        .iter()
+        .any(|class| !elem_classes.contains(&**class))
+    {
+        return false;
+    }
+
+    selector
+        .pseudo_classes
+        .iter()
+        .all(|pseudo_class| matches_pseudo_class(context, pseudo_class))
+}
+
+fn matches_pseudo_class(context: &ElementContext, pseudo_class: &PseudoClass) -> bool {
+    let elem = context.element;
+    let same_type = |sibling: &Element| sibling.name == elem.name;
+    match *pseudo_class {
+        PseudoClass::Root => context.parent.is_none(),
+        PseudoClass::Empty => elem.children.iter().all(|child| match child {
+            Node::Element(_) => false,
+            Node::Text(text) => text.is_empty(),
+        }),
+        PseudoClass::FirstChild => context.previous_siblings().next().is_none(),
+        PseudoClass::LastChild => context.next_siblings().next().is_none(),
+        PseudoClass::OnlyChild => {
+            context.previous_siblings().next().is_none() && context.next_siblings().next().is_none()
+        }
+        PseudoClass::FirstOfType => !context
+            .previous_siblings()
+            .any(|sibling| same_type(sibling.element)),
+        PseudoClass::LastOfType => !context.next_siblings().any(same_type),
+        PseudoClass::OnlyOfType => {
+            !context
+                .previous_siblings()
+                .any(|sibling| same_type(sibling.element))
+                && !context.next_siblings().any(same_type)
+        }
+        PseudoClass::Link => {
+            matches!(&*elem.name, "a" | "area") && elem.attributes.contains_key("href")
+        }
+        PseudoClass::Visited | PseudoClass::Hover | PseudoClass::Active | PseudoClass::Focus => {
+            false
+        }
+        PseudoClass::Not(ref selectors) => !selectors
+            .iter()
+            .any(|selector| matches_compound(context, selector)),
+    }
+}
+
+const FILTER_BITS: u32 = 12;
+const FILTER_SIZE: usize = 1 << FILTER_BITS;
+
+/// A counting Bloom filter of the tag names, ids and classes of the ancestors of the element
+/// being styled. A selector that needs an ancestor the filter has never seen cannot match,
+/// which rules out most descendant selectors without walking up the tree.
+///
+/// Push each element before styling its children and pop it afterwards.
+#[derive(Clone)]
+pub struct AncestorFilter {
+    counts: Box<[u8; FILTER_SIZE]>,
+}
+
+impl Default for AncestorFilter {
+    fn default() -> Self {
+        AncestorFilter {
+            counts: Box::new([0; FILTER_SIZE]),
+        }
+    }
+}
+
+impl AncestorFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, element: &Element) {
+        for hash in element_hashes(element) {
+            for index in filter_indices(hash) {
+                let count = &mut self.counts[index];
+                *count = count.saturating_add(1);
+            }
+        }
+    }
+
+    pub fn pop(&mut self, element: &Element) {
+        for hash in element_hashes(element) {
+            for index in filter_indices(hash) {
+                let count = &mut self.counts[index];
+                // A saturated count no longer knows how many elements set it, so it stays.
+                if *count != u8::MAX {
+                    *count -= 1;
+                }
+            }
+        }
+    }
+
+    fn might_contain(&self, hash: u32) -> bool {
+        filter_indices(hash)
+            .into_iter()
+            .all(|index| self.counts[index] != 0)
+    }
+}
+
+fn filter_indices(hash: u32) -> [usize; 2] {
+    let mask = FILTER_SIZE as u32 - 1;
+    [
+        (hash & mask) as usize,
+        ((hash >> FILTER_BITS) & mask) as usize,
+    ]
+}
+
+// Tag names, ids and classes are hashed with different prefixes so that `#a` and `.a` differ.
+fn name_hash(kind: u8, name: &str) -> u32 {
+    // 32-bit FNV-1a.
+    std::iter::once(kind)
+        .chain(name.bytes())
+        .fold(0x811c9dc5, |hash, byte| {
+            (hash ^ byte as u32).wrapping_mul(0x01000193)
+        })
+}
+
+fn element_hashes(element: &Element) -> Vec<u32> {
+    let mut hashes = vec![name_hash(b't', &element.name)];
+    hashes.extend(element.id().map(|id| name_hash(b'#', id)));
+    hashes.extend(element.classes().iter().map(|class| name_hash(b'.', class)));
+    hashes
+}
+
+fn compound_hashes(selector: &SimpleSelector) -> impl Iterator<Item = u32> + '_ {
+    let tag = selector.tag.iter().map(|tag| name_hash(b't', tag));
+    let id = selector.id.iter().map(|id| name_hash(b'#', id));
+    let classes = selector.class.iter().map(|class| name_hash(b'.', class));
+    tag.chain(id).chain(classes)
+}
+
+// The names that the ancestors of a matching element must have, from the compound selectors
+// left of descendant and child combinators. Past a sibling combinator the compounds apply to
+// siblings of an ancestor, which the filter knows nothing about.
+fn ancestor_hashes(selector: &Selector) -> Vec<u32> {
+    let mut hashes = Vec::new();
+    let mut current = selector;
+    while let Selector::Combined(ref left, combinator, _) = *current {
+        if let Combinator::NextSibling | Combinator::SubsequentSibling = combinator {
+            break;
+        }
+        hashes.extend(compound_hashes(left.subject()));
+        current = left;
+    }
+    hashes
+}
+
+// Whether the selector has sibling combinators or pseudo-classes, whose matching does not only
+// depend on the name, id and classes of the element and its ancestors.
+fn needs_revalidation(selector: &Selector) -> bool {
+    match *selector {
+        Selector::Simple(ref simple) => !simple.pseudo_classes.is_empty(),
+        Selector::Combined(_, Combinator::NextSibling | Combinator::SubsequentSibling, _) => true,
+        Selector::Combined(ref left, _, ref simple) => {
+            !simple.pseudo_classes.is_empty() || needs_revalidation(left)
+        }
+    }
+}
+
+struct RuleSelector<'a> {
+    stylesheet: &'a Stylesheet,
+    rule: &'a Rule,
+    selector: &'a Selector,
+    // Position of the rule among all rules of the cascade.
+    source_order: usize,
+    ancestor_hashes: Vec<u32>,
+}
+
+/// The rules of the stylesheets taking part in a cascade, bucketed by the id, class or tag
+/// name their rightmost compound selector requires, so that an element is only tested against
+/// the rules that might match it.
+#[derive(Default)]
+pub struct RuleMap<'a> {
+    by_id: HashMap<&'a str, Vec<RuleSelector<'a>>>,
+    by_class: HashMap<&'a str, Vec<RuleSelector<'a>>>,
+    by_tag: HashMap<&'a str, Vec<RuleSelector<'a>>>,
+    universal: Vec<RuleSelector<'a>>,
+    // Selectors with sibling combinators or pseudo-classes, whose matching does not only
+    // depend on the element and its ancestors, and the parts of them left of a combinator
+    // that have any.
+    sibling_selectors: Vec<&'a Selector>,
+}
+
+impl<'a> RuleMap<'a> {
+    /// Bucket the rules of `stylesheets` whose `@media` conditions match `viewport`.
+    pub fn new(stylesheets: &[&'a Stylesheet], viewport: &Viewport) -> RuleMap<'a> {
+        let mut map = RuleMap::default();
+        let rules = stylesheets.iter().flat_map(|&stylesheet| {
+            stylesheet
+                .rules()
+                .iter()
+                .map(move |rule| (stylesheet, rule))
+        });
+        for (source_order, (stylesheet, rule)) in rules.enumerate() {
+            if !rule.media.iter().all(|media| media.matches(viewport)) {
+                continue;
+            }
+            for selector in &rule.selector {
+                // What the left of a selector matches, e.g. `li:first-child` in
+                // `li:first-child a`, decides what its descendants and later siblings match.
+                let mut prefix = Some(selector);
+                while let Some(selector) = prefix.filter(|&selector| needs_revalidation(selector)) {
+                    map.sibling_selectors.push(selector);
+                    prefix = match *selector {
+                        Selector::Combined(ref left, _, _) => Some(&**left),
+                        Selector::Simple(_) => None,
+                    };
+                }
+                let subject = selector.subject();
+                let bucket = if let Some(ref id) = subject.id {
+                    map.by_id.entry(id).or_default()
+                } else if let Some(class) = subject.class.first() {
+                    map.by_class.entry(class).or_default()
+                } else if let Some(ref tag) = subject.tag {
+                    map.by_tag.entry(tag).or_default()
+                } else {
+                    &mut map.universal
+                };
+                bucket.push(RuleSelector {
+                    stylesheet,
+                    rule,
+                    selector,
+                    source_order,
+                    ancestor_hashes: ancestor_hashes(selector),
+                });
+            }
+        }
+        map
+    }
+
+    /// The rules matching the element, or its pseudo-element `pseudo`, in source order.
+    /// `filter` must hold the element's ancestors.
+    pub fn matching_rules(
+        &self,
+        context: &ElementContext,
+        filter: &AncestorFilter,
+        pseudo: Option<PseudoElement>,
+    ) -> Vec<MatchedRule<'a>> {
+        let element = context.element;
+        let classes = element.classes();
+        let candidates = element
+            .id()
+            .and_then(|id| self.by_id.get(&**id))
+            .into_iter()
+            .chain(classes.iter().filter_map(|class| self.by_class.get(class)))
+            .chain(self.by_tag.get(&*element.name))
+            .flatten()
+            .chain(&self.universal);
+
+        let mut matched: Vec<(usize, Specificity, &RuleSelector<'a>)> = candidates
+            .filter(|candidate| {
+                candidate
+                    .ancestor_hashes
+                    .iter()
+                    .all(|&hash| filter.might_contain(hash))
+                    && matches(context, candidate.selector, pseudo)
+            })
+            .map(|candidate| {
+                let specificity = candidate.selector.specificity();
+                (candidate.source_order, specificity, candidate)
+            })
+            .collect();
+
+        // A rule counts once, with its most specific matching selector.
+        matched.sort_by_key(|&(order, specificity, _)| (order, Reverse(specificity)));
+        matched.dedup_by_key(|&mut (order, _, _)| order);
+        matched
+            .into_iter()
+            .map(|(_, specificity, candidate)| (specificity, candidate.stylesheet, candidate.rule))
+            .collect()
+    }
+
+    /// Whether any rule has a selector with a sibling combinator or a pseudo-class, so that
+    /// changing an element may change which rules match its siblings or its parent.
+    pub fn has_sibling_selectors(&self) -> bool {
+        !self.sibling_selectors.is_empty()
+    }
+
+    /// Which of the selectors with sibling combinators or pseudo-classes match the element or
+    /// one of its pseudo-elements. Two elements with the same name, id, classes and parent style match
+    /// the same rules when these agree.
+    pub fn revalidate(&self, context: &ElementContext) -> Vec<bool> {
+        self.sibling_selectors
+            .iter()
+            .map(|selector| matches_complex(context, selector))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{css::parse_css, dom::parse_element, tokenizer::Tokenizer};
+
+    fn parse_page(html: &str) -> Node {
+        let mut tokenizer = Tokenizer::new(html);
+        let mut tokens = Vec::new();
+        while let Some(token) = tokenizer.next_token() {
+            tokens.push(token);
+        }
+        Node::Element(parse_element(&tokens).0)
+    }
+
+    // The rules matching the element, found by testing every selector of every rule.
+    fn naive_matching_rules<'a>(
+        context: &ElementContext,
+        stylesheet: &'a Stylesheet,
+    ) -> Vec<MatchedRule<'a>> {
+        stylesheet
+            .rules()
+            .iter()
+            .filter_map(|rule| {
+                rule.selector
+                    .iter()
+                    .filter(|selector| matches(context, selector, None))
+                    .map(Selector::specificity)
+                    .max()
+                    .map(|specificity| (specificity, stylesheet, rule))
+            })
+            .collect()
+    }
+
+    // Compare the two ways of matching on `node` and its descendants. Returns the number of
+    // rules matched.
+    fn compare(
+        node: &Node,
+        parent: Option<&ElementContext>,
+        preceding_siblings: &[Node],
+        filter: &mut AncestorFilter,
+        rules: &RuleMap,
+        stylesheet: &Stylesheet,
+    ) -> usize {
+        let Node::Element(ref element) = *node else {
+            return 0;
+        };
+        let context = ElementContext {
+            element,
+            parent,
+            preceding_siblings,
+        };
+        let matched = rules.matching_rules(&context, filter, None);
+        let expected = naive_matching_rules(&context, stylesheet);
+        assert_eq!(matched.len(), expected.len(), "Rules of {}", element.name);
+        for (&(a, _, rule_a), &(b, _, rule_b)) in matched.iter().zip(&expected) {
+            assert_eq!(a, b);
+            assert!(std::ptr::eq(rule_a, rule_b));
+        }
+
+        let mut count = matched.len();
+        filter.push(element);
+        for (i, child) in element.children.iter().enumerate() {
+            let siblings = &element.children[..i];
+            count += compare(child, Some(&context), siblings, filter, rules, stylesheet);
+        }
+        filter.pop(element);
+        count
+    }
+
+    #[test]
+    fn bucketed_matching_agrees_with_testing_every_rule() {
+        let root = parse_page(
+            "<html><body><div id=\"main\" class=\"box wide\"><ul class=\"list\">\
+             <li class=\"item\"><a class=\"link\">a</a></li><li class=\"item last\">\
+             <span>b</span></li></ul><p class=\"item\">c</p></div><p>d</p></body></html>",
+        );
+        let stylesheet = parse_css(
+            "#main { } .item { } li.item.last { } a { } * { } \
+             div .link { } .box > ul > li { } body > p { } .wide p { } \
+             #main li > span { } .missing a { } ul a, #nothing, p.item { } \
+             li + li span { } .box > * { } *.item.last { } span { } html * { }"
+                .to_string(),
+        );
+        let viewport = Viewport::new(800.0, 600.0);
+        let rules = RuleMap::new(&[&stylesheet], &viewport);
+        let mut filter = AncestorFilter::new();
+        let count = compare(&root, None, &[], &mut filter, &rules, &stylesheet);
+        assert!(count > 30);
+    }
+
+    #[test]
+    fn the_filter_forgets_popped_ancestors() {
+        let mut filter = AncestorFilter::new();
+        let mut div = Element::new("div".to_string());
+        div.set_attribute("class", "a b");
+        filter.push(&div);
+        assert!(filter.might_contain(name_hash(b'.', "a")));
+        assert!(filter.might_contain(name_hash(b't', "div")));
+        filter.pop(&div);
+        assert!(!filter.might_contain(name_hash(b'.', "a")));
+        assert!(!filter.might_contain(name_hash(b't', "div")));
+    }
+}
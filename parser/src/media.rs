@@ -0,0 +1,751 @@
+use std::fmt;
+
+/// The environment media queries are evaluated against.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Viewport {
+    pub width: f32,
+    pub height: f32,
+    // Device pixels per CSS pixel.
+    pub resolution: f32,
+    pub color_scheme: ColorScheme,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorScheme {
+    Light,
+    Dark,
+}
+
+impl Viewport {
+    pub fn new(width: f32, height: f32) -> Viewport {
+        Viewport {
+            width,
+            height,
+            resolution: 1.0,
+            color_scheme: ColorScheme::Light,
+        }
+    }
+}
+
+/// A comma separated list of media queries, which matches if any of them does. An empty list
+/// always matches.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaQueryList(pub Vec<MediaQuery>);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaQuery {
+    pub negated: bool,
+    // `None` stands for `all`.
+    pub media_type: Option<String>,
+    pub condition: Option<MediaCondition>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MediaCondition {
+    Feature(MediaFeature),
+    Not(Box<MediaCondition>),
+    And(Vec<MediaCondition>),
+    Or(Vec<MediaCondition>),
+    // Syntax that is valid but not understood, e.g. `(unknown-thing 1)`. It never matches.
+    Unknown(String),
+}
+
+/// A single media feature test. The `min-`/`max-` prefixes and the range syntax are all
+/// normalized to `name op value`, e.g. `(400px <= width)` becomes `width >= 400px`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MediaFeature {
+    Boolean(String),
+    Range {
+        name: String,
+        op: RangeOp,
+        value: MediaValue,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeOp {
+    Lt,
+    Le,
+    Eq,
+    Ge,
+    Gt,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MediaValue {
+    Number(f32),
+    // A length converted to pixels.
+    Length(f32),
+    Ratio(f32, f32),
+    // A resolution converted to dots per pixel.
+    Resolution(f32),
+    Ident(String),
+}
+
+/// Parse a media query list such as `screen and (min-width: 600px), print`. A query that
+/// fails to parse becomes `not all`, without affecting the others in the list.
+pub fn parse_media_query_list(input: &str) -> MediaQueryList {
+    let queries = split_top_level(input, ',')
+        .into_iter()
+        .filter(|query| !query.trim().is_empty() || input.contains(','))
+        .map(|query| {
+            let mut parser = MediaParser::new(query);
+            parser
+                .parse_media_query()
+                .filter(|_| parser.at_end())
+                .unwrap_or(MediaQuery {
+                    negated: true,
+                    media_type: None,
+                    condition: None,
+                })
+        })
+        .collect();
+    MediaQueryList(queries)
+}
+
+impl MediaQueryList {
+    pub fn matches(&self, viewport: &Viewport) -> bool {
+        self.0.is_empty() || self.0.iter().any(|query| query.matches(viewport))
+    }
+}
+
+impl MediaQuery {
+    pub fn matches(&self, viewport: &Viewport) -> bool {
+        let type_matches = match self.media_type.as_deref() {
+            None | Some("all") | Some("screen") => true,
+            Some(_) => false,
+        };
+        let condition_matches = match self.condition {
+            Some(ref condition) => condition.matches(viewport),
+            None => true,
+        };
+        (type_matches && condition_matches) != self.negated
+    }
+}
+
+impl MediaCondition {
+    pub fn matches(&self, viewport: &Viewport) -> bool {
+        match *self {
+            MediaCondition::Feature(ref feature) => feature.matches(viewport),
+            MediaCondition::Not(ref condition) => !condition.matches(viewport),
+            MediaCondition::And(ref conditions) => conditions.iter().all(|c| c.matches(viewport)),
+            MediaCondition::Or(ref conditions) => conditions.iter().any(|c| c.matches(viewport)),
+            MediaCondition::Unknown(_) => false,
+        }
+    }
+}
+
+// The value of a media feature for the viewport, or `None` if the feature is not supported.
+enum FeatureValue {
+    Number(f32),
+    Ident(&'static str),
+}
+
+fn feature_value(name: &str, viewport: &Viewport) -> Option<FeatureValue> {
+    Some(match name {
+        "width" | "device-width" => FeatureValue::Number(viewport.width),
+        "height" | "device-height" => FeatureValue::Number(viewport.height),
+        "aspect-ratio" | "device-aspect-ratio" => {
+            FeatureValue::Number(viewport.width / viewport.height)
+        }
+        "resolution" => FeatureValue::Number(viewport.resolution),
+        "orientation" if viewport.height >= viewport.width => FeatureValue::Ident("portrait"),
+        "orientation" => FeatureValue::Ident("landscape"),
+        "prefers-color-scheme" => FeatureValue::Ident(match viewport.color_scheme {
+            ColorScheme::Light => "light",
+            ColorScheme::Dark => "dark",
+        }),
+        "color" => FeatureValue::Number(8.0),
+        "monochrome" | "grid" => FeatureValue::Number(0.0),
+        "scan" => FeatureValue::Ident("progressive"),
+        _ => return None,
+    })
+}
+
+impl MediaFeature {
+    pub fn matches(&self, viewport: &Viewport) -> bool {
+        match *self {
+            MediaFeature::Boolean(ref name) => match feature_value(name, viewport) {
+                Some(FeatureValue::Number(n)) => n != 0.0,
+                Some(FeatureValue::Ident(ident)) => ident != "none",
+                None => false,
+            },
+            MediaFeature::Range {
+                ref name,
+                op,
+                ref value,
+            } => match (feature_value(name, viewport), value) {
+                (Some(FeatureValue::Ident(actual)), MediaValue::Ident(expected)) => {
+                    op == RangeOp::Eq && actual.eq_ignore_ascii_case(expected)
+                }
+                (Some(FeatureValue::Number(actual)), value) => match value.to_number() {
+                    Some(expected) => match op {
+                        RangeOp::Lt => actual < expected,
+                        RangeOp::Le => actual <= expected,
+                        RangeOp::Eq => (actual - expected).abs() < 1e-4,
+                        RangeOp::Ge => actual >= expected,
+                        RangeOp::Gt => actual > expected,
+                    },
+                    None => false,
+                },
+                _ => false,
+            },
+        }
+    }
+}
+
+impl MediaValue {
+    fn to_number(&self) -> Option<f32> {
+        match *self {
+            MediaValue::Number(n) | MediaValue::Length(n) | MediaValue::Resolution(n) => Some(n),
+            MediaValue::Ratio(a, b) => Some(a / b),
+            MediaValue::Ident(_) => None,
+        }
+    }
+}
+
+// Split on `separator` outside of parentheses.
+fn split_top_level(input: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in input.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            c if c == separator && depth == 0 => {
+                parts.push(&input[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&input[start..]);
+    parts
+}
+
+struct MediaParser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl MediaParser {
+    fn new(input: &str) -> MediaParser {
+        MediaParser {
+            chars: input.chars().collect(),
+            pos: 0,
+        }
+    }
+
+    fn parse_media_query(&mut self) -> Option<MediaQuery> {
+        self.consume_whitespace();
+        if self.peek() == Some('(') || self.peek_keyword("not") && self.followed_by_paren() {
+            return Some(MediaQuery {
+                negated: false,
+                media_type: None,
+                condition: Some(self.parse_condition(true)?),
+            });
+        }
+
+        let mut negated = false;
+        if self.consume_keyword("not") {
+            negated = true;
+        } else {
+            self.consume_keyword("only");
+        }
+        let media_type = self.parse_identifier()?.to_ascii_lowercase();
+        if matches!(&*media_type, "and" | "or" | "not" | "only") {
+            return None;
+        }
+
+        let condition = if self.consume_keyword("and") {
+            Some(self.parse_condition(false)?)
+        } else {
+            None
+        };
+        Some(MediaQuery {
+            negated,
+            media_type: Some(media_type),
+            condition,
+        })
+    }
+
+    // `allow_or` is false after a media type, where only `and` may combine conditions.
+    fn parse_condition(&mut self, allow_or: bool) -> Option<MediaCondition> {
+        self.consume_whitespace();
+        if self.consume_keyword("not") {
+            return Some(MediaCondition::Not(Box::new(self.parse_in_parens()?)));
+        }
+
+        let first = self.parse_in_parens()?;
+        let combinator = if self.peek_keyword("and") {
+            "and"
+        } else if allow_or && self.peek_keyword("or") {
+            "or"
+        } else {
+            return Some(first);
+        };
+
+        let mut conditions = vec![first];
+        while self.consume_keyword(combinator) {
+            conditions.push(self.parse_in_parens()?);
+        }
+        Some(match combinator {
+            "and" => MediaCondition::And(conditions),
+            _ => MediaCondition::Or(conditions),
+        })
+    }
+
+    fn parse_in_parens(&mut self) -> Option<MediaCondition> {
+        self.consume_whitespace();
+        if self.peek() != Some('(') {
+            return None;
+        }
+        let start = self.pos;
+        self.pos += 1;
+        self.consume_whitespace();
+
+        let condition = if self.peek() == Some('(') || self.peek_keyword("not") {
+            self.parse_condition(true)
+        } else {
+            self.parse_feature()
+        };
+        self.consume_whitespace();
+
+        match condition {
+            Some(condition) if self.peek() == Some(')') => {
+                self.pos += 1;
+                self.consume_whitespace();
+                Some(condition)
+            }
+            // Anything else in balanced parentheses is <general-enclosed>.
+            _ => {
+                self.pos = start;
+                let text = self.skip_parens()?;
+                Some(MediaCondition::Unknown(text))
+            }
+        }
+    }
+
+    // A feature in parentheses. Two sided ranges such as `(400px < width <= 700px)` become a
+    // pair of features joined with `and`.
+    fn parse_feature(&mut self) -> Option<MediaCondition> {
+        // `<name>`, `<name>: <value>` or `<name> <op> <value>`.
+        if let Some(name) = self.try_parse(|p| p.parse_identifier()) {
+            let name = name.to_ascii_lowercase();
+            self.consume_whitespace();
+            if self.peek() == Some(')') {
+                return Some(MediaCondition::Feature(MediaFeature::Boolean(name)));
+            }
+            if self.peek() == Some(':') {
+                self.pos += 1;
+                let value = self.parse_value()?;
+                return Some(match name.strip_prefix("min-") {
+                    Some(name) => range(name, RangeOp::Ge, value),
+                    None => match name.strip_prefix("max-") {
+                        Some(name) => range(name, RangeOp::Le, value),
+                        None => range(&name, RangeOp::Eq, value),
+                    },
+                });
+            }
+            let op = self.parse_op()?;
+            let value = self.parse_value()?;
+            return Some(range(&name, op, value));
+        }
+
+        // `<value> <op> <name>`, optionally followed by `<op> <value>`.
+        let low = self.parse_value()?;
+        let low_op = self.parse_op()?;
+        let name = self.parse_identifier()?.to_ascii_lowercase();
+        let first = range(&name, low_op.flipped(), low);
+        self.consume_whitespace();
+        if self.peek() == Some(')') {
+            return Some(first);
+        }
+        let high_op = self.parse_op()?;
+        let high = self.parse_value()?;
+        // Both comparisons must point the same way, as in `400px < width <= 700px`.
+        let increasing = |op| matches!(op, RangeOp::Lt | RangeOp::Le);
+        if increasing(low_op) != increasing(high_op) || low_op == RangeOp::Eq {
+            return None;
+        }
+        Some(MediaCondition::And(vec![
+            first,
+            range(&name, high_op, high),
+        ]))
+    }
+
+    fn parse_op(&mut self) -> Option<RangeOp> {
+        self.consume_whitespace();
+        let op = match self.peek()? {
+            '<' => RangeOp::Lt,
+            '>' => RangeOp::Gt,
+            '=' => RangeOp::Eq,
+            _ => return None,
+        };
+        self.pos += 1;
+        if op != RangeOp::Eq && self.peek() == Some('=') {
+            self.pos += 1;
+            return Some(if op == RangeOp::Lt {
+                RangeOp::Le
+            } else {
+                RangeOp::Ge
+            });
+        }
+        Some(op)
+    }
+
+    fn parse_value(&mut self) -> Option<MediaValue> {
+        self.consume_whitespace();
+        if let Some(ident) = self.try_parse(|p| p.parse_identifier()) {
+            return Some(MediaValue::Ident(ident.to_ascii_lowercase()));
+        }
+
+        let number = self.parse_number()?;
+        let unit = self.try_parse(|p| p.parse_identifier()).unwrap_or_default();
+        Some(match &*unit.to_ascii_lowercase() {
+            "" => {
+                self.consume_whitespace();
+                if self.peek() == Some('/') {
+                    self.pos += 1;
+                    self.consume_whitespace();
+                    MediaValue::Ratio(number, self.parse_number()?)
+                } else {
+                    MediaValue::Number(number)
+                }
+            }
+            "px" => MediaValue::Length(number),
+            // Relative lengths in media queries use the initial font size.
+            "em" | "rem" => MediaValue::Length(number * 16.0),
+            "dppx" | "x" => MediaValue::Resolution(number),
+            "dpi" => MediaValue::Resolution(number / 96.0),
+            "dpcm" => MediaValue::Resolution(number * 2.54 / 96.0),
+            _ => return None,
+        })
+    }
+
+    fn parse_number(&mut self) -> Option<f32> {
+        let start = self.pos;
+        if matches!(self.peek(), Some('-' | '+')) {
+            self.pos += 1;
+        }
+        while matches!(self.peek(), Some('0'..='9' | '.')) {
+            self.pos += 1;
+        }
+        let text: String = self.chars[start..self.pos].iter().collect();
+        match text.parse() {
+            Ok(n) => Some(n),
+            Err(_) => {
+                self.pos = start;
+                None
+            }
+        }
+    }
+
+    fn parse_identifier(&mut self) -> Option<String> {
+        self.consume_whitespace();
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+        let ident: String = self.chars[start..self.pos].iter().collect();
+        if ident.is_empty() || ident.starts_with(|c: char| c.is_ascii_digit()) {
+            self.pos = start;
+            return None;
+        }
+        Some(ident)
+    }
+
+    // Skip a balanced parenthesized block, returning its text.
+    fn skip_parens(&mut self) -> Option<String> {
+        let start = self.pos;
+        let mut depth = 0;
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match c {
+                '(' => depth += 1,
+                ')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        let text = self.chars[start..self.pos].iter().collect();
+                        self.consume_whitespace();
+                        return Some(text);
+                    }
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
+    fn try_parse<T>(&mut self, f: impl FnOnce(&mut Self) -> Option<T>) -> Option<T> {
+        let start = self.pos;
+        let result = f(self);
+        if result.is_none() {
+            self.pos = start;
+        }
+        result
+    }
+
+    fn peek_keyword(&mut self, keyword: &str) -> bool {
+        let start = self.pos;
+        let found = self
+            .parse_identifier()
+            .map_or(false, |ident| ident.eq_ignore_ascii_case(keyword));
+        self.pos = start;
+        found
+    }
+
+    fn consume_keyword(&mut self, keyword: &str) -> bool {
+        if self.peek_keyword(keyword) {
+            self.parse_identifier();
+            self.consume_whitespace();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn followed_by_paren(&mut self) -> bool {
+        let start = self.pos;
+        self.parse_identifier();
+        self.consume_whitespace();
+        let paren = self.peek() == Some('(');
+        self.pos = start;
+        paren
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn consume_whitespace(&mut self) {
+        while self.peek().map_or(false, char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.consume_whitespace();
+        self.pos == self.chars.len()
+    }
+}
+
+fn range(name: &str, op: RangeOp, value: MediaValue) -> MediaCondition {
+    MediaCondition::Feature(MediaFeature::Range {
+        name: name.to_string(),
+        op,
+        value,
+    })
+}
+
+impl RangeOp {
+    // The operator with its operands swapped: `a < b` is `b > a`.
+    fn flipped(self) -> RangeOp {
+        match self {
+            RangeOp::Lt => RangeOp::Gt,
+            RangeOp::Le => RangeOp::Ge,
+            RangeOp::Eq => RangeOp::Eq,
+            RangeOp::Ge => RangeOp::Le,
+            RangeOp::Gt => RangeOp::Lt,
+        }
+    }
+}
+
+impl fmt::Display for MediaQueryList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, query) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", query)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for MediaQuery {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.negated {
+            write!(f, "not ")?;
+        }
+        match (&self.media_type, &self.condition) {
+            (Some(media_type), Some(condition)) => {
+                write!(f, "{} and ", media_type)?;
+                write_in_parens(f, condition)
+            }
+            (Some(media_type), None) => write!(f, "{}", media_type),
+            (None, Some(condition)) => write!(f, "{}", condition),
+            (None, None) => write!(f, "all"),
+        }
+    }
+}
+
+impl fmt::Display for MediaCondition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MediaCondition::Feature(feature) => write!(f, "{}", feature),
+            MediaCondition::Not(condition) => {
+                write!(f, "not ")?;
+                write_in_parens(f, condition)
+            }
+            MediaCondition::And(conditions) | MediaCondition::Or(conditions) => {
+                let combinator = match self {
+                    MediaCondition::And(_) => " and ",
+                    _ => " or ",
+                };
+                for (i, condition) in conditions.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "{}", combinator)?;
+                    }
+                    write_in_parens(f, condition)?;
+                }
+                Ok(())
+            }
+            MediaCondition::Unknown(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+// Features are already parenthesized; combined conditions need parentheses when nested.
+fn write_in_parens(f: &mut fmt::Formatter, condition: &MediaCondition) -> fmt::Result {
+    match condition {
+        MediaCondition::Feature(_) | MediaCondition::Unknown(_) => write!(f, "{}", condition),
+        condition => write!(f, "({})", condition),
+    }
+}
+
+impl fmt::Display for MediaFeature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MediaFeature::Boolean(name) => write!(f, "({})", name),
+            MediaFeature::Range {
+                name,
+                op: RangeOp::Eq,
+                value,
+            } => write!(f, "({}: {})", name, value),
+            MediaFeature::Range { name, op, value } => write!(f, "({} {} {})", name, op, value),
+        }
+    }
+}
+
+impl fmt::Display for RangeOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let op = match self {
+            RangeOp::Lt => "<",
+            RangeOp::Le => "<=",
+            RangeOp::Eq => "=",
+            RangeOp::Ge => ">=",
+            RangeOp::Gt => ">",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+impl fmt::Display for MediaValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MediaValue::Number(n) => write!(f, "{}", n),
+            MediaValue::Length(px) => write!(f, "{}px", px),
+            MediaValue::Ratio(a, b) => write!(f, "{}/{}", a, b),
+            MediaValue::Resolution(dppx) => write!(f, "{}dppx", dppx),
+            MediaValue::Ident(ident) => write!(f, "{}", ident),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(query: &str, width: f32) -> bool {
+        parse_media_query_list(query).matches(&Viewport::new(width, 600.0))
+    }
+
+    #[test]
+    fn range_syntax() {
+        let query = "(400px <= width < 800px)";
+        assert!(!matches(query, 399.0));
+        assert!(matches(query, 400.0));
+        assert!(matches(query, 799.0));
+        assert!(!matches(query, 800.0));
+        assert!(matches("(width > 30em)", 481.0));
+        assert!(!matches("(800px < width <= 400px)", 600.0));
+        // Comparisons pointing opposite ways are invalid.
+        assert!(!matches("(400px < width > 800px)", 600.0));
+    }
+
+    #[test]
+    fn min_and_max_prefixes() {
+        assert_eq!(
+            parse_media_query_list("(min-width: 600px)"),
+            parse_media_query_list("(width >= 600px)")
+        );
+        assert!(matches("(min-width: 600px) and (max-width: 900px)", 600.0));
+        assert!(matches("(min-width: 600px) and (max-width: 900px)", 900.0));
+        assert!(!matches("(min-width: 600px) and (max-width: 900px)", 901.0));
+        assert!(matches("(min-aspect-ratio: 4/3)", 800.0));
+        assert!(!matches("(min-aspect-ratio: 4/3)", 799.0));
+    }
+
+    #[test]
+    fn not_only_and_or() {
+        assert!(matches("screen", 800.0));
+        assert!(!matches("print", 800.0));
+        assert!(matches("not print", 800.0));
+        assert!(matches("only screen and (min-width: 600px)", 800.0));
+        assert!(!matches("not screen and (min-width: 600px)", 800.0));
+        assert!(matches("not screen and (min-width: 600px)", 400.0));
+        assert!(matches("(max-width: 400px) or (min-width: 700px)", 800.0));
+        assert!(!matches("(max-width: 400px) or (min-width: 700px)", 500.0));
+        assert!(matches(
+            "not ((max-width: 400px) or (min-width: 700px))",
+            500.0
+        ));
+        assert!(matches("print, (orientation: landscape)", 800.0));
+        // `or` cannot follow a media type.
+        assert!(!matches(
+            "screen and (width: 800px) or (width: 400px)",
+            800.0
+        ));
+    }
+
+    #[test]
+    fn invalid_queries_become_not_all() {
+        let not_all = MediaQuery {
+            negated: true,
+            media_type: None,
+            condition: None,
+        };
+        let list = parse_media_query_list("screen and, (min-width: 600px)");
+        assert_eq!(list.0[0], not_all);
+        assert!(list.matches(&Viewport::new(800.0, 600.0)));
+        assert_eq!(
+            parse_media_query_list("(min-width: 600px) and").0,
+            [not_all.clone()]
+        );
+        assert_eq!(parse_media_query_list("screen print").0, [not_all]);
+        assert!(!matches("only and", 800.0));
+        // Unknown features and values are valid but never match.
+        assert!(!matches("(hover-color: red)", 800.0));
+        assert!(!matches("(min-width: 600zz)", 800.0));
+        assert!(matches("not (hover-color: red)", 800.0));
+    }
+
+    #[test]
+    fn queries_are_evaluated_again_when_the_viewport_changes() {
+        let list = parse_media_query_list("(max-width: 600px), (orientation: portrait)");
+        let mut viewport = Viewport::new(800.0, 600.0);
+        assert!(!list.matches(&viewport));
+        viewport.width = 500.0;
+        assert!(list.matches(&viewport));
+        viewport = Viewport::new(700.0, 900.0);
+        assert!(list.matches(&viewport));
+        viewport.color_scheme = ColorScheme::Dark;
+        assert!(parse_media_query_list("(prefers-color-scheme: dark)").matches(&viewport));
+    }
+}
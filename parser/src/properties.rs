@@ -0,0 +1,408 @@
+use std::{collections::HashMap, sync::OnceLock};
+
+use crate::{
+    calc::CalcType,
+    computed::{
+        is_css_wide_keyword, ComputedValue, Content, CounterList, Direction, FontFamilyList,
+        FontStyle, LengthPercentage, LengthPercentageOrAuto, LineBreak, LineHeight, OverflowWrap,
+        Position, Quotes, ShadowList, Spacing, TextAlign, TextDecorationLine, TextDecorationStyle,
+        TextDecorationThickness, TextTransform, UnicodeBidi, WhiteSpace, WordBreak,
+    },
+    css::{parse_value, Color, Declaration, Value},
+    styles::{Display, PropertyMap},
+};
+
+/// A longhand property known to the style system.
+#[derive(Debug)]
+pub struct Property {
+    pub name: &'static str,
+    // Whether elements take the parent's computed value when the property is not specified.
+    pub inherited: bool,
+    pub initial: Value,
+}
+
+macro_rules! is_inherited {
+    (inherited) => {
+        true
+    };
+    (reset) => {
+        false
+    };
+}
+
+// Generate a struct for every group of properties, `ComputedStyle` holding all groups, and
+// the table of properties with their CSS names and initial values. The properties of a group
+// are either all inherited or all not.
+macro_rules! properties {
+    ($(
+        $(#[$doc:meta])*
+        $kind:ident $group:ident: $Group:ident {
+            $($name:literal $field:ident: $Type:ty = $initial:literal,)*
+        }
+    )*) => {
+        $(
+            $(#[$doc])*
+            #[derive(Debug, Clone, PartialEq)]
+            pub struct $Group {
+                $(pub $field: $Type,)*
+            }
+        )*
+
+        /// The computed values of all properties of an element.
+        #[derive(Debug, Clone, PartialEq)]
+        pub struct ComputedStyle {
+            $(pub $group: $Group,)*
+            // Custom properties, which have no fixed type.
+            pub custom: PropertyMap,
+        }
+
+        // Whether `value` can be specified for the longhand `name`, where a math function is
+        // given by the type `calc` it resolves to. Unknown properties accept any value.
+        fn longhand_accepts(name: &str, value: &Value, calc: Option<CalcType>) -> bool {
+            match (name, calc) {
+                $($(($name, Some(calc)) => <$Type as ComputedValue>::accepts_calc(calc),
+                ($name, None) => <$Type as ComputedValue>::accepts(value),)*)*
+                _ => true,
+            }
+        }
+
+        // Name, whether the property is inherited and its initial value.
+        const PROPERTIES: &[(&str, bool, &str)] = &[
+            $($(($name, is_inherited!($kind), $initial),)*)*
+        ];
+
+        impl ComputedStyle {
+            /// The computed value of the property `name` as a generic value, custom properties
+            /// included.
+            pub fn get(&self, name: &str) -> Option<Value> {
+                match name {
+                    $($($name => Some(self.$group.$field.to_value()),)*)*
+                    _ => self.custom.get(name).cloned(),
+                }
+            }
+
+            /// Set the longhand `name` from a computed value. Returns false if the value does
+            /// not have the property's type, or the property is unknown.
+            pub fn set(&mut self, name: &str, value: &Value) -> bool {
+                match name {
+                    $($($name => match ComputedValue::from_value(value) {
+                        Some(value) => {
+                            self.$group.$field = value;
+                            true
+                        }
+                        None => false,
+                    },)*)*
+                    _ => false,
+                }
+            }
+
+            /// Give the longhand `name` the value it has in `other`.
+            pub fn copy(&mut self, name: &str, other: &ComputedStyle) {
+                match name {
+                    $($($name => self.$group.$field = other.$group.$field.clone(),)*)*
+                    _ => {}
+                }
+            }
+
+            /// The style of an element that only inherits from `parent`, such as text.
+            pub fn inherit(parent: &ComputedStyle) -> ComputedStyle {
+                let initial = ComputedStyle::initial();
+                ComputedStyle {
+                    $($group: if is_inherited!($kind) {
+                        parent.$group.clone()
+                    } else {
+                        initial.$group.clone()
+                    },)*
+                    custom: parent.custom.clone(),
+                }
+            }
+
+            // Initial values that are only known once the element's color is, such as
+            // `currentcolor`, get the type's default.
+            fn initial_values() -> ComputedStyle {
+                ComputedStyle {
+                    $($group: $Group {
+                        $($field: ComputedValue::from_value(&initial_value($initial))
+                            .unwrap_or_default(),)*
+                    },)*
+                    custom: PropertyMap::new(),
+                }
+            }
+        }
+    };
+}
+
+properties! {
+    /// Size and spacing of the box.
+    reset box_model: BoxModelProperties {
+        "display" display: Display = "inline",
+        "width" width: LengthPercentageOrAuto = "auto",
+        "height" height: LengthPercentageOrAuto = "auto",
+        "margin-top" margin_top: LengthPercentageOrAuto = "0px",
+        "margin-right" margin_right: LengthPercentageOrAuto = "0px",
+        "margin-bottom" margin_bottom: LengthPercentageOrAuto = "0px",
+        "margin-left" margin_left: LengthPercentageOrAuto = "0px",
+        "padding-top" padding_top: LengthPercentage = "0px",
+        "padding-right" padding_right: LengthPercentage = "0px",
+        "padding-bottom" padding_bottom: LengthPercentage = "0px",
+        "padding-left" padding_left: LengthPercentage = "0px",
+    }
+
+    /// Positioning scheme and offsets.
+    reset position: PositionProperties {
+        "position" position: Position = "static",
+        "top" top: LengthPercentageOrAuto = "auto",
+        "right" right: LengthPercentageOrAuto = "auto",
+        "bottom" bottom: LengthPercentageOrAuto = "auto",
+        "left" left: LengthPercentageOrAuto = "auto",
+    }
+
+    /// Border widths in pixels, and colors.
+    reset border: BorderProperties {
+        "border-top-width" border_top_width: f32 = "0px",
+        "border-right-width" border_right_width: f32 = "0px",
+        "border-bottom-width" border_bottom_width: f32 = "0px",
+        "border-left-width" border_left_width: f32 = "0px",
+        "border-top-color" border_top_color: Color = "currentcolor",
+        "border-right-color" border_right_color: Color = "currentcolor",
+        "border-bottom-color" border_bottom_color: Color = "currentcolor",
+        "border-left-color" border_left_color: Color = "currentcolor",
+    }
+
+    reset background: BackgroundProperties {
+        "background-color" background_color: Color = "transparent",
+    }
+
+    /// Font families, size in pixels, weight and style.
+    inherited font: FontProperties {
+        "font-family" font_family: FontFamilyList = "sans-serif",
+        "font-size" font_size: f32 = "16px",
+        "font-weight" font_weight: u16 = "400",
+        "font-style" font_style: FontStyle = "normal",
+    }
+
+    inherited text: TextProperties {
+        "color" color: Color = "#000000",
+        "list-style-type" list_style_type: String = "disc",
+        "quotes" quotes: Quotes = "auto",
+        "word-break" word_break: WordBreak = "normal",
+        "overflow-wrap" overflow_wrap: OverflowWrap = "normal",
+        "line-break" line_break: LineBreak = "auto",
+        "direction" direction: Direction = "ltr",
+        "text-align" text_align: TextAlign = "start",
+        "text-indent" text_indent: LengthPercentage = "0px",
+        "text-transform" text_transform: TextTransform = "none",
+        "line-height" line_height: LineHeight = "normal",
+        "letter-spacing" letter_spacing: Spacing = "normal",
+        "word-spacing" word_spacing: Spacing = "normal",
+        "white-space" white_space: WhiteSpace = "normal",
+        "text-underline-offset" text_underline_offset: LengthPercentageOrAuto = "auto",
+        "text-shadow" text_shadow: ShadowList = "none",
+    }
+
+    /// Lines drawn under, over or through the text of the element and its descendants.
+    reset decoration: DecorationProperties {
+        "text-decoration-line" text_decoration_line: TextDecorationLine = "none",
+        "text-decoration-style" text_decoration_style: TextDecorationStyle = "solid",
+        "text-decoration-color" text_decoration_color: Color = "currentcolor",
+        "text-decoration-thickness" text_decoration_thickness: TextDecorationThickness = "auto",
+    }
+
+    reset bidi: BidiProperties {
+        "unicode-bidi" unicode_bidi: UnicodeBidi = "normal",
+    }
+
+    /// Generated content of pseudo-elements and the counters it can show.
+    reset content: ContentProperties {
+        "content" content: Content = "normal",
+        "counter-reset" counter_reset: CounterList = "none",
+        "counter-increment" counter_increment: CounterList = "none",
+    }
+}
+
+impl ComputedStyle {
+    /// The style of an element for which no property is specified and nothing is inherited.
+    pub fn initial() -> &'static ComputedStyle {
+        static INITIAL: OnceLock<ComputedStyle> = OnceLock::new();
+        INITIAL.get_or_init(ComputedStyle::initial_values)
+    }
+}
+
+/// Look up a longhand property by name.
+pub fn property(name: &str) -> Option<&'static Property> {
+    registry().get(name)
+}
+
+/// All registered longhand properties, in no particular order.
+pub fn properties() -> impl Iterator<Item = &'static Property> {
+    registry().values()
+}
+
+// The initial values in the tables above are always valid CSS.
+fn initial_value(text: &str) -> Value {
+    parse_value(text).unwrap_or_else(|| panic!("Invalid initial value {}", text))
+}
+
+fn registry() -> &'static HashMap<&'static str, Property> {
+    static REGISTRY: OnceLock<HashMap<&'static str, Property>> = OnceLock::new();
+    REGISTRY.get_or_init(|| {
+        PROPERTIES
+            .iter()
+            .map(|&(name, inherited, initial)| {
+                let initial = initial_value(initial);
+                (
+                    name,
+                    Property {
+                        name,
+                        inherited,
+                        initial,
+                    },
+                )
+            })
+            .collect()
+    })
+}
+
+/// Whether the properties of `declaration` accept its values, which rules out e.g.
+/// `display: garbage`, or `width: calc(2 + 3)` since math functions must resolve to a type the
+/// property takes.
+pub fn accepts(declaration: &Declaration) -> bool {
+    expand_shorthand(declaration)
+        .iter()
+        .all(|(name, value)| match value {
+            Value::Keyword(k) if is_css_wide_keyword(k) => true,
+            // Values with `var()` are only known at computed-value time.
+            Value::Unparsed(_) | Value::PendingShorthand(..) => true,
+            Value::Calc(calc) => calc.check().is_some_and(|calc| match &**name {
+                // A percentage is of the parent's font size.
+                "font-size" => calc != CalcType::Number,
+                name => longhand_accepts(name, value, Some(calc)),
+            }),
+            value => longhand_accepts(name, value, None),
+        })
+}
+
+/// Expand a shorthand declaration into its longhands, so that a later `margin: 0` overrides
+/// an earlier `margin-top: 8px` and vice versa. Other declarations are returned unchanged.
+pub fn expand_shorthand(declaration: &Declaration) -> Vec<(String, Value)> {
+    // Which part of a value with `var()` goes to which longhand is only known once the
+    // references are substituted.
+    if let Value::Unparsed(ref text) = declaration.value {
+        let placeholder = Declaration {
+            name: declaration.name.clone(),
+            value: Value::Keyword("initial".to_string()),
+            important: declaration.important,
+        };
+        return expand_shorthand(&placeholder)
+            .into_iter()
+            .map(|(longhand, _)| {
+                let value = match longhand == declaration.name {
+                    true => Value::Unparsed(text.clone()),
+                    false => Value::PendingShorthand(declaration.name.clone(), text.clone()),
+                };
+                (longhand, value)
+            })
+            .collect();
+    }
+
+    // Like `margin: 1px 2px 3px`, box edge shorthands take one to four values for the top,
+    // right, bottom and left edges.
+    let (prefix, suffix) = match &*declaration.name {
+        "margin" => ("margin", ""),
+        "padding" => ("padding", ""),
+        "border-width" => ("border", "-width"),
+        "border-color" => ("border", "-color"),
+        "background" => return vec![("background-color".to_string(), declaration.value.clone())],
+        // `word-wrap` is the legacy name of `overflow-wrap`.
+        "word-wrap" => return vec![("overflow-wrap".to_string(), declaration.value.clone())],
+        "text-decoration" => return expand_text_decoration(&declaration.value),
+        _ => return vec![(declaration.name.clone(), declaration.value.clone())],
+    };
+
+    let values = match declaration.value {
+        Value::List(ref values) => values.clone(),
+        ref value => vec![value.clone()],
+    };
+    let [top, right, bottom, left] = match values.as_slice() {
+        [all] => [all, all, all, all],
+        [vertical, horizontal] => [vertical, horizontal, vertical, horizontal],
+        [top, horizontal, bottom] => [top, horizontal, bottom, horizontal],
+        [top, right, bottom, left] => [top, right, bottom, left],
+        _ => return Vec::new(),
+    };
+
+    [
+        ("top", top),
+        ("right", right),
+        ("bottom", bottom),
+        ("left", left),
+    ]
+    .into_iter()
+    .map(|(side, value)| (format!("{}-{}{}", prefix, side, suffix), value.clone()))
+    .collect()
+}
+
+// `text-decoration` sets the lines, style, color and thickness of decorations in any order,
+// and the ones it leaves out to their initial values.
+fn expand_text_decoration(value: &Value) -> Vec<(String, Value)> {
+    let longhands = [
+        "text-decoration-line",
+        "text-decoration-style",
+        "text-decoration-color",
+        "text-decoration-thickness",
+    ];
+    if let Value::Keyword(ref k) = value {
+        if is_css_wide_keyword(k) {
+            return longhands
+                .iter()
+                .map(|longhand| (longhand.to_string(), value.clone()))
+                .collect();
+        }
+    }
+
+    let values = match value {
+        Value::List(values) => values.clone(),
+        value => vec![value.clone()],
+    };
+    let mut lines = Vec::new();
+    let (mut style, mut color, mut thickness) = (None, None, None);
+    for value in values {
+        let part = match value {
+            Value::Keyword(ref k)
+                if matches!(
+                    &**k,
+                    "none" | "underline" | "overline" | "line-through" | "blink"
+                ) =>
+            {
+                lines.push(value);
+                continue;
+            }
+            Value::Keyword(ref k) if k == "auto" || k == "from-font" => &mut thickness,
+            Value::Keyword(ref k) if k == "currentcolor" || k == "transparent" => &mut color,
+            Value::Keyword(_) if TextDecorationStyle::from_value(&value).is_some() => &mut style,
+            Value::Color(_) => &mut color,
+            Value::Length(..) | Value::Number(_) | Value::Calc(_) => &mut thickness,
+            _ => return Vec::new(),
+        };
+        if part.replace(value).is_some() {
+            return Vec::new();
+        }
+    }
+
+    let line = match lines.len() {
+        0 => Value::Keyword("none".to_string()),
+        1 => lines.remove(0),
+        _ => Value::List(lines),
+    };
+    let values = [
+        line,
+        style.unwrap_or_else(|| Value::Keyword("solid".to_string())),
+        color.unwrap_or_else(|| Value::Keyword("currentcolor".to_string())),
+        thickness.unwrap_or_else(|| Value::Keyword("auto".to_string())),
+    ];
+    longhands
+        .into_iter()
+        .map(str::to_string)
+        .zip(values)
+        .collect()
+}
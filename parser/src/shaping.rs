@@ -0,0 +1,182 @@
+//! Shaping: turning a run of text into glyphs of a font placed along the line, with the
+//! ligatures, kerning, mark positioning and contextual forms from the font's OpenType tables.
+
+use rustybuzz::{Direction, Face, UnicodeBuffer};
+
+use crate::fontdb::FontInstance;
+
+/// A glyph of a shaped run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Glyph {
+    /// The glyph in the font, not a character.
+    pub id: u16,
+    /// Where the glyph origin goes, from the left of the run and from the top of its content
+    /// area, so `y` is on the baseline unless the font moves the glyph up or down.
+    pub x: f32,
+    pub y: f32,
+    /// Byte offset in the run's text of the first character the glyph stands for.
+    pub cluster: usize,
+    /// The face the glyph is from. Shaping with a single font leaves it at the default, for
+    /// the font database to fill in.
+    pub font: FontInstance,
+}
+
+/// Text shaped in one font and direction. The glyphs are in visual order, from left to right.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GlyphRun {
+    /// The text, in logical order.
+    pub text: String,
+    pub font_size: f32,
+    pub rtl: bool,
+    pub width: f32,
+    pub glyphs: Vec<Glyph>,
+}
+
+/// Shape `text` with the font whose tables are in `face`, scaled for a font of `font_size`
+/// pixels with the baseline `ascent` below the top of the content area. The script is
+/// guessed from the text.
+pub fn shape(face: &Face, text: &str, font_size: f32, ascent: f32, rtl: bool) -> GlyphRun {
+    let mut buffer = UnicodeBuffer::new();
+    buffer.push_str(text);
+    buffer.set_direction(match rtl {
+        true => Direction::RightToLeft,
+        false => Direction::LeftToRight,
+    });
+    buffer.guess_segment_properties();
+    let output = rustybuzz::shape(face, &[], buffer);
+
+    let scale = font_size / face.units_per_em() as f32;
+    let mut x = 0.0;
+    let mut glyphs = Vec::with_capacity(output.len());
+    for (info, position) in output.glyph_infos().iter().zip(output.glyph_positions()) {
+        glyphs.push(Glyph {
+            id: info.glyph_id as u16,
+            x: x + position.x_offset as f32 * scale,
+            y: ascent - position.y_offset as f32 * scale,
+            cluster: info.cluster as usize,
+            font: FontInstance::default(),
+        });
+        x += position.x_advance as f32 * scale;
+    }
+    GlyphRun {
+        text: text.to_string(),
+        font_size,
+        rtl,
+        width: x,
+        glyphs,
+    }
+}
+
+/// A run of `text` without shaping: one glyph per character, given by `glyph`, each after the
+/// advance of the one before it.
+pub fn place_characters(
+    text: &str,
+    font_size: f32,
+    ascent: f32,
+    rtl: bool,
+    glyph: impl Fn(char) -> u16,
+    advance: impl Fn(char) -> f32,
+) -> GlyphRun {
+    let mut characters: Vec<(usize, char)> = text.char_indices().collect();
+    if rtl {
+        characters.reverse();
+    }
+    let mut x = 0.0;
+    let mut glyphs = Vec::with_capacity(characters.len());
+    for (cluster, c) in characters {
+        glyphs.push(Glyph {
+            id: glyph(c),
+            x,
+            y: ascent,
+            cluster,
+            font: FontInstance::default(),
+        });
+        x += advance(c);
+    }
+    GlyphRun {
+        text: text.to_string(),
+        font_size,
+        rtl,
+        width: x,
+        glyphs,
+    }
+}
+
+/// Whether `c` separates words, so that `word-spacing` and justification widen it.
+pub fn is_word_separator(c: char) -> bool {
+    c == ' ' || c == '\u{A0}'
+}
+
+/// Spread the glyphs of `run` apart by `letter_spacing` after each character, and by
+/// `word_spacing` more after each word separator. The characters a glyph stands for together,
+/// such as those of a ligature, count as one.
+pub fn space_glyphs(run: &mut GlyphRun, letter_spacing: f32, word_spacing: f32) {
+    if letter_spacing == 0.0 && word_spacing == 0.0 {
+        return;
+    }
+    let text = &run.text;
+    let gap = |cluster: usize| match text[cluster..].starts_with(is_word_separator) {
+        true => letter_spacing + word_spacing,
+        false => letter_spacing,
+    };
+    let mut shift = 0.0;
+    let mut previous = None;
+    for glyph in &mut run.glyphs {
+        if let Some(cluster) = previous.filter(|&cluster| cluster != glyph.cluster) {
+            shift += gap(cluster);
+        }
+        glyph.x += shift;
+        previous = Some(glyph.cluster);
+    }
+    if let Some(cluster) = previous {
+        shift += gap(cluster);
+    }
+    run.width += shift;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(text: &str, rtl: bool) -> GlyphRun {
+        place_characters(text, 16.0, 12.0, rtl, |c| c as u16, |_| 8.0)
+    }
+
+    fn positions(run: &GlyphRun) -> Vec<(usize, f32)> {
+        run.glyphs
+            .iter()
+            .map(|glyph| (glyph.cluster, glyph.x))
+            .collect()
+    }
+
+    #[test]
+    fn characters_are_placed_one_advance_apart() {
+        let run = run("ab", false);
+        assert_eq!(run.width, 16.0);
+        assert_eq!(positions(&run), [(0, 0.0), (1, 8.0)]);
+        assert!(run.glyphs.iter().all(|glyph| glyph.y == 12.0));
+    }
+
+    #[test]
+    fn right_to_left_runs_are_in_visual_order() {
+        assert_eq!(positions(&run("ab", true)), [(1, 0.0), (0, 8.0)]);
+    }
+
+    #[test]
+    fn word_spacing_only_widens_word_separators() {
+        let mut spaced = run("a b", false);
+        space_glyphs(&mut spaced, 1.0, 4.0);
+        assert_eq!(positions(&spaced), [(0, 0.0), (1, 9.0), (2, 22.0)]);
+        assert_eq!(spaced.width, 24.0 + 3.0 + 4.0);
+    }
+
+    #[test]
+    fn glyphs_of_one_cluster_move_together() {
+        let mut spaced = run("ab", false);
+        // A ligature-like pair of glyphs for the first character.
+        spaced.glyphs[1].cluster = 0;
+        space_glyphs(&mut spaced, 2.0, 0.0);
+        assert_eq!(positions(&spaced), [(0, 0.0), (0, 8.0)]);
+        assert_eq!(spaced.width, 18.0);
+    }
+}
@@ -0,0 +1,241 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    mem,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use crate::{
+    matching::{ElementContext, RuleMap},
+    properties::ComputedStyle,
+};
+
+/// The computed style of an element and of its pseudo-elements, which can be shared with
+/// other elements that are styled the same way.
+#[derive(Clone)]
+pub struct SharedStyle {
+    pub style: Arc<ComputedStyle>,
+    // Pseudo-elements that generate a box, in the order of their boxes.
+    pub marker: Option<Arc<ComputedStyle>>,
+    pub before: Option<Arc<ComputedStyle>>,
+    pub after: Option<Arc<ComputedStyle>>,
+}
+
+/// What decides the style of an element: elements with equal keys match the same rules and
+/// inherit the same values, so they get the same computed style.
+#[derive(PartialEq, Eq, Hash)]
+struct SharingKey {
+    // Address of the parent's computed style. Elements whose parents share a style have
+    // ancestors with the same names, ids and classes, since sharing implies so recursively.
+    parent: usize,
+    name: String,
+    id: Option<String>,
+    classes: Vec<String>,
+    revalidation: Vec<bool>,
+}
+
+static PASSES: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    // The cache left on this thread by the last parallel job, and the pass it belongs to.
+    static THREAD_CACHE: RefCell<Option<(u64, StyleSharingCache)>> = const { RefCell::new(None) };
+}
+
+/// Computed styles of the elements styled so far, to reuse for elements with the same tag,
+/// classes and parent style, such as the items of a long list.
+#[derive(Default)]
+pub struct StyleSharingCache {
+    elements: HashMap<SharingKey, SharedStyle>,
+    // The style of the text in each element, by address of the element's style.
+    text: HashMap<usize, Arc<ComputedStyle>>,
+    // The parent styles the keys point to, kept alive so their addresses are not reused.
+    parents: HashMap<usize, Arc<ComputedStyle>>,
+    pub hits: usize,
+}
+
+impl StyleSharingCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A number identifying a new styling pass. Caches are only valid within one pass, since
+    /// another pass may use other stylesheets.
+    pub fn new_pass() -> u64 {
+        PASSES.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// The cache left on the current thread by an earlier job of styling pass `pass`, or an
+    /// empty one. Parallel jobs that run on the same thread share styles this way; give the
+    /// cache back with `put_thread_cache` when the job is done.
+    pub fn take_thread_cache(pass: u64) -> StyleSharingCache {
+        THREAD_CACHE
+            .with(|cache| cache.borrow_mut().take())
+            .filter(|&(cache_pass, _)| cache_pass == pass)
+            .map(|(_, cache)| cache)
+            .unwrap_or_default()
+    }
+
+    pub fn put_thread_cache(&mut self, pass: u64) {
+        let cache = mem::take(self);
+        THREAD_CACHE.with(|thread_cache| *thread_cache.borrow_mut() = Some((pass, cache)));
+    }
+
+    /// The style of the element, computed by `compute` unless an element styled the same way
+    /// has been seen before.
+    pub fn element_style(
+        &mut self,
+        context: &ElementContext,
+        parent: Option<&Arc<ComputedStyle>>,
+        rules: &RuleMap,
+        compute: impl FnOnce() -> SharedStyle,
+    ) -> SharedStyle {
+        let element = context.element;
+        // The `style` attribute and presentational hints are specific to the element.
+        if element.attributes.contains_key("style") || !element.presentational_hints().is_empty() {
+            return compute();
+        }
+
+        let mut classes: Vec<String> = element
+            .classes()
+            .into_iter()
+            .filter(|class| !class.is_empty())
+            .map(str::to_string)
+            .collect();
+        classes.sort();
+        classes.dedup();
+        let key = SharingKey {
+            parent: self.parent_address(parent),
+            name: element.name.clone(),
+            id: element.id().cloned(),
+            classes,
+            revalidation: rules.revalidate(context),
+        };
+
+        if let Some(shared) = self.elements.get(&key) {
+            self.hits += 1;
+            return shared.clone();
+        }
+        let shared = compute();
+        self.elements.insert(key, shared.clone());
+        shared
+    }
+
+    /// The style of the text in an element with style `parent`. Text has no declarations of
+    /// its own and only inherits, so all text in the element has the same style.
+    pub fn text_style(
+        &mut self,
+        parent: Option<&Arc<ComputedStyle>>,
+        compute: impl FnOnce() -> ComputedStyle,
+    ) -> Arc<ComputedStyle> {
+        let address = self.parent_address(parent);
+        if let Some(style) = self.text.get(&address) {
+            self.hits += 1;
+            return style.clone();
+        }
+        let style = Arc::new(compute());
+        self.text.insert(address, style.clone());
+        style
+    }
+
+    fn parent_address(&mut self, parent: Option<&Arc<ComputedStyle>>) -> usize {
+        match parent {
+            Some(parent) => {
+                let address = Arc::as_ptr(parent) as usize;
+                self.parents
+                    .entry(address)
+                    .or_insert_with(|| parent.clone());
+                address
+            }
+            None => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        css::parse_css,
+        datatypes::Node,
+        dom::parse_element,
+        media::Viewport,
+        styles::{style_tree, StyleNode},
+        tokenizer::Tokenizer,
+    };
+
+    use super::*;
+
+    // Whether each pair of the elements of `body` named `name`, or of its text if `name` is
+    // empty, has the same style object, in document order.
+    fn shared(body: &str, css: &str, name: &str) -> Vec<bool> {
+        let html = format!("<html><body>{}</body></html>", body);
+        let mut tokenizer = Tokenizer::new(&html);
+        let mut tokens = Vec::new();
+        while let Some(token) = tokenizer.next_token() {
+            tokens.push(token);
+        }
+        let root = Node::Element(parse_element(&tokens).0);
+        let stylesheets = [parse_css(css.to_string())];
+        let tree = style_tree(&root, &stylesheets, &Viewport::new(800.0, 600.0));
+        let mut styles = Vec::new();
+        collect(&tree, name, &mut styles);
+        styles
+            .windows(2)
+            .map(|pair| Arc::ptr_eq(&pair[0], &pair[1]))
+            .collect()
+    }
+
+    fn collect(node: &StyleNode, name: &str, styles: &mut Vec<Arc<ComputedStyle>>) {
+        match *node.node {
+            Node::Element(ref element) if element.name == name && node.pseudo.is_none() => {
+                styles.push(node.style.clone())
+            }
+            Node::Text(_) if name.is_empty() => styles.push(node.style.clone()),
+            _ => {}
+        }
+        if node.pseudo.is_none() {
+            for child in &node.children {
+                collect(child, name, styles);
+            }
+        }
+    }
+
+    #[test]
+    fn elements_styled_the_same_way_share_their_style() {
+        let body =
+            "<ul><li class=\"a b\">x</li><li class=\"b a\">y</li><li class=\"a b a\">z</li></ul>\
+                    <ul><li class=\"a b\">w</li></ul>";
+        let css = ".a { color: #ff0000 } li::before { content: \"-\" }";
+        assert_eq!(shared(body, css, "li"), [true, true, true]);
+        // So does their text.
+        assert_eq!(shared(body, css, ""), [true, true, true]);
+    }
+
+    #[test]
+    fn elements_that_may_match_other_rules_do_not_share() {
+        let css = ".a { color: #ff0000 } #c { color: #0000ff } \
+                   li:first-child { margin-left: 1px }";
+        let li = |attributes: &str| format!("<li {}>x</li>", attributes);
+        let ul = |attributes: &[&str]| {
+            let items: String = attributes.iter().map(|a| li(a)).collect();
+            format!("<ul><li>x</li>{}</ul>", items)
+        };
+        // The first item only matches `:first-child`.
+        assert_eq!(shared(&ul(&[""]), css, "li"), [false]);
+        assert_eq!(shared(&ul(&["", "class=\"a\""]), css, "li"), [false, false]);
+        assert_eq!(shared(&ul(&["", "id=\"c\""]), css, "li"), [false, false]);
+        // Elements with a style attribute or presentational hints are never shared.
+        assert_eq!(shared(&ul(&["", "style=\"\""]), css, "li"), [false, false]);
+        assert_eq!(shared(&ul(&["", "dir=\"rtl\""]), css, "li"), [false, false]);
+    }
+
+    #[test]
+    fn elements_whose_parents_are_styled_differently_do_not_share() {
+        let body = "<div><p>x</p></div><div class=\"a\"><p>y</p></div><div><p>z</p></div>";
+        let css = ".a { color: #ff0000 }";
+        assert_eq!(shared(body, css, "p"), [false, false]);
+        assert_eq!(shared(body, css, "div"), [false, false]);
+    }
+}
@@ -1,119 +1,1008 @@
-use std::collections::HashMap;
+use std::{borrow::Cow, collections::HashMap, mem, sync::Arc};
+
+use rayon::prelude::*;
 
 use crate::{
-    css::{Rule, Selector, SimpleSelector, Specificity, Stylesheet, Value},
-    datatypes::{Element, Node},
+    computed::{compute_values, Content},
+    content::generate_content,
+    css::{Declaration, Origin, PseudoElement, Specificity, Stylesheet, Value},
+    cssom::StyleVersion,
+    datatypes::{ChildChange, Dirty, Element, Node},
+    matching::{AncestorFilter, ElementContext, RuleMap},
+    media::Viewport,
+    properties::{expand_shorthand, ComputedStyle},
+    sharing::{SharedStyle, StyleSharingCache},
+    useragent::user_agent_stylesheet,
+    variables::Registrations,
 };
 
-type PropertyMap = HashMap<String, Value>;
+pub type PropertyMap = HashMap<String, Value>;
+
+// Sort key of a declaration in the cascade: origin and importance, whether it comes from the
+// element's `style` attribute, cascade layer, then specificity. Source order breaks remaining
+// ties because declarations are sorted stably.
+type CascadeKey = (u8, bool, usize, Specificity);
 
-type MatchedRule<'a> = (Specificity, &'a Rule);
+/// The stylesheets taking part in the cascade, in source order, together with the
+/// precedence of every cascade layer they declare.
+struct Cascade<'a> {
+    stylesheets: Vec<&'a Stylesheet>,
+    layer_ranks: HashMap<(Origin, &'a str), usize>,
+    rules: RuleMap<'a>,
+    registrations: Registrations<'a>,
+    // What `vw` and `vh` lengths are relative to.
+    viewport: Viewport,
+}
 
 #[derive(Debug)]
 pub struct StyleNode<'a> {
-    pub node: &'a Node,
-    pub style_values: PropertyMap,
+    // Pseudo-elements borrow their originating element; their generated text is owned.
+    pub node: Cow<'a, Node>,
+    pub pseudo: Option<PseudoElement>,
+    // Shared with the elements that have the same style.
+    pub style: Arc<ComputedStyle>,
     pub children: Vec<StyleNode<'a>>,
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub enum Display {
+    #[default]
     Inline,
     Block,
+    ListItem,
     None,
 }
 
 impl<'a> StyleNode<'a> {
-    pub fn value(&self, name: &str) -> Option<Value> {
-        self.style_values.get(name).cloned()
+    pub fn display(&self) -> Display {
+        self.style.box_model.display
     }
+}
 
-    pub fn display(&self) -> Display {
-        match self.value("display") {
-            Some(Value::Keyword(s)) => match &*s {
-                "block" => Display::Block,
-                "none" => Display::None,
-                _ => Display::Inline,
-            },
-            _ => Display::Inline,
+fn specified_values(
+    context: &ElementContext,
+    cascade: &Cascade,
+    filter: &AncestorFilter,
+    pseudo: Option<PseudoElement>,
+) -> PropertyMap {
+    let mut values = HashMap::new();
+    let mut declarations: Vec<(CascadeKey, Origin, &Declaration)> = Vec::new();
+
+    let matched = cascade.rules.matching_rules(context, filter, pseudo);
+    for (specificity, stylesheet, rule) in matched {
+        let layer = cascade.layer_rank(stylesheet.origin, rule.layer.as_deref());
+        for declaration in &rule.declaration {
+            let key = cascade_key(
+                stylesheet.origin,
+                declaration.important,
+                false,
+                layer,
+                specificity,
+            );
+            declarations.push((key, stylesheet.origin, declaration));
         }
     }
-    pub fn lookup(&self, name: &str, fallback_name: &str, default: &Value) -> Value {
-        self.value(name)
-            .unwrap_or_else(|| self.value(fallback_name).unwrap_or_else(|| default.clone()))
+
+    // Hints from attributes such as `dir` weigh like a user-agent rule with an attribute
+    // selector.
+    let hints = match pseudo {
+        None => context.element.presentational_hints(),
+        Some(_) => Vec::new(),
+    };
+    for declaration in &hints {
+        let key = cascade_key(Origin::UserAgent, false, false, usize::MAX, (0, 1, 0));
+        declarations.push((key, Origin::UserAgent, declaration));
+    }
+
+    // The `style` attribute belongs to the author origin and wins over any selector.
+    let inline_style = match pseudo {
+        None => context.element.style(),
+        Some(_) => Vec::new(),
+    };
+    for declaration in &inline_style {
+        let key = cascade_key(
+            Origin::Author,
+            declaration.important,
+            true,
+            usize::MAX,
+            (0, 0, 0),
+        );
+        declarations.push((key, Origin::Author, declaration));
     }
+
+    declarations.sort_by(|(a, _, _), (b, _, _)| a.cmp(b));
+
+    // Every value applied so far for each property, with its origin, to support `revert`.
+    let mut applied: HashMap<String, Vec<(Origin, Value)>> = HashMap::new();
+
+    for (_, origin, declaration) in declarations {
+        for (name, value) in expand_shorthand(declaration) {
+            let history = applied.entry(name.clone()).or_default();
+            let value = match value {
+                // Roll back to the value the property would have had from the lower origins.
+                Value::Keyword(ref k) if k == "revert" => history
+                    .iter()
+                    .rev()
+                    .find(|(o, _)| *o < origin)
+                    .map(|(_, value)| value.clone())
+                    .unwrap_or(value),
+                value => value,
+            };
+            history.push((origin, value.clone()));
+            values.insert(name, value);
+        }
+    }
+
+    return values;
 }
 
-fn matches(elem: &Element, selector: &Selector) -> bool {
-    match *selector {
-        Selector::Simple(ref simple_sel) => match_simple_selector(elem, simple_sel),
+fn cascade_key(
+    origin: Origin,
+    important: bool,
+    inline: bool,
+    layer_rank: usize,
+    specificity: Specificity,
+) -> CascadeKey {
+    match important {
+        false => (origin as u8, inline, layer_rank, specificity),
+        // Important declarations reverse the precedence of both origins and layers.
+        true => (
+            2 * Origin::Author as u8 + 1 - origin as u8,
+            inline,
+            usize::MAX - layer_rank,
+            specificity,
+        ),
     }
 }
 
-fn match_simple_selector(elem: &Element, selector: &SimpleSelector) -> bool {
-    if selector.tag.iter().any(|name| elem.name != *name) {
-        return false;
+impl<'a> Cascade<'a> {
+    fn new(stylesheets: &'a [Stylesheet], viewport: &Viewport) -> Cascade<'a> {
+        // The user-agent stylesheet always applies, before any other stylesheet.
+        let stylesheets: Vec<&Stylesheet> = std::iter::once(user_agent_stylesheet())
+            .chain(stylesheets.iter().filter(|sheet| {
+                !sheet.disabled && sheet.media.as_ref().map_or(true, |m| m.matches(viewport))
+            }))
+            .collect();
+
+        let mut cascade = Cascade {
+            rules: RuleMap::new(&stylesheets, viewport),
+            stylesheets,
+            layer_ranks: HashMap::new(),
+            registrations: HashMap::new(),
+            viewport: viewport.clone(),
+        };
+        // When a custom property is registered more than once, the last registration wins.
+        for stylesheet in &cascade.stylesheets {
+            for registration in &stylesheet.registrations {
+                cascade
+                    .registrations
+                    .insert(&registration.name, registration);
+            }
+        }
+        for origin in [Origin::UserAgent, Origin::User, Origin::Author] {
+            let mut names: Vec<&str> = Vec::new();
+            for stylesheet in cascade.stylesheets.iter().filter(|s| s.origin == origin) {
+                for name in &stylesheet.layers {
+                    if !names.contains(&&**name) {
+                        names.push(name);
+                    }
+                }
+            }
+            let mut rank = 0;
+            cascade.rank_layers(origin, &names, None, &mut rank);
+        }
+        cascade
     }
 
-    if selector.id.iter().any(|id| elem.id() != Some(id)) {
-        return false;
+    // Rank the layers nested directly in `parent` in declaration order. Each layer ranks after
+    // the layers nested inside it, since rules directly in a layer win over its sub-layers.
+    fn rank_layers(
+        &mut self,
+        origin: Origin,
+        names: &[&'a str],
+        parent: Option<&str>,
+        rank: &mut usize,
+    ) {
+        for &name in names {
+            let name_parent = name.rsplit_once('.').map(|(parent, _)| parent);
+            if name_parent == parent {
+                self.rank_layers(origin, names, Some(name), rank);
+                self.layer_ranks.insert((origin, name), *rank);
+                *rank += 1;
+            }
+        }
     }
 
-    let elem_classes = elem.classes();
-    if selector
-        .class
-        .iter()
-        .any(|class| !elem_classes.contains(&**class))
-    {
-        return false;
+    // Unlayered rules take precedence over all layers.
+    fn layer_rank(&self, origin: Origin, layer: Option<&str>) -> usize {
+        layer
+            .and_then(|name| self.layer_ranks.get(&(origin, name)).copied())
+            .unwrap_or(usize::MAX)
     }
+}
 
-    return true;
+/// Style `root` with `stylesheets`, evaluating media queries against `viewport`. Since the
+/// result depends on the viewport size, the tree must be rebuilt when the window is resized.
+///
+/// To restyle a document after it changes, use a `Styler` instead.
+pub fn style_tree<'a>(
+    root: &'a Node,
+    stylesheets: &'a [Stylesheet],
+    viewport: &Viewport,
+) -> StyleNode<'a> {
+    let cascade = Cascade::new(stylesheets, viewport);
+    let data = Restyle::new(&cascade).node(root, None, None, Invalidation::Subtree, None, &[]);
+    let mut root = build_style_node(root, data);
+    generate_content(&mut root);
+    root
 }
 
-fn match_rule<'a>(elem: &Element, rule: &'a Rule) -> Option<MatchedRule<'a>> {
-    rule.selector
-        .iter()
-        .find(|selector| matches(elem, *selector))
-        .map(|selector| (selector.specificity(), rule))
+/// The styles of a document, kept between changes to the document so that only the elements
+/// they affect are restyled.
+///
+/// Modify elements through their methods, such as `Element::set_attribute`, which mark them
+/// dirty. Changing the stylesheets or the viewport restyles the whole document.
+#[derive(Default)]
+pub struct Styler {
+    data: Option<StyleData>,
+    version: Option<StyleVersion>,
+    viewport: Option<Viewport>,
+    // Style sibling subtrees in parallel on the rayon thread pool. The styles are the same
+    // either way.
+    pub parallel: bool,
 }
 
-fn matching_rules<'a>(elem: &Element, stylesheet: &'a Stylesheet) -> Vec<MatchedRule<'a>> {
-    stylesheet
-        .rules
-        .iter()
-        .filter_map(|rule| match_rule(elem, rule))
-        .collect()
+impl Styler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bring the styles up to date with `root` and clear its dirty bits. Returns the number
+    /// of elements whose style was computed again.
+    pub fn restyle(
+        &mut self,
+        root: &mut Node,
+        stylesheets: &[Stylesheet],
+        viewport: &Viewport,
+    ) -> usize {
+        let version = StyleVersion::of(stylesheets);
+        let old = self.data.take().filter(|_| {
+            self.version.as_ref() == Some(&version) && self.viewport.as_ref() == Some(viewport)
+        });
+
+        let cascade = Cascade::new(stylesheets, viewport);
+        let mut restyle = Restyle::new(&cascade);
+        restyle.parallel = self.parallel;
+        self.data = Some(restyle.node(root, old, None, Invalidation::None, None, &[]));
+        self.version = Some(version);
+        self.viewport = Some(viewport.clone());
+        clear_dirty(root);
+        restyle.restyled
+    }
+
+    /// The style tree of `root`, which must be the document last passed to `restyle`.
+    pub fn style_tree<'a>(&self, root: &'a Node) -> StyleNode<'a> {
+        let data = self.data.clone().expect("Document has not been styled");
+        let mut root = build_style_node(root, data);
+        generate_content(&mut root);
+        root
+    }
 }
 
-fn specified_values(elem: &Element, stylesheet: &Stylesheet) -> PropertyMap {
-    let mut values = HashMap::new();
-    let mut rules = matching_rules(elem, stylesheet);
+// The computed styles of a node and of its descendants, with a child for each child node.
+#[derive(Clone)]
+struct StyleData {
+    shared: SharedStyle,
+    // Which selectors with sibling combinators or pseudo-classes matched the element, see
+    // `RuleMap::revalidate`. Empty for text.
+    revalidation: Vec<bool>,
+    children: Vec<StyleData>,
+}
 
-    rules.sort_by(|&(a, _), &(b, _)| a.cmp(&b));
+// What of a subtree must be styled again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Invalidation {
+    None,
+    // The siblings of the root of the subtree changed, which only matters if selectors with
+    // sibling combinators or pseudo-classes now match it differently.
+    Siblings,
+    // The style the root of the subtree inherits from changed.
+    Inherited,
+    // Everything, since rules may match differently.
+    Subtree,
+}
 
-    for (_, rule) in rules {
-        for declaration in &rule.declaration {
-            values.insert(declaration.name.clone(), declaration.value.clone());
+// Elements with fewer children are not worth splitting into parallel jobs.
+const PARALLEL_MIN_CHILDREN: usize = 8;
+
+// A pass computing the styles of the nodes that changed, and of those they affect.
+struct Restyle<'c, 'a> {
+    cascade: &'c Cascade<'a>,
+    // Ancestors of the node being styled.
+    filter: AncestorFilter,
+    // The style of the root element, which `rem` lengths are relative to, once it is known.
+    root: Option<Arc<ComputedStyle>>,
+    cache: StyleSharingCache,
+    // Number of elements whose style was computed.
+    restyled: usize,
+    // Whether to style the children of an element in parallel.
+    parallel: bool,
+    pass: u64,
+    // Whether this is a parallel job, which leaves its cache to later jobs on the thread.
+    job: bool,
+}
+
+impl Drop for Restyle<'_, '_> {
+    fn drop(&mut self) {
+        if self.job {
+            self.cache.put_thread_cache(self.pass);
         }
     }
+}
 
-    return values;
+impl<'c, 'a> Restyle<'c, 'a> {
+    fn new(cascade: &'c Cascade<'a>) -> Self {
+        Restyle {
+            cascade,
+            filter: AncestorFilter::new(),
+            root: None,
+            cache: StyleSharingCache::new(),
+            restyled: 0,
+            parallel: false,
+            pass: StyleSharingCache::new_pass(),
+            job: false,
+        }
+    }
+
+    // Style `node` given its previous styles `old`, if any, and what about them is invalid.
+    // `parent_context` is the parent element, and `preceding_siblings` the nodes before
+    // `node` among its children.
+    fn node(
+        &mut self,
+        node: &Node,
+        mut old: Option<StyleData>,
+        parent: Option<&Arc<ComputedStyle>>,
+        invalidation: Invalidation,
+        parent_context: Option<&ElementContext>,
+        preceding_siblings: &[Node],
+    ) -> StyleData {
+        let (registrations, viewport) = (&self.cascade.registrations, &self.cascade.viewport);
+        let root = self.root.as_deref();
+        let elem = match node {
+            Node::Element(ref elem) => elem,
+            // Text nodes have no declarations of their own and only inherit from their parent.
+            Node::Text(_) => {
+                if let Some(old) = old.filter(|_| invalidation <= Invalidation::Siblings) {
+                    return old;
+                }
+                let style = self.cache.text_style(parent, || {
+                    let parent = parent.map(|p| &**p);
+                    compute_values(HashMap::new(), parent, root, viewport, registrations)
+                });
+                return StyleData {
+                    shared: SharedStyle {
+                        style,
+                        marker: None,
+                        before: None,
+                        after: None,
+                    },
+                    revalidation: Vec::new(),
+                    children: Vec::new(),
+                };
+            }
+        };
+        let context = ElementContext {
+            element: elem,
+            parent: parent_context,
+            preceding_siblings,
+        };
+
+        // A changed attribute may change which rules match the element and its descendants,
+        // and so may changed siblings or children when the element's place among its siblings
+        // or `:empty` now matches rules differently.
+        let rules = &self.cascade.rules;
+        let (revalidation, rematched) = match old {
+            Some(ref mut old) if invalidation == Invalidation::None && !elem.dirty.children => {
+                (mem::take(&mut old.revalidation), false)
+            }
+            _ if !rules.has_sibling_selectors() => (Vec::new(), false),
+            Some(ref old) => {
+                let revalidation = rules.revalidate(&context);
+                let rematched = revalidation != old.revalidation;
+                (revalidation, rematched)
+            }
+            None => (rules.revalidate(&context), false),
+        };
+        let invalidation = match elem.dirty.attributes || rematched {
+            true => Invalidation::Subtree,
+            false => invalidation,
+        };
+        let old_font_size = old.as_ref().map(|old| old.shared.style.font.font_size);
+        let (shared, old_children, changed) = match old {
+            Some(old) if invalidation <= Invalidation::Siblings => {
+                (old.shared, old.children, false)
+            }
+            old => {
+                self.restyled += 1;
+                let (cascade, filter) = (self.cascade, &self.filter);
+                let shared = self
+                    .cache
+                    .element_style(&context, parent, &cascade.rules, || {
+                        element_style(&context, cascade, filter, parent, root)
+                    });
+                match old {
+                    // The element's own style is unchanged, so its descendants still inherit
+                    // the same values. The old style may be shared with other elements, which
+                    // only holds while the element and its ancestors keep their names, ids and
+                    // classes, see `StyleSharingCache`.
+                    Some(old)
+                        if invalidation < Invalidation::Subtree
+                            && *old.shared.style == *shared.style =>
+                    {
+                        let shared = SharedStyle {
+                            style: old.shared.style,
+                            ..shared
+                        };
+                        (shared, old.children, false)
+                    }
+                    old => (shared, old.map_or(Vec::new(), |old| old.children), true),
+                }
+            }
+        };
+        if parent_context.is_none() {
+            self.root = Some(shared.style.clone());
+        }
+        // `rem` lengths anywhere in the document are relative to the root's font size.
+        let invalidation = match parent_context {
+            None if old_font_size.is_some_and(|size| size != shared.style.font.font_size) => {
+                Invalidation::Subtree
+            }
+            _ => invalidation,
+        };
+        let old_children = line_up_children(old_children, elem);
+
+        // Inserted and removed children move their siblings, and rules with sibling
+        // combinators may match the siblings after a changed child differently.
+        let mut siblings_changed = elem.dirty.children;
+        let invalidations: Vec<Invalidation> = elem
+            .children
+            .iter()
+            .map(|child| {
+                let child_invalidation = match (invalidation, changed) {
+                    (Invalidation::Subtree, _) => Invalidation::Subtree,
+                    (_, true) => Invalidation::Inherited,
+                    _ if siblings_changed && rules.has_sibling_selectors() => {
+                        Invalidation::Siblings
+                    }
+                    _ => Invalidation::None,
+                };
+                if let Node::Element(ref child) = *child {
+                    siblings_changed |= child.dirty.attributes;
+                }
+                child_invalidation
+            })
+            .collect();
+        let children = elem.children.iter().zip(old_children).zip(invalidations);
+
+        self.filter.push(elem);
+        let parallel = self.parallel
+            && elem.children.len() >= PARALLEL_MIN_CHILDREN
+            && rayon::current_num_threads() > 1;
+        let children = if parallel {
+            // Each job styles its share of the children with its own copy of the ancestors,
+            // and shares styles with the jobs before it on the same thread.
+            let (cascade, filter, root, pass) = (self.cascade, &self.filter, &self.root, self.pass);
+            let styled: Vec<(StyleData, usize)> = children
+                .collect::<Vec<_>>()
+                .into_par_iter()
+                .enumerate()
+                .map_init(
+                    || Restyle {
+                        cascade,
+                        filter: filter.clone(),
+                        root: root.clone(),
+                        cache: StyleSharingCache::take_thread_cache(pass),
+                        restyled: 0,
+                        parallel: true,
+                        pass,
+                        job: true,
+                    },
+                    |restyle, (i, ((child, old), invalidation))| {
+                        let restyled = restyle.restyled;
+                        let data = restyle.node(
+                            child,
+                            old,
+                            Some(&shared.style),
+                            invalidation,
+                            Some(&context),
+                            &elem.children[..i],
+                        );
+                        (data, restyle.restyled - restyled)
+                    },
+                )
+                .collect();
+            styled
+                .into_iter()
+                .map(|(data, restyled)| {
+                    self.restyled += restyled;
+                    data
+                })
+                .collect()
+        } else {
+            children
+                .enumerate()
+                .map(|(i, ((child, old), invalidation))| {
+                    self.node(
+                        child,
+                        old,
+                        Some(&shared.style),
+                        invalidation,
+                        Some(&context),
+                        &elem.children[..i],
+                    )
+                })
+                .collect()
+        };
+        self.filter.pop(elem);
+
+        StyleData {
+            shared,
+            revalidation,
+            children,
+        }
+    }
 }
 
-pub fn style_tree<'a>(root: &'a Node, stylesheet: &'a Stylesheet) -> StyleNode<'a> {
-    StyleNode {
-        node: root,
-        style_values: match root {
-            Node::Element(ref elem) => specified_values(elem, stylesheet),
-            Node::Text(_) => HashMap::new(),
-        },
-        children: if let Node::Element(ref elem) = root {
+// The previous styles of the children of `elem`, or `None` for the children inserted since.
+fn line_up_children(old: Vec<StyleData>, elem: &Element) -> Vec<Option<StyleData>> {
+    let mut children: Vec<Option<StyleData>> = old.into_iter().map(Some).collect();
+    // Without previous styles there is nothing to line up.
+    if !children.is_empty() {
+        for change in &elem.dirty.child_changes {
+            match *change {
+                ChildChange::Inserted(index) if index <= children.len() => {
+                    children.insert(index, None)
+                }
+                ChildChange::Removed(index) if index < children.len() => {
+                    children.remove(index);
+                }
+                _ => {}
+            }
+        }
+    }
+    children.resize_with(elem.children.len(), || None);
+    children
+}
+
+fn clear_dirty(node: &mut Node) {
+    if let Node::Element(ref mut elem) = *node {
+        elem.dirty = Dirty::default();
+        elem.children.iter_mut().for_each(clear_dirty);
+    }
+}
+
+fn build_style_node(root: &Node, data: StyleData) -> StyleNode<'_> {
+    let pseudo_node = |pseudo, style| StyleNode {
+        node: Cow::Borrowed(root),
+        pseudo: Some(pseudo),
+        style,
+        children: Vec::new(),
+    };
+    let shared = data.shared;
+    let mut children = Vec::new();
+    children.extend(shared.marker.map(|s| pseudo_node(PseudoElement::Marker, s)));
+    children.extend(shared.before.map(|s| pseudo_node(PseudoElement::Before, s)));
+    if let Node::Element(ref elem) = *root {
+        children.extend(
             elem.children
                 .iter()
-                .map(|child| style_tree(child, stylesheet))
-                .collect()
+                .zip(data.children)
+                .map(|(child, data)| build_style_node(child, data)),
+        );
+    }
+    children.extend(shared.after.map(|s| pseudo_node(PseudoElement::After, s)));
+    StyleNode {
+        node: Cow::Borrowed(root),
+        pseudo: None,
+        style: shared.style,
+        children,
+    }
+}
+
+// Compute the style of an element and of its pseudo-elements. `root` is the style of the root
+// element, or `None` if this is the root element.
+fn element_style(
+    context: &ElementContext,
+    cascade: &Cascade,
+    filter: &AncestorFilter,
+    parent: Option<&Arc<ComputedStyle>>,
+    root: Option<&ComputedStyle>,
+) -> SharedStyle {
+    let style = Arc::new(compute_values(
+        specified_values(context, cascade, filter, None),
+        parent.map(|p| &**p),
+        root,
+        &cascade.viewport,
+        &cascade.registrations,
+    ));
+    // The pseudo-elements of the root element are relative to its style.
+    let root = root.unwrap_or(&style);
+    let pseudo = |pseudo| pseudo_style(context, cascade, filter, &style, root, pseudo);
+    let list_item = style.box_model.display == Display::ListItem;
+    SharedStyle {
+        marker: if list_item {
+            pseudo(PseudoElement::Marker)
         } else {
-            Vec::new()
+            None
         },
+        before: pseudo(PseudoElement::Before),
+        after: pseudo(PseudoElement::After),
+        style,
+    }
+}
+
+// Style a pseudo-element of an element. Its generated content is filled in later by
+// `generate_content`, once counters and quotes can be resolved in document order.
+fn pseudo_style(
+    context: &ElementContext,
+    cascade: &Cascade,
+    filter: &AncestorFilter,
+    parent: &ComputedStyle,
+    root: &ComputedStyle,
+    pseudo: PseudoElement,
+) -> Option<Arc<ComputedStyle>> {
+    // Pseudo-elements inherit from their originating element.
+    let style = compute_values(
+        specified_values(context, cascade, filter, Some(pseudo)),
+        Some(parent),
+        Some(root),
+        &cascade.viewport,
+        &cascade.registrations,
+    );
+
+    // `::before` and `::after` only generate a box when `content` is neither `normal` nor
+    // `none`; list items always get a marker.
+    if pseudo != PseudoElement::Marker {
+        if let Content::Normal | Content::None = style.content.content {
+            return None;
+        }
+    }
+    Some(Arc::new(style))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        css::{parse_css, parse_stylesheet, Color},
+        datatypes::Element,
+        dom::parse_element,
+        tokenizer::Tokenizer,
+    };
+
+    const PAGE: &str = "<html><body><div><span>a</span><span>b</span><span>c</span></div>\
+                        <p><em>d</em></p></body></html>";
+
+    fn parse_page(html: &str) -> Node {
+        let mut tokenizer = Tokenizer::new(html);
+        let mut tokens = Vec::new();
+        while let Some(token) = tokenizer.next_token() {
+            tokens.push(token);
+        }
+        Node::Element(parse_element(&tokens).0)
+    }
+
+    // The element reached by following the child indices in `path` from `node`.
+    fn element<'n>(node: &'n mut Node, path: &[usize]) -> &'n mut Element {
+        let Node::Element(ref mut elem) = *node else {
+            panic!("Not an element");
+        };
+        match path.split_first() {
+            Some((&index, rest)) => element(&mut elem.children[index], rest),
+            None => elem,
+        }
+    }
+
+    fn style<'s>(tree: &'s StyleNode, path: &[usize]) -> &'s ComputedStyle {
+        match path.split_first() {
+            Some((&index, rest)) => style(&tree.children[index], rest),
+            None => &tree.style,
+        }
+    }
+
+    // A styler that has styled `root` once.
+    fn styled(root: &mut Node, stylesheets: &[Stylesheet]) -> Styler {
+        let mut styler = Styler::new();
+        styler.restyle(root, stylesheets, &Viewport::new(800.0, 600.0));
+        styler
+    }
+
+    fn restyle(styler: &mut Styler, root: &mut Node, stylesheets: &[Stylesheet]) -> usize {
+        styler.restyle(root, stylesheets, &Viewport::new(800.0, 600.0))
+    }
+
+    const RED: Color = Color {
+        r: 255,
+        g: 0,
+        b: 0,
+        a: 255,
+    };
+
+    const GREEN: Color = Color {
+        r: 0,
+        g: 255,
+        b: 0,
+        a: 255,
+    };
+
+    const BLUE: Color = Color {
+        r: 0,
+        g: 0,
+        b: 255,
+        a: 255,
+    };
+
+    // The color of the paragraph `<p id="a" class="b">` with the `style` attribute `inline`.
+    fn paragraph_color(inline: &str, stylesheets: &[(Origin, &str)]) -> Color {
+        let root = parse_page(&format!(
+            "<html><body><p id=\"a\" class=\"b\" style=\"{}\">x</p></body></html>",
+            inline
+        ));
+        let stylesheets: Vec<Stylesheet> = stylesheets
+            .iter()
+            .map(|&(origin, css)| parse_stylesheet(css.to_string(), origin))
+            .collect();
+        let tree = style_tree(&root, &stylesheets, &Viewport::new(800.0, 600.0));
+        style(&tree, &[0, 0]).text.color.clone()
+    }
+
+    #[test]
+    fn author_rules_win_over_user_and_user_agent_rules() {
+        let user_agent = (Origin::UserAgent, "#a.b { color: #ff0000; }");
+        let user = (Origin::User, "p.b { color: #00ff00; }");
+        let author = (Origin::Author, "p { color: #0000ff; }");
+        assert_eq!(paragraph_color("", &[author, user, user_agent]), BLUE);
+        assert_eq!(paragraph_color("", &[user_agent, user]), GREEN);
+        assert_eq!(paragraph_color("", &[user_agent]), RED);
+    }
+
+    #[test]
+    fn later_rules_win_between_equal_selectors() {
+        let author = (
+            Origin::Author,
+            "p { color: #ff0000; } p { color: #00ff00; }",
+        );
+        assert_eq!(paragraph_color("", &[author]), GREEN);
+        let author = (
+            Origin::Author,
+            ".b { color: #ff0000; } p { color: #00ff00; }",
+        );
+        assert_eq!(paragraph_color("", &[author]), RED);
+    }
+
+    #[test]
+    fn important_declarations_reverse_the_origins() {
+        let user_agent = (Origin::UserAgent, "p { color: #ff0000 !important; }");
+        let user = (Origin::User, "p { color: #00ff00 !important; }");
+        let author = (Origin::Author, "#a { color: #0000ff !important; }");
+        assert_eq!(paragraph_color("", &[author, user, user_agent]), RED);
+        assert_eq!(paragraph_color("", &[author, user]), GREEN);
+        let author = (
+            Origin::Author,
+            "#a { color: #0000ff; } p { color: #ff0000 !important; }",
+        );
+        assert_eq!(paragraph_color("", &[author]), RED);
+    }
+
+    #[test]
+    fn later_layers_and_unlayered_rules_win() {
+        let css = "@layer a, b; @layer b { p { color: #00ff00; } } \
+                   @layer a { #a.b { color: #ff0000; } }";
+        assert_eq!(paragraph_color("", &[(Origin::Author, css)]), GREEN);
+        let css = "p { color: #0000ff; } @layer a { #a.b { color: #ff0000; } }";
+        assert_eq!(paragraph_color("", &[(Origin::Author, css)]), BLUE);
+        // Rules directly in a layer win over its sub-layers.
+        let css = "@layer a { p { color: #00ff00; } @layer b { #a { color: #ff0000; } } }";
+        assert_eq!(paragraph_color("", &[(Origin::Author, css)]), GREEN);
+    }
+
+    #[test]
+    fn important_declarations_reverse_the_layers() {
+        let css = "@layer a, b; @layer a { p { color: #ff0000 !important; } } \
+                   @layer b { p { color: #00ff00 !important; } } \
+                   #a { color: #0000ff !important; }";
+        assert_eq!(paragraph_color("", &[(Origin::Author, css)]), RED);
+    }
+
+    #[test]
+    fn the_style_attribute_wins_over_author_rules() {
+        let author = (Origin::Author, "#a.b { color: #ff0000; }");
+        assert_eq!(paragraph_color("color: #00ff00", &[author]), GREEN);
+        let author = (Origin::Author, "#a { color: #ff0000 !important; }");
+        assert_eq!(paragraph_color("color: #00ff00", &[author]), RED);
+        assert_eq!(
+            paragraph_color("color: #00ff00 !important", &[author]),
+            GREEN
+        );
+        let user = (Origin::User, "p { color: #0000ff !important; }");
+        assert_eq!(paragraph_color("color: #00ff00 !important", &[user]), BLUE);
+    }
+
+    #[test]
+    fn nothing_is_restyled_without_changes() {
+        let mut root = parse_page(PAGE);
+        let stylesheets = [parse_css("span + span { color: #ff0000; }".to_string())];
+        let mut styler = styled(&mut root, &stylesheets);
+
+        assert_eq!(restyle(&mut styler, &mut root, &stylesheets), 0);
+    }
+
+    #[test]
+    fn an_attribute_change_restyles_the_element() {
+        let mut root = parse_page(PAGE);
+        let stylesheets = [parse_css(".x { color: #ff0000; }".to_string())];
+        let mut styler = styled(&mut root, &stylesheets);
+
+        element(&mut root, &[0, 0, 1]).set_attribute("class", "x");
+        assert_eq!(restyle(&mut styler, &mut root, &stylesheets), 1);
+        let tree = styler.style_tree(&root);
+        assert_eq!(style(&tree, &[0, 0, 1]).text.color, RED);
+        assert_ne!(style(&tree, &[0, 0, 2]).text.color, RED);
+    }
+
+    #[test]
+    fn a_class_change_restyles_the_descendants() {
+        let mut root = parse_page(PAGE);
+        let stylesheets = [parse_css(".x em { font-style: italic; }".to_string())];
+        let mut styler = styled(&mut root, &stylesheets);
+
+        element(&mut root, &[0, 1]).set_attribute("class", "x");
+        // The `p` and its `em`.
+        assert_eq!(restyle(&mut styler, &mut root, &stylesheets), 2);
+    }
+
+    #[test]
+    fn an_inherited_property_reaches_the_descendants() {
+        let mut root = parse_page(PAGE);
+        let stylesheets = [parse_css("".to_string())];
+        let mut styler = styled(&mut root, &stylesheets);
+
+        element(&mut root, &[0, 0]).set_style_property("color", "#ff0000", false);
+        // The `div` and its three `span`s.
+        assert_eq!(restyle(&mut styler, &mut root, &stylesheets), 4);
+        let tree = styler.style_tree(&root);
+        assert_eq!(style(&tree, &[0, 0, 2]).text.color, RED);
+        assert_ne!(style(&tree, &[0, 1]).text.color, RED);
+    }
+
+    #[test]
+    fn sibling_rules_only_restyle_the_siblings_they_match_differently() {
+        let mut root = parse_page(PAGE);
+        let stylesheets = [parse_css(
+            "span:first-child { color: #ff0000; }".to_string(),
+        )];
+        let mut styler = styled(&mut root, &stylesheets);
+
+        let new = Node::Element(Element::new("span".to_string()));
+        element(&mut root, &[0, 0]).insert_child(0, new);
+        // The new `span`, and the one that is no longer the first child.
+        assert_eq!(restyle(&mut styler, &mut root, &stylesheets), 2);
+        let tree = styler.style_tree(&root);
+        assert_eq!(style(&tree, &[0, 0, 0]).text.color, RED);
+        assert_ne!(style(&tree, &[0, 0, 1]).text.color, RED);
+
+        element(&mut root, &[0, 0]).remove_child(0);
+        assert_eq!(restyle(&mut styler, &mut root, &stylesheets), 1);
+        let tree = styler.style_tree(&root);
+        assert_eq!(style(&tree, &[0, 0, 0]).text.color, RED);
+    }
+
+    #[test]
+    fn inserting_and_removing_children_keeps_the_styles_of_the_others() {
+        let mut root = parse_page(PAGE);
+        let stylesheets = [parse_css("span { color: #ff0000; }".to_string())];
+        let mut styler = styled(&mut root, &stylesheets);
+
+        let div = element(&mut root, &[0, 0]);
+        div.remove_child(1);
+        div.add_child(Node::Element(Element::new("span".to_string())));
+        div.insert_child(0, Node::Text("e".to_string()));
+        assert_eq!(restyle(&mut styler, &mut root, &stylesheets), 1);
+        let tree = styler.style_tree(&root);
+        assert_eq!(style(&tree, &[0, 0, 3]).text.color, RED);
+
+        element(&mut root, &[0, 0]).remove_child(3);
+        assert_eq!(restyle(&mut styler, &mut root, &stylesheets), 0);
+    }
+
+    #[test]
+    fn a_class_change_does_not_share_styles_with_the_old_class() {
+        let mut root = parse_page(
+            "<html><body><div class=\"x\"><span>a</span></div>\
+             <div class=\"x\"><span>b</span></div></body></html>",
+        );
+        let stylesheets = [parse_css(".y span { color: #ff0000; }".to_string())];
+        let mut styler = styled(&mut root, &stylesheets);
+
+        element(&mut root, &[0, 0]).set_attribute("class", "y");
+        element(&mut root, &[0, 1]).set_attribute("title", "b");
+        restyle(&mut styler, &mut root, &stylesheets);
+        let tree = styler.style_tree(&root);
+        assert_eq!(style(&tree, &[0, 0, 0]).text.color, RED);
+        assert_ne!(style(&tree, &[0, 1, 0]).text.color, RED);
+        let fresh = style_tree(&root, &stylesheets, &Viewport::new(800.0, 600.0));
+        assert_ne!(style(&fresh, &[0, 1, 0]).text.color, RED);
+    }
+
+    fn same_styles(a: &StyleNode, b: &StyleNode) -> bool {
+        a.style == b.style
+            && a.children.len() == b.children.len()
+            && a.children
+                .iter()
+                .zip(&b.children)
+                .all(|(a, b)| same_styles(a, b))
+    }
+
+    #[test]
+    fn parallel_styling_gives_the_same_styles() {
+        let items: String = (0..40)
+            .map(|i| {
+                format!(
+                    "<li class=\"c{}\"><em>{}</em> <span>x</span></li>",
+                    i % 3,
+                    i
+                )
+            })
+            .collect();
+        let mut root = parse_page(&format!("<html><body><ul>{}</ul></body></html>", items));
+        let stylesheets = [parse_css(
+            ".c1 em { color: #ff0000; } li + .c2 { font-size: 20px; } \
+             li:first-child span { font-weight: bold; } \
+             li::before { content: counter(item); counter-increment: item; }"
+                .to_string(),
+        )];
+        let viewport = Viewport::new(800.0, 600.0);
+        // Parallel styling only splits the work with more than one thread to run it on.
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(4)
+            .build()
+            .unwrap();
+
+        let mut sequential = Styler::new();
+        let mut parallel = Styler::new();
+        parallel.parallel = true;
+        sequential.restyle(&mut root, &stylesheets, &viewport);
+        pool.install(|| parallel.restyle(&mut root, &stylesheets, &viewport));
+        assert!(same_styles(
+            &sequential.style_tree(&root),
+            &parallel.style_tree(&root)
+        ));
+
+        element(&mut root, &[0, 0]).remove_child(0);
+        element(&mut root, &[0, 0, 5]).set_attribute("class", "c1");
+        let mut restyled = root.clone();
+        sequential.restyle(&mut root, &stylesheets, &viewport);
+        pool.install(|| parallel.restyle(&mut restyled, &stylesheets, &viewport));
+        assert!(same_styles(
+            &sequential.style_tree(&root),
+            &parallel.style_tree(&restyled)
+        ));
+        let fresh = style_tree(&root, &stylesheets, &viewport);
+        assert!(same_styles(&fresh, &parallel.style_tree(&restyled)));
+    }
+
+    #[test]
+    fn a_change_to_the_left_of_a_combinator_restyles_the_descendants() {
+        let mut root = parse_page(PAGE);
+        let stylesheets = [parse_css(
+            "p:first-child em { color: #ff0000; }".to_string(),
+        )];
+        let mut styler = styled(&mut root, &stylesheets);
+
+        element(&mut root, &[0]).remove_child(0);
+        // The `p` and its `em`.
+        assert_eq!(restyle(&mut styler, &mut root, &stylesheets), 2);
+        let tree = styler.style_tree(&root);
+        assert_eq!(style(&tree, &[0, 0, 0]).text.color, RED);
     }
 }
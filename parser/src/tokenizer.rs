@@ -1,10 +1,15 @@
 use crate::datatypes::Token;
 use std::str::Chars;
 
+// Elements whose content is raw text, in which nothing but their own end tag is markup.
+const RAW_TEXT_ELEMENTS: [&str; 2] = ["script", "style"];
+
 #[derive(Debug)]
 pub struct Tokenizer<'a> {
     chars: Chars<'a>,
     current_char: Option<char>,
+    // The name of the raw text element whose start tag was just read.
+    raw_text: Option<String>,
 }
 
 impl<'a> Tokenizer<'a> {
@@ -14,28 +19,50 @@ impl<'a> Tokenizer<'a> {
         Tokenizer {
             chars,
             current_char,
+            raw_text: None,
         }
     }
 
     pub fn next_token(&mut self) -> Option<Token> {
+        if let Some(name) = self.raw_text.take() {
+            let text = self.read_raw_text(&name);
+            if !text.is_empty() {
+                return Some(Token::Data(text));
+            }
+        }
+
+        // White space is kept in front of text, where it separates words, but produces no
+        // token between tags.
+        let mut whitespace = String::new();
         while let Some(c) = self.current_char {
             if c.is_whitespace() {
+                whitespace.push(c);
                 self.advance();
                 continue;
             }
 
             if c == '<' {
+                whitespace.clear();
                 self.advance();
                 if let Some(c) = self.current_char {
                     if c == '/' {
                         self.advance();
                         return Some(Token::ClosingTag(self.read_tag_name()));
+                    } else if c == '!' {
+                        // Comments and the doctype produce no tokens.
+                        self.skip_markup_declaration();
                     } else {
-                        return Some(Token::OpeningTag(self.read_tag_name()));
+                        let tag = self.read_tag_name();
+                        let name = tag.split_whitespace().next().unwrap_or_default();
+                        let name = name.to_ascii_lowercase();
+                        if RAW_TEXT_ELEMENTS.contains(&&*name) && !tag.trim_end().ends_with('/') {
+                            self.raw_text = Some(name);
+                        }
+                        return Some(Token::OpeningTag(tag));
                     }
                 }
             } else {
-                return Some(Token::Data(self.read_text()));
+                return Some(Token::Data(whitespace + &self.read_text()));
             }
         }
 
@@ -46,14 +73,76 @@ impl<'a> Tokenizer<'a> {
         self.current_char = self.chars.next();
     }
 
+    // Skip `<!-- ... -->` or `<!DOCTYPE ...>`, starting after the `<`. Quotes have no meaning
+    // in either, so a comment only ends at `-->` and a doctype at the first `>`.
+    fn skip_markup_declaration(&mut self) {
+        self.advance();
+        let comment = self.current_char == Some('-') && self.chars.clone().next() == Some('-');
+        if !comment {
+            while let Some(c) = self.current_char {
+                self.advance();
+                if c == '>' {
+                    break;
+                }
+            }
+            return;
+        }
+
+        self.advance();
+        self.advance();
+        // The dashes that open the comment count towards the ones that close it, so that
+        // `<!-->` and `<!--->` are empty comments.
+        let mut dashes = 2;
+        while let Some(c) = self.current_char {
+            self.advance();
+            match c {
+                '-' => dashes += 1,
+                '>' if dashes >= 2 => break,
+                _ => dashes = 0,
+            }
+        }
+    }
+
+    // Read the text of a raw text element up to its end tag.
+    fn read_raw_text(&mut self, name: &str) -> String {
+        let mut text = String::new();
+        while let Some(c) = self.current_char {
+            if c == '<' && self.at_end_tag(name) {
+                break;
+            }
+            text.push(c);
+            self.advance();
+        }
+        text
+    }
+
+    // Whether the `<` at the current position starts the end tag `</name>`, in any case.
+    fn at_end_tag(&self, name: &str) -> bool {
+        let mut rest = self.chars.clone();
+        rest.next() == Some('/')
+            && name
+                .chars()
+                .all(|c| rest.next().is_some_and(|r| r.eq_ignore_ascii_case(&c)))
+            && rest
+                .next()
+                .is_none_or(|c| c == '>' || c == '/' || c.is_whitespace())
+    }
+
     fn read_tag_name(&mut self) -> String {
         let mut tag_name = String::new();
+        // A `>` inside a quoted attribute value does not end the tag.
+        let mut quote = None;
 
         while let Some(c) = self.current_char {
-            if c == '>' {
+            if c == '>' && quote.is_none() {
                 self.advance();
                 break;
             } else {
+                match (quote, c) {
+                    (None, '"' | '\'') => quote = Some(c),
+                    (Some(q), c) if q == c => quote = None,
+                    _ => {}
+                }
                 tag_name.push(c);
                 self.advance();
             }
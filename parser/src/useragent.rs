@@ -0,0 +1,125 @@
+use std::sync::OnceLock;
+
+use crate::css::{parse_stylesheet, Origin, Stylesheet};
+
+const USER_AGENT_CSS: &str = include_str!("useragent.css");
+
+/// The built-in default stylesheet, parsed on first use.
+pub fn user_agent_stylesheet() -> &'static Stylesheet {
+    static STYLESHEET: OnceLock<Stylesheet> = OnceLock::new();
+    STYLESHEET.get_or_init(|| parse_stylesheet(USER_AGENT_CSS.to_string(), Origin::UserAgent))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{
+        computed::{LengthPercentage, LengthPercentageOrAuto},
+        css::parse_css,
+        datatypes::Node,
+        dom::parse_element,
+        media::Viewport,
+        properties::ComputedStyle,
+        styles::{style_tree, Display, StyleNode},
+        tokenizer::Tokenizer,
+    };
+
+    // The display and style of each element of `html` styled with `css`, by tag name, in
+    // document order.
+    fn styles(html: &str, css: &str) -> Vec<(String, Display, Arc<ComputedStyle>)> {
+        let mut tokenizer = Tokenizer::new(html);
+        let mut tokens = Vec::new();
+        while let Some(token) = tokenizer.next_token() {
+            tokens.push(token);
+        }
+        let root = Node::Element(parse_element(&tokens).0);
+        let stylesheets = [parse_css(css.to_string())];
+        let tree = style_tree(&root, &stylesheets, &Viewport::new(800.0, 600.0));
+        let mut styles = Vec::new();
+        collect(&tree, &mut styles);
+        styles
+    }
+
+    fn collect(node: &StyleNode, styles: &mut Vec<(String, Display, Arc<ComputedStyle>)>) {
+        if let (Node::Element(ref element), None) = (&*node.node, node.pseudo) {
+            styles.push((element.name.clone(), node.display(), node.style.clone()));
+        }
+        for child in &node.children {
+            collect(child, styles);
+        }
+    }
+
+    fn px(n: f32) -> LengthPercentageOrAuto {
+        LengthPercentageOrAuto::LengthPercentage(LengthPercentage::Length(n))
+    }
+
+    #[test]
+    fn elements_get_their_display_types() {
+        let html = "<html><head><title>t</title><style>p {}</style></head><body>\
+                    <div><p>a <em>b</em> <span>c</span></p><ul><li>d</li></ul>\
+                    <script>e</script></div></body></html>";
+        let displays: Vec<(String, Display)> = styles(html, "")
+            .into_iter()
+            .map(|(name, display, _)| (name, display))
+            .collect();
+        let expected = [
+            ("html", Display::Block),
+            ("head", Display::None),
+            ("title", Display::None),
+            ("style", Display::None),
+            ("body", Display::Block),
+            ("div", Display::Block),
+            ("p", Display::Block),
+            ("em", Display::Inline),
+            ("span", Display::Inline),
+            ("ul", Display::Block),
+            ("li", Display::ListItem),
+            ("script", Display::None),
+        ];
+        let expected: Vec<(String, Display)> = expected
+            .iter()
+            .map(|&(name, display)| (name.to_string(), display))
+            .collect();
+        assert_eq!(displays, expected);
+    }
+
+    #[test]
+    fn headings_lists_and_the_body_have_default_sizes() {
+        let html = "<html><body><h1>a</h1><h2>b</h2><h6>c</h6><ol><li>d</li></ol>\
+                    <p><strong>e</strong></p></body></html>";
+        let styles = styles(html, "");
+        let style = |name: &str| {
+            let (_, _, style) = styles.iter().find(|(n, _, _)| n == name).unwrap();
+            style.clone()
+        };
+        assert_eq!(style("body").box_model.margin_left, px(8.0));
+        assert_eq!(style("h1").font.font_size, 32.0);
+        assert_eq!(style("h1").font.font_weight, 700);
+        assert_eq!(style("h1").box_model.margin_top, px(32.0 * 0.67));
+        assert_eq!(style("h2").font.font_size, 24.0);
+        assert!((style("h6").font.font_size - 16.0 * 0.67).abs() < 0.01);
+        assert_eq!(style("p").box_model.margin_top, px(16.0));
+        assert_eq!(style("strong").font.font_weight, 700);
+        assert_eq!(
+            style("ol").box_model.padding_left,
+            LengthPercentage::Length(40.0)
+        );
+        assert_eq!(style("li").text.list_style_type, "decimal");
+    }
+
+    #[test]
+    fn author_rules_override_the_defaults() {
+        let html = "<html><head><title>t</title></head><body><h1>a</h1></body></html>";
+        let css = "head { display: block } h1 { font-size: 10px; margin-top: 0px }";
+        let styles = styles(html, css);
+        assert_eq!(styles[1].0, "head");
+        assert_eq!(styles[1].1, Display::Block);
+        // The title is still hidden.
+        assert_eq!(styles[2].1, Display::None);
+        let (_, _, h1) = &styles[4];
+        assert_eq!(h1.font.font_size, 10.0);
+        assert_eq!(h1.font.font_weight, 700);
+        assert_eq!(h1.box_model.margin_top, px(0.0));
+    }
+}
@@ -0,0 +1,426 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    calc::CalcType,
+    computed::is_css_wide_keyword,
+    css::{parse_value, Declaration, PropertyRegistration, Unit, Value},
+    properties::expand_shorthand,
+    styles::PropertyMap,
+};
+
+/// The custom properties registered with `@property`, by name.
+pub type Registrations<'a> = HashMap<&'a str, &'a PropertyRegistration>;
+
+pub fn is_custom_property(name: &str) -> bool {
+    name.starts_with("--")
+}
+
+/// Whether `text` references a custom property with `var()`.
+pub fn contains_var(text: &str) -> bool {
+    find_var(text).is_some()
+}
+
+/// Compute the custom properties of an element from its cascaded custom properties: apply
+/// inheritance and the CSS-wide keywords, then substitute the `var()` references between
+/// them. Returns the text of every custom property with a valid value.
+///
+/// Properties that reference each other in a cycle are invalid, as are properties that
+/// reference an invalid property without a fallback.
+pub fn custom_properties(
+    cascaded: PropertyMap,
+    parent: Option<&PropertyMap>,
+    registrations: &Registrations,
+) -> HashMap<String, String> {
+    let inherits = |name: &str| registrations.get(name).map_or(true, |r| r.inherits);
+    let initial = |name: &str| {
+        registrations
+            .get(name)
+            .and_then(|r| r.initial.as_ref())
+            .map(|value| value.to_string())
+    };
+    // The parent's values are computed already, so they contain no more references.
+    let inherited = |name: &str| {
+        parent
+            .and_then(|p| p.get(name))
+            .map(value_text)
+            .or_else(|| initial(name))
+    };
+
+    let mut resolved: HashMap<String, Option<String>> = HashMap::new();
+    if let Some(parent) = parent {
+        for name in parent.keys().filter(|name| is_custom_property(name)) {
+            if inherits(name) {
+                resolved.insert(name.clone(), inherited(name));
+            }
+        }
+    }
+    for &name in registrations.keys() {
+        if !resolved.contains_key(name) {
+            resolved.insert(name.to_string(), initial(name));
+        }
+    }
+
+    let mut specified = HashMap::new();
+    for (name, value) in cascaded {
+        let text = value_text(&value);
+        let value = match text.trim() {
+            "inherit" => inherited(&name),
+            "initial" => initial(&name),
+            "unset" | "revert" if inherits(&name) => inherited(&name),
+            "unset" | "revert" => initial(&name),
+            _ => {
+                resolved.remove(&name);
+                specified.insert(name, text);
+                continue;
+            }
+        };
+        resolved.insert(name, value);
+    }
+
+    let mut resolver = Resolver {
+        specified,
+        resolved,
+        stack: Vec::new(),
+        cyclic: HashSet::new(),
+    };
+    let names: Vec<String> = resolver.specified.keys().cloned().collect();
+    for name in names {
+        resolver.resolve(&name);
+    }
+    resolver
+        .resolved
+        .into_iter()
+        .filter_map(|(name, value)| value.map(|value| (name, value)))
+        .collect()
+}
+
+/// Substitute the computed custom properties into a value that contains `var()` and parse
+/// the result. Returns `None` if a reference cannot be substituted, which makes the value
+/// invalid at computed-value time.
+///
+/// `name` is the longhand the value is for, which picks its part of a shorthand's value.
+pub fn substitute_vars(
+    name: &str,
+    value: Value,
+    custom: &HashMap<String, String>,
+) -> Option<Value> {
+    let mut lookup = |var: &str| custom.get(var).cloned();
+    match value {
+        Value::Unparsed(text) => substitute(&text, &mut lookup).and_then(|text| parse_value(&text)),
+        Value::PendingShorthand(shorthand, text) => {
+            let declaration = Declaration {
+                name: shorthand,
+                value: parse_value(&substitute(&text, &mut lookup)?)?,
+                important: false,
+            };
+            expand_shorthand(&declaration)
+                .into_iter()
+                .find(|(longhand, _)| longhand == name)
+                .map(|(_, value)| value)
+        }
+        value => Some(value),
+    }
+}
+
+/// Whether `value` matches the syntax of a registered custom property, such as `<length>`,
+/// `<color>+` or `small | medium | large`.
+pub fn matches_syntax(syntax: &str, value: &Value) -> bool {
+    let syntax = syntax.trim();
+    if syntax == "*" {
+        return true;
+    }
+    syntax
+        .split('|')
+        .map(str::trim)
+        .any(|component| match (component.strip_suffix('+'), value) {
+            (Some(single), Value::List(items)) => {
+                items.iter().all(|item| matches_component(single, item))
+            }
+            (Some(single), value) => matches_component(single, value),
+            (None, value) => matches_component(component, value),
+        })
+}
+
+fn matches_component(component: &str, value: &Value) -> bool {
+    match (component, value) {
+        ("<length>", Value::Length(_, unit)) if *unit != Unit::Pr => true,
+        ("<length>" | "<length-percentage>", Value::Number(n)) => *n == 0.0,
+        ("<percentage>", Value::Length(_, Unit::Pr)) => true,
+        ("<length-percentage>", Value::Length(..)) => true,
+        ("<number>", Value::Number(_)) => true,
+        ("<integer>", Value::Number(n)) => n.fract() == 0.0,
+        ("<color>", Value::Color(_)) => true,
+        ("<color>", Value::Keyword(k)) => k == "currentcolor" || k == "transparent",
+        ("<string>", Value::Str(_)) => true,
+        ("<custom-ident>", Value::Keyword(k)) => !is_css_wide_keyword(k),
+        (component, Value::Calc(calc)) => matches!(
+            (component, calc.check()),
+            ("<length>", Some(CalcType::Length))
+                | ("<percentage>", Some(CalcType::Percentage))
+                | ("<number>", Some(CalcType::Number))
+                | (
+                    "<length-percentage>",
+                    Some(CalcType::Length | CalcType::Percentage)
+                )
+                | ("<length-percentage>", Some(CalcType::LengthPercentage))
+        ),
+        (ident, Value::Keyword(k)) => !ident.starts_with('<') && ident == k,
+        _ => false,
+    }
+}
+
+fn value_text(value: &Value) -> String {
+    match value {
+        Value::Unparsed(text) => text.clone(),
+        value => value.to_string(),
+    }
+}
+
+struct Resolver {
+    // Unresolved text of the custom properties specified on the element.
+    specified: HashMap<String, String>,
+    // Resolved values, `None` for invalid properties.
+    resolved: HashMap<String, Option<String>>,
+    // Properties being resolved, each referenced by the one before it.
+    stack: Vec<String>,
+    cyclic: HashSet<String>,
+}
+
+impl Resolver {
+    fn resolve(&mut self, name: &str) -> Option<String> {
+        if let Some(value) = self.resolved.get(name) {
+            return value.clone();
+        }
+        if let Some(start) = self.stack.iter().position(|n| n == name) {
+            self.cyclic.extend(self.stack[start..].iter().cloned());
+            return None;
+        }
+        let text = self.specified.get(name)?.clone();
+
+        self.stack.push(name.to_string());
+        let value = substitute(&text, &mut |var| self.resolve(var));
+        self.stack.pop();
+
+        let value = value.filter(|_| !self.cyclic.contains(name));
+        self.resolved.insert(name.to_string(), value.clone());
+        value
+    }
+}
+
+// Replace each `var(--name, fallback)` in `text` by the value `lookup` gives for the name or,
+// failing that, by its fallback.
+fn substitute(text: &str, lookup: &mut dyn FnMut(&str) -> Option<String>) -> Option<String> {
+    let mut result = String::new();
+    let mut rest = text;
+    while let Some(start) = find_var(rest) {
+        result.push_str(&rest[..start]);
+        let arguments_start = start + "var(".len();
+        let end = arguments_start + closing_parenthesis(&rest[arguments_start..])?;
+
+        let arguments = &rest[arguments_start..end];
+        let (name, fallback) = match arguments.split_once(',') {
+            Some((name, fallback)) => (name.trim(), Some(fallback.trim())),
+            None => (arguments.trim(), None),
+        };
+        let value = match lookup(name) {
+            Some(value) => value,
+            None => substitute(fallback?, lookup)?,
+        };
+        result.push_str(&value);
+        rest = &rest[end + 1..];
+    }
+    result.push_str(rest);
+    Some(result)
+}
+
+// The position of the next `var(` outside of strings.
+fn find_var(text: &str) -> Option<usize> {
+    let mut quote = None;
+    let mut previous = ' ';
+    for (i, c) in text.char_indices() {
+        match quote {
+            Some(q) if c == q && previous != '\\' => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if !is_name_char(previous)
+                && text
+                    .get(i..i + 4)
+                    .map_or(false, |s| s.eq_ignore_ascii_case("var(")) =>
+            {
+                return Some(i)
+            }
+            None => {}
+        }
+        previous = c;
+    }
+    None
+}
+
+// The position of the `)` closing a parenthesis that is open at the start of `text`.
+fn closing_parenthesis(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut quote = None;
+    let mut previous = ' ';
+    for (i, c) in text.char_indices() {
+        match quote {
+            Some(q) if c == q && previous != '\\' => quote = None,
+            Some(_) => {}
+            None => match c {
+                '"' | '\'' => quote = Some(c),
+                '(' => depth += 1,
+                ')' if depth == 0 => return Some(i),
+                ')' => depth -= 1,
+                _ => {}
+            },
+        }
+        previous = c;
+    }
+    None
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '-' || c == '_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        css::{parse_css, Color},
+        datatypes::Node,
+        dom::parse_element,
+        media::Viewport,
+        styles::style_tree,
+        tokenizer::Tokenizer,
+    };
+
+    fn cascaded(declarations: &[(&str, &str)]) -> PropertyMap {
+        declarations
+            .iter()
+            .map(|&(name, text)| (name.to_string(), Value::Unparsed(text.to_string())))
+            .collect()
+    }
+
+    #[test]
+    fn fallbacks_replace_missing_properties() {
+        let custom = custom_properties(
+            cascaded(&[
+                ("--a", "var(--missing, 1px)"),
+                ("--b", "var(--missing, var(--a))"),
+            ]),
+            None,
+            &Registrations::new(),
+        );
+        assert_eq!(custom["--a"], "1px");
+        assert_eq!(custom["--b"], "1px");
+
+        let width = Value::Unparsed("var(--missing, 2px)".to_string());
+        assert_eq!(
+            substitute_vars("width", width, &custom),
+            Some(Value::Length(2.0, Unit::Px))
+        );
+        let width = Value::Unparsed("var(--missing)".to_string());
+        assert_eq!(substitute_vars("width", width, &custom), None);
+    }
+
+    #[test]
+    fn properties_in_a_cycle_are_invalid() {
+        let custom = custom_properties(
+            cascaded(&[
+                ("--a", "var(--b)"),
+                ("--b", "var(--a)"),
+                ("--self", "1px var(--self)"),
+                ("--c", "var(--a, 1px)"),
+                ("--d", "var(--a)"),
+            ]),
+            None,
+            &Registrations::new(),
+        );
+        assert!(!custom.contains_key("--a"));
+        assert!(!custom.contains_key("--b"));
+        assert!(!custom.contains_key("--self"));
+        assert!(!custom.contains_key("--d"));
+        // A reference to an invalid property takes its fallback.
+        assert_eq!(custom["--c"], "1px");
+
+        let color = Value::Unparsed("var(--a)".to_string());
+        assert_eq!(substitute_vars("color", color, &custom), None);
+    }
+
+    #[test]
+    fn shorthands_with_references_are_split_once_substituted() {
+        let custom =
+            custom_properties(cascaded(&[("--x", "1px 2px")]), None, &Registrations::new());
+        let margin = Value::PendingShorthand("margin".to_string(), "var(--x)".to_string());
+        assert_eq!(
+            substitute_vars("margin-left", margin, &custom),
+            Some(Value::Length(2.0, Unit::Px))
+        );
+    }
+
+    #[test]
+    fn registered_syntaxes() {
+        let value = |text: &str| parse_value(text).unwrap();
+        assert!(matches_syntax("<length>", &value("2em")));
+        assert!(!matches_syntax("<length>", &value("50%")));
+        assert!(matches_syntax(
+            "<length-percentage>",
+            &value("calc(50% - 1em)")
+        ));
+        assert!(matches_syntax("<color>+", &value("#ff0000 currentcolor")));
+        assert!(matches_syntax("small | medium", &value("medium")));
+        assert!(!matches_syntax("small | medium", &value("large")));
+        assert!(!matches_syntax("<integer>", &value("1.5")));
+        assert!(matches_syntax("*", &value("anything at all")));
+    }
+
+    // The computed custom property `name` of the `p` in `<body><p>` styled with `css`.
+    fn computed(css: &str, name: &str) -> Option<Value> {
+        let mut tokenizer = Tokenizer::new("<html><body><p>x</p></body></html>");
+        let mut tokens = Vec::new();
+        while let Some(token) = tokenizer.next_token() {
+            tokens.push(token);
+        }
+        let root = Node::Element(parse_element(&tokens).0);
+        let stylesheets = [parse_css(css.to_string())];
+        let tree = style_tree(&root, &stylesheets, &Viewport::new(800.0, 600.0));
+        tree.children[0].children[0].style.custom.get(name).cloned()
+    }
+
+    #[test]
+    fn registered_properties_are_typed_values() {
+        let registration =
+            "@property --size { syntax: '<length>'; inherits: false; initial-value: 10px; }";
+        let css = format!("{} p {{ --size: 2em; }}", registration);
+        assert_eq!(
+            computed(&css, "--size"),
+            Some(Value::Length(32.0, Unit::Px))
+        );
+        // Invalid values and values that are not inherited give the initial value.
+        let css = format!("{} p {{ --size: red; }}", registration);
+        assert_eq!(
+            computed(&css, "--size"),
+            Some(Value::Length(10.0, Unit::Px))
+        );
+        let css = format!("{} body {{ --size: 5px; }}", registration);
+        assert_eq!(
+            computed(&css, "--size"),
+            Some(Value::Length(10.0, Unit::Px))
+        );
+
+        let css = "@property --tint { syntax: '<color>'; inherits: true; \
+                   initial-value: #000000; } body { --tint: #ff0000; }";
+        let red = Color {
+            r: 255,
+            g: 0,
+            b: 0,
+            a: 255,
+        };
+        assert_eq!(computed(css, "--tint"), Some(Value::Color(red)));
+        // Unregistered properties inherit their text as it is.
+        assert_eq!(
+            computed("body { --gap: 2em; }", "--gap"),
+            Some(Value::Unparsed("2em".to_string()))
+        );
+    }
+}
@@ -1,16 +1,26 @@
+use crate::boxmodel;
+use crate::computed::TextDecorationStyle;
+use crate::css;
 use crate::display::DisplayCommand;
-use sdl2::event::Event;
+use crate::fontdb::{FontDatabase, FontInstance};
+use crate::shaping::{Glyph, GlyphRun};
+use sdl2::event::{Event, WindowEvent};
 use sdl2::keyboard::Keycode;
-use sdl2::pixels::Color;
+use sdl2::pixels::{Color, PixelFormatEnum};
 use sdl2::rect::Rect;
-use sdl2::ttf::Font;
-use std::collections::HashMap;
+use sdl2::render::{BlendMode, Canvas, Texture, TextureCreator};
+use sdl2::video::{Window, WindowContext};
+use std::collections::hash_map::{Entry, HashMap};
+use std::f32::consts::TAU;
 use std::time::Duration;
 
+/// Open a window and draw the display list produced by `render` for the window's size, with
+/// glyphs from the faces of `fonts`. The display list is rebuilt whenever the window is resized.
 pub fn make_window(
     width: u32,
     height: u32,
-    displaylist: Vec<DisplayCommand>,
+    fonts: &FontDatabase,
+    mut render: impl FnMut(u32, u32) -> Vec<DisplayCommand>,
 ) -> Result<(), String> {
     let sdl_context = sdl2::init()?;
     let video_subsystem = sdl_context.video()?;
@@ -18,22 +28,25 @@ pub fn make_window(
     let window = video_subsystem
         .window("Positron", width, height)
         .position_centered()
+        .resizable()
         .opengl()
         .build()
         .map_err(|e| e.to_string())?;
 
     let mut canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
 
-    let ttf_context = sdl2::ttf::init().map_err(|e| e.to_string())?;
-
-    let mut FontMap: HashMap<u16, Font> = HashMap::new();
-
     let texture_creator = canvas.texture_creator();
+    let mut glyphs = GlyphCache {
+        texture_creator: &texture_creator,
+        fonts,
+        glyphs: HashMap::new(),
+    };
 
     canvas.set_draw_color(Color::RGB(255, 255, 255));
     canvas.clear();
     canvas.present();
     let mut event_pump = sdl_context.event_pump()?;
+    let mut displaylist = render(width, height);
 
     'running: loop {
         for event in event_pump.poll_iter() {
@@ -43,6 +56,10 @@ pub fn make_window(
                     keycode: Some(Keycode::Escape),
                     ..
                 } => break 'running,
+                Event::Window {
+                    win_event: WindowEvent::Resized(w, h),
+                    ..
+                } => displaylist = render(w as u32, h as u32),
                 _ => {}
             }
         }
@@ -61,57 +78,15 @@ pub fn make_window(
                         rect.height as u32,
                     ))?;
                 }
-                DisplayCommand::Text(color, rect, text) => {
-                    let pointheight = rect.height * 0.75;
-
-                    if let Some(font) = FontMap.get(&(pointheight as u16)) {
-                        let surface = font
-                            .render(text)
-                            .blended(Color::RGBA(color.r, color.g, color.b, color.a))
-                            .map_err(|e| e.to_string())?;
-
-                        let texture = texture_creator
-                            .create_texture_from_surface(&surface)
-                            .map_err(|e| e.to_string())?;
-
-                        canvas.copy(
-                            &texture,
-                            None,
-                            Some(Rect::new(
-                                rect.x as i32,
-                                rect.y as i32,
-                                surface.width() as u32,
-                                surface.height() as u32,
-                            )),
-                        )?;
-                    } else {
-                        let pointheight = rect.height * 0.75;
-                        let f = ttf_context
-                            .load_font("./assets/OpenSans-Regular.ttf", pointheight as u16)?;
-                        FontMap.insert(pointheight as u16, f);
-
-                        if let Some(font) = FontMap.get(&(pointheight as u16)) {
-                            let surface = font
-                                .render(text)
-                                .blended(Color::RGBA(color.r, color.g, color.b, color.a))
-                                .map_err(|e| e.to_string())?;
-
-                            let texture = texture_creator
-                                .create_texture_from_surface(&surface)
-                                .map_err(|e| e.to_string())?;
-
-                            canvas.copy(
-                                &texture,
-                                None,
-                                Some(Rect::new(
-                                    rect.x as i32,
-                                    rect.y as i32,
-                                    surface.width() as u32,
-                                    surface.height() as u32,
-                                )),
-                            )?;
-                        }
-                    };
+                DisplayCommand::Text(color, rect, run) => {
+                    glyphs.draw_run(&mut canvas, color, rect, run, 0.0)?;
+                }
+                DisplayCommand::TextShadow(color, rect, run, blur) => {
+                    glyphs.draw_run(&mut canvas, color, rect, run, *blur)?;
+                }
+                DisplayCommand::Line(color, rect, style) => {
+                    canvas.set_draw_color(Color::RGBA(color.r, color.g, color.b, color.a));
+                    canvas.fill_rects(&line_rects(rect, *style))?;
                 }
             }
         }
@@ -119,3 +94,144 @@ pub fn make_window(
     }
     Ok(())
 }
+
+// Glyphs of the faces of `fonts` drawn into textures, by face, glyph, font size and blur
+// radius, or `None` for glyphs without an outline.
+struct GlyphCache<'t, 'f> {
+    texture_creator: &'t TextureCreator<WindowContext>,
+    fonts: &'f FontDatabase,
+    glyphs: HashMap<(FontInstance, u16, u32, u32), Option<GlyphTexture<'t>>>,
+}
+
+impl GlyphCache<'_, '_> {
+    // Draw the glyphs of `run` in `color` from the top left of `rect`, blurred by a radius of
+    // `blur`.
+    fn draw_run(
+        &mut self,
+        canvas: &mut Canvas<Window>,
+        color: &css::Color,
+        rect: &boxmodel::Rect,
+        run: &GlyphRun,
+        blur: f32,
+    ) -> Result<(), String> {
+        for glyph in &run.glyphs {
+            let key = (
+                glyph.font,
+                glyph.id,
+                run.font_size.to_bits(),
+                blur.to_bits(),
+            );
+            let drawn = match self.glyphs.entry(key) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => entry.insert(draw_glyph(
+                    self.texture_creator,
+                    self.fonts,
+                    glyph,
+                    run.font_size,
+                    blur,
+                )?),
+            };
+            if let Some(GlyphTexture { texture, left, top }) = drawn {
+                texture.set_color_mod(color.r, color.g, color.b);
+                texture.set_alpha_mod(color.a);
+                let query = texture.query();
+                canvas.copy(
+                    texture,
+                    None,
+                    Some(Rect::new(
+                        (rect.x + glyph.x).round() as i32 + *left,
+                        (rect.y + glyph.y).round() as i32 + *top,
+                        query.width,
+                        query.height,
+                    )),
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// The pixels a decoration line along `rect` covers in `style`.
+fn line_rects(rect: &boxmodel::Rect, style: TextDecorationStyle) -> Vec<Rect> {
+    let thickness = rect.height.round().max(1.0);
+    let band = |x: f32, y: f32, width: f32| {
+        Rect::new(
+            x.round() as i32,
+            y.round() as i32,
+            width.round().max(1.0) as u32,
+            thickness as u32,
+        )
+    };
+    let end = rect.x + rect.width;
+    // Dots and dashes, `on` long and `period` apart.
+    let pattern = |on: f32, period: f32| {
+        let mut rects = Vec::new();
+        let mut x = rect.x;
+        while x < end {
+            rects.push(band(x, rect.y, on.min(end - x)));
+            x += period;
+        }
+        rects
+    };
+    match style {
+        TextDecorationStyle::Solid => vec![band(rect.x, rect.y, rect.width)],
+        TextDecorationStyle::Double => vec![
+            band(rect.x, rect.y, rect.width),
+            band(rect.x, rect.y + 2.0 * thickness, rect.width),
+        ],
+        TextDecorationStyle::Dotted => pattern(thickness, 2.0 * thickness),
+        TextDecorationStyle::Dashed => pattern(3.0 * thickness, 5.0 * thickness),
+        TextDecorationStyle::Wavy => {
+            // A column of pixels at a time, along a sine wave.
+            let amplitude = thickness;
+            let wavelength = 6.0 * thickness.max(2.0);
+            (0..rect.width.round() as i32)
+                .map(|i| {
+                    let wave = (i as f32 * TAU / wavelength).sin() * amplitude;
+                    band(rect.x + i as f32, rect.y + wave, 1.0)
+                })
+                .collect()
+        }
+    }
+}
+
+// A drawn glyph and where its top left pixel goes from the glyph origin.
+struct GlyphTexture<'t> {
+    texture: Texture<'t>,
+    left: i32,
+    top: i32,
+}
+
+// Draw `glyph` into a texture of white pixels whose alpha is the coverage, which is tinted
+// with the text color when copied to the canvas. Shadows are blurred by a radius of `blur`.
+fn draw_glyph<'t>(
+    texture_creator: &'t TextureCreator<WindowContext>,
+    fonts: &FontDatabase,
+    glyph: &Glyph,
+    font_size: f32,
+    blur: f32,
+) -> Result<Option<GlyphTexture<'t>>, String> {
+    let face = fonts.face(glyph.font.face);
+    let image = match face.rasterize(glyph.id, font_size, glyph.font.synthesis) {
+        Some(image) if blur > 0.0 => image.blurred(blur),
+        Some(image) => image,
+        None => return Ok(None),
+    };
+    let pixels: Vec<u8> = image
+        .coverage
+        .iter()
+        .flat_map(|&alpha| [255, 255, 255, alpha])
+        .collect();
+    let mut texture = texture_creator
+        .create_texture_static(PixelFormatEnum::RGBA32, image.width, image.height)
+        .map_err(|e| e.to_string())?;
+    texture
+        .update(None, &pixels, image.width as usize * 4)
+        .map_err(|e| e.to_string())?;
+    texture.set_blend_mode(BlendMode::Blend);
+    Ok(Some(GlyphTexture {
+        texture,
+        left: image.left,
+        top: image.top,
+    }))
+}
@@ -0,0 +1,629 @@
+//! WOFF and WOFF2, the compressed packagings of TrueType and OpenType fonts used on the web,
+//! unpacked back into the font file they were made from.
+
+use std::io::Read;
+
+const WOFF: &[u8] = b"wOFF";
+const WOFF2: &[u8] = b"wOF2";
+
+// Tags of the tables a WOFF2 directory can refer to by index instead of spelling out.
+const KNOWN_TAGS: [&[u8; 4]; 63] = [
+    b"cmap", b"head", b"hhea", b"hmtx", b"maxp", b"name", b"OS/2", b"post", b"cvt ", b"fpgm",
+    b"glyf", b"loca", b"prep", b"CFF ", b"VORG", b"EBDT", b"EBLC", b"gasp", b"hdmx", b"kern",
+    b"LTSH", b"PCLT", b"VDMX", b"vhea", b"vmtx", b"BASE", b"GDEF", b"GPOS", b"GSUB", b"EBSC",
+    b"JSTF", b"MATH", b"CBDT", b"CBLC", b"COLR", b"CPAL", b"SVG ", b"sbix", b"acnt", b"avar",
+    b"bdat", b"bloc", b"bsln", b"cvar", b"fdsc", b"feat", b"fmtx", b"fvar", b"gvar", b"hsty",
+    b"just", b"lcar", b"mort", b"morx", b"opbd", b"prop", b"trak", b"Zapf", b"Silf", b"Glat",
+    b"Gloc", b"Feat", b"Sill",
+];
+
+// Flags of the components of composite glyphs.
+const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+const WE_HAVE_A_SCALE: u16 = 0x0008;
+const MORE_COMPONENTS: u16 = 0x0020;
+const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+const WE_HAVE_INSTRUCTIONS: u16 = 0x0100;
+
+// Flags of the points of simple glyphs.
+const ON_CURVE_POINT: u8 = 0x01;
+const X_SHORT_VECTOR: u8 = 0x02;
+const Y_SHORT_VECTOR: u8 = 0x04;
+const X_IS_SAME_OR_POSITIVE: u8 = 0x10;
+const Y_IS_SAME_OR_POSITIVE: u8 = 0x20;
+const OVERLAP_SIMPLE: u8 = 0x40;
+
+/// The font file in `data`: unpacked if it is a WOFF or WOFF2 file, else as it is. Returns
+/// `None` if the packaging is broken.
+pub fn decode(data: Vec<u8>) -> Option<Vec<u8>> {
+    match data.get(..4)? {
+        WOFF => decode_woff(&data),
+        WOFF2 => decode_woff2(&data),
+        _ => Some(data),
+    }
+}
+
+// A table of the font being rebuilt.
+struct Table {
+    tag: [u8; 4],
+    data: Vec<u8>,
+}
+
+// WOFF stores each table on its own, compressed with zlib unless that would not make it
+// smaller.
+fn decode_woff(data: &[u8]) -> Option<Vec<u8>> {
+    let mut header = Reader::new(data);
+    header.skip(4)?;
+    let flavor = header.u32()?;
+    header.skip(4)?;
+    let num_tables = header.u16()?;
+    header.skip(30)?;
+
+    let mut tables = Vec::with_capacity(num_tables as usize);
+    for _ in 0..num_tables {
+        let tag = header.tag()?;
+        let offset = header.u32()? as usize;
+        let compressed_length = header.u32()? as usize;
+        let length = header.u32()? as usize;
+        header.skip(4)?;
+
+        let stored = data.get(offset..offset.checked_add(compressed_length)?)?;
+        // Inflating stops at the length of the table, so that a small file cannot expand into
+        // an unbounded amount of memory.
+        let data = match compressed_length < length {
+            true => miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(stored, length).ok()?,
+            false => stored.to_vec(),
+        };
+        if data.len() != length {
+            return None;
+        }
+        tables.push(Table { tag, data });
+    }
+    Some(font_file(flavor, tables))
+}
+
+// WOFF2 compresses all the tables together with Brotli, after rewriting the glyph outlines,
+// their offsets and the horizontal metrics in forms that compress better.
+fn decode_woff2(data: &[u8]) -> Option<Vec<u8>> {
+    let mut header = Reader::new(data);
+    header.skip(4)?;
+    let flavor = header.u32()?;
+    // Collections of several fonts are not supported.
+    if &flavor.to_be_bytes() == b"ttcf" {
+        return None;
+    }
+    header.skip(4)?;
+    let num_tables = header.u16()?;
+    header.skip(6)?;
+    let compressed_size = header.u32()? as usize;
+    header.skip(24)?;
+
+    // Tag, length in the decompressed stream and whether the table is transformed.
+    let mut entries = Vec::with_capacity(num_tables as usize);
+    for _ in 0..num_tables {
+        let flags = header.u8()?;
+        let tag = match flags & 0x3f {
+            0x3f => header.tag()?,
+            known => *KNOWN_TAGS[known as usize],
+        };
+        let length = header.base128()?;
+        // glyf and loca are transformed unless their transformation version is 3, the other
+        // tables only when it is not 0.
+        let transformed = match &tag {
+            b"glyf" | b"loca" => flags >> 6 != 3,
+            _ => flags >> 6 != 0,
+        };
+        let stored_length = match transformed {
+            true => header.base128()?,
+            false => length,
+        };
+        entries.push((tag, stored_length, transformed));
+    }
+
+    // The stream holds the tables one after the other. Decompressing stops just past their
+    // total length, so that a small file cannot expand into an unbounded amount of memory.
+    let total = entries
+        .iter()
+        .try_fold(0u64, |total, &(_, stored_length, _)| {
+            total.checked_add(stored_length as u64)
+        })?;
+    let compressed = data.get(header.pos..header.pos.checked_add(compressed_size)?)?;
+    let mut stream = Vec::new();
+    brotli_decompressor::Decompressor::new(compressed, 4096)
+        .take(total + 1)
+        .read_to_end(&mut stream)
+        .ok()?;
+    if stream.len() as u64 != total {
+        return None;
+    }
+
+    let mut tables = Vec::with_capacity(entries.len());
+    let mut offset = 0usize;
+    for &(tag, stored_length, _) in &entries {
+        let data = stream.get(offset..offset.checked_add(stored_length as usize)?)?;
+        tables.push(Table {
+            tag,
+            data: data.to_vec(),
+        });
+        offset += stored_length as usize;
+    }
+
+    let is_transformed = |name: &[u8; 4]| {
+        entries
+            .iter()
+            .any(|(tag, _, transformed)| tag == name && *transformed)
+    };
+    let index = |tables: &[Table], name: &[u8; 4]| tables.iter().position(|t| t.tag == *name);
+
+    // The x minimum of each glyph, which transformed metrics leave out of the side bearings.
+    let mut x_mins = Vec::new();
+    if is_transformed(b"glyf") {
+        let glyf = index(&tables, b"glyf")?;
+        let loca = index(&tables, b"loca")?;
+        let (glyphs, offsets, mins) = reconstruct_glyf(&tables[glyf].data)?;
+        tables[glyf].data = glyphs;
+        tables[loca].data = offsets;
+        x_mins = mins;
+    }
+    if is_transformed(b"hmtx") {
+        // The side bearings left out are those of the glyphs just rebuilt.
+        if x_mins.is_empty() {
+            return None;
+        }
+        let hhea = &tables[index(&tables, b"hhea")?].data;
+        let num_h_metrics = Reader::new(hhea.get(34..36)?).u16()?;
+        let hmtx = index(&tables, b"hmtx")?;
+        tables[hmtx].data = reconstruct_hmtx(&tables[hmtx].data, num_h_metrics, &x_mins)?;
+    }
+    Some(font_file(flavor, tables))
+}
+
+// Rebuild the glyf table from its transformed form, which splits the glyphs into streams of
+// like values. Returns the table, the loca table of offsets into it and the x minimum of each
+// glyph.
+fn reconstruct_glyf(data: &[u8]) -> Option<(Vec<u8>, Vec<u8>, Vec<i16>)> {
+    let mut header = Reader::new(data);
+    header.skip(2)?;
+    let options = header.u16()?;
+    let num_glyphs = header.u16()? as usize;
+    let index_format = header.u16()?;
+    let mut streams = Vec::with_capacity(7);
+    let mut offset = 36usize;
+    for _ in 0..7 {
+        let size = header.u32()? as usize;
+        streams.push(Reader::new(data.get(offset..offset.checked_add(size)?)?));
+        offset += size;
+    }
+    let overlaps = match options & 1 {
+        1 => data.get(offset..offset + num_glyphs.div_ceil(8))?,
+        _ => &[],
+    };
+    let [mut contours, mut points, mut flags, mut glyph_stream, mut composites, mut bboxes, mut instructions] =
+        <[Reader; 7]>::try_from(streams).ok()?;
+
+    let bbox_bitmap = bboxes.bytes(num_glyphs.div_ceil(32) * 4)?;
+    let has_bbox = |glyph: usize| bbox_bitmap[glyph / 8] & (0x80 >> (glyph % 8)) != 0;
+    let overlap = |glyph: usize| {
+        overlaps
+            .get(glyph / 8)
+            .is_some_and(|&b| b & (0x80 >> (glyph % 8)) != 0)
+    };
+
+    let mut glyf = Vec::new();
+    let mut offsets = Vec::with_capacity(num_glyphs + 1);
+    let mut x_mins = Vec::with_capacity(num_glyphs);
+    for glyph in 0..num_glyphs {
+        offsets.push(glyf.len());
+        let num_contours = contours.u16()? as i16;
+        let explicit_bbox = match has_bbox(glyph) {
+            true => Some([bboxes.i16()?, bboxes.i16()?, bboxes.i16()?, bboxes.i16()?]),
+            false => None,
+        };
+
+        if num_contours == 0 {
+            x_mins.push(0);
+            continue;
+        }
+        if num_contours < 0 {
+            // A composite glyph, whose components are stored as they are in glyf.
+            let bbox = explicit_bbox?;
+            let start = composites.pos;
+            let mut has_instructions = false;
+            loop {
+                let component_flags = composites.u16()?;
+                has_instructions |= component_flags & WE_HAVE_INSTRUCTIONS != 0;
+                let mut size = 2 + match component_flags & ARG_1_AND_2_ARE_WORDS {
+                    0 => 2,
+                    _ => 4,
+                };
+                if component_flags & WE_HAVE_A_SCALE != 0 {
+                    size += 2;
+                } else if component_flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+                    size += 4;
+                } else if component_flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+                    size += 8;
+                }
+                composites.skip(size)?;
+                if component_flags & MORE_COMPONENTS == 0 {
+                    break;
+                }
+            }
+            push_i16(&mut glyf, -1);
+            bbox.iter().for_each(|&v| push_i16(&mut glyf, v));
+            glyf.extend_from_slice(&composites.data[start..composites.pos]);
+            if has_instructions {
+                let length = glyph_stream.u255()?;
+                push_u16(&mut glyf, length);
+                glyf.extend_from_slice(instructions.bytes(length as usize)?);
+            }
+            x_mins.push(bbox[0]);
+            pad(&mut glyf);
+            continue;
+        }
+
+        // A simple glyph: its points are triplets of a flag and a coordinate delta.
+        let mut end_points = Vec::with_capacity(num_contours as usize);
+        let mut num_points = 0u16;
+        for _ in 0..num_contours {
+            num_points = num_points.checked_add(points.u255()?)?;
+            end_points.push(num_points.checked_sub(1)?);
+        }
+        let mut deltas = Vec::with_capacity(num_points as usize);
+        let (mut x, mut y) = (0i32, 0i32);
+        let (mut x_min, mut y_min, mut x_max, mut y_max) = (i32::MAX, i32::MAX, i32::MIN, i32::MIN);
+        for _ in 0..num_points {
+            let flag = flags.u8()?;
+            let (dx, dy) = glyph_stream.triplet(flag & 0x7f)?;
+            x += dx;
+            y += dy;
+            (x_min, y_min) = (x_min.min(x), y_min.min(y));
+            (x_max, y_max) = (x_max.max(x), y_max.max(y));
+            deltas.push((flag & 0x80 == 0, dx, dy));
+        }
+        let instruction_length = glyph_stream.u255()?;
+        let bbox =
+            explicit_bbox.unwrap_or([x_min as i16, y_min as i16, x_max as i16, y_max as i16]);
+
+        push_i16(&mut glyf, num_contours);
+        bbox.iter().for_each(|&v| push_i16(&mut glyf, v));
+        end_points.iter().for_each(|&v| push_u16(&mut glyf, v));
+        push_u16(&mut glyf, instruction_length);
+        glyf.extend_from_slice(instructions.bytes(instruction_length as usize)?);
+        let (mut xs, mut ys) = (Vec::new(), Vec::new());
+        for (i, &(on_curve, dx, dy)) in deltas.iter().enumerate() {
+            let mut flag = if on_curve { ON_CURVE_POINT } else { 0 };
+            if i == 0 && overlap(glyph) {
+                flag |= OVERLAP_SIMPLE;
+            }
+            flag |= encode_delta(dx, &mut xs, X_SHORT_VECTOR, X_IS_SAME_OR_POSITIVE);
+            flag |= encode_delta(dy, &mut ys, Y_SHORT_VECTOR, Y_IS_SAME_OR_POSITIVE);
+            glyf.push(flag);
+        }
+        glyf.extend_from_slice(&xs);
+        glyf.extend_from_slice(&ys);
+        x_mins.push(bbox[0]);
+        pad(&mut glyf);
+    }
+    offsets.push(glyf.len());
+
+    let mut loca = Vec::with_capacity(offsets.len() * 4);
+    for offset in offsets {
+        match index_format {
+            0 => push_u16(&mut loca, u16::try_from(offset / 2).ok()?),
+            _ => loca.extend_from_slice(&(offset as u32).to_be_bytes()),
+        }
+    }
+    Some((glyf, loca, x_mins))
+}
+
+// Write a coordinate delta of a simple glyph to `coordinates` in its shortest form, and
+// return the flags that tell which form it is.
+fn encode_delta(delta: i32, coordinates: &mut Vec<u8>, short: u8, same_or_positive: u8) -> u8 {
+    match delta {
+        0 => same_or_positive,
+        -255..=255 => {
+            coordinates.push(delta.unsigned_abs() as u8);
+            match delta > 0 {
+                true => short | same_or_positive,
+                false => short,
+            }
+        }
+        _ => {
+            push_i16(coordinates, delta as i16);
+            0
+        }
+    }
+}
+
+// Rebuild hmtx from its transformed form, which leaves out the left side bearings that equal
+// the x minimum of their glyph.
+fn reconstruct_hmtx(data: &[u8], num_h_metrics: u16, x_mins: &[i16]) -> Option<Vec<u8>> {
+    let mut reader = Reader::new(data);
+    let flags = reader.u8()?;
+    let advances: Vec<u16> = (0..num_h_metrics)
+        .map(|_| reader.u16())
+        .collect::<Option<_>>()?;
+    let mut hmtx = Vec::with_capacity(x_mins.len() * 4);
+    for (glyph, &x_min) in x_mins.iter().enumerate() {
+        let proportional = glyph < num_h_metrics as usize;
+        if proportional {
+            push_u16(&mut hmtx, advances[glyph]);
+        }
+        let stored = match proportional {
+            true => flags & 1 == 0,
+            false => flags & 2 == 0,
+        };
+        let bearing = match stored {
+            true => reader.i16()?,
+            false => x_min,
+        };
+        push_i16(&mut hmtx, bearing);
+    }
+    Some(hmtx)
+}
+
+// A font file of `tables`, whose version is `flavor`: the table directory sorted by tag, then
+// the tables, each starting at a multiple of 4 bytes.
+fn font_file(flavor: u32, mut tables: Vec<Table>) -> Vec<u8> {
+    tables.sort_by_key(|table| table.tag);
+    let num_tables = tables.len() as u32;
+    let entry_selector = num_tables.max(1).ilog2();
+    let search_range = 16 << entry_selector;
+    // Directories of more than 4095 tables overflow the search fields, which readers ignore.
+    let field = |value: u32| u16::try_from(value).unwrap_or(u16::MAX);
+
+    let mut file = Vec::new();
+    file.extend_from_slice(&flavor.to_be_bytes());
+    push_u16(&mut file, num_tables as u16);
+    push_u16(&mut file, field(search_range));
+    push_u16(&mut file, entry_selector as u16);
+    push_u16(
+        &mut file,
+        field((num_tables * 16).saturating_sub(search_range)),
+    );
+
+    let mut offset = 12 + tables.len() * 16;
+    for table in &tables {
+        file.extend_from_slice(&table.tag);
+        file.extend_from_slice(&checksum(&table.data).to_be_bytes());
+        file.extend_from_slice(&(offset as u32).to_be_bytes());
+        file.extend_from_slice(&(table.data.len() as u32).to_be_bytes());
+        offset += table.data.len().next_multiple_of(4);
+    }
+    for table in &tables {
+        file.extend_from_slice(&table.data);
+        pad(&mut file);
+    }
+    file
+}
+
+// The sum of the table as big endian 32-bit numbers, padded with zeros.
+fn checksum(data: &[u8]) -> u32 {
+    data.chunks(4).fold(0u32, |sum, chunk| {
+        let mut word = [0; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        sum.wrapping_add(u32::from_be_bytes(word))
+    })
+}
+
+fn push_u16(data: &mut Vec<u8>, value: u16) {
+    data.extend_from_slice(&value.to_be_bytes());
+}
+
+fn push_i16(data: &mut Vec<u8>, value: i16) {
+    data.extend_from_slice(&value.to_be_bytes());
+}
+
+// Pad with zeros to a multiple of 4 bytes.
+fn pad(data: &mut Vec<u8>) {
+    data.resize(data.len().next_multiple_of(4), 0);
+}
+
+// Reads the big endian numbers of font data. Every read returns `None` past the end.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Reader { data, pos: 0 }
+    }
+
+    fn bytes(&mut self, n: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.pos..self.pos.checked_add(n)?)?;
+        self.pos += n;
+        Some(bytes)
+    }
+
+    fn skip(&mut self, n: usize) -> Option<()> {
+        self.bytes(n).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_be_bytes(self.bytes(2)?.try_into().ok()?))
+    }
+
+    fn i16(&mut self) -> Option<i16> {
+        Some(self.u16()? as i16)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_be_bytes(self.bytes(4)?.try_into().ok()?))
+    }
+
+    fn tag(&mut self) -> Option<[u8; 4]> {
+        self.bytes(4)?.try_into().ok()
+    }
+
+    // UIntBase128: seven bits per byte, most significant first, while the top bit is set.
+    fn base128(&mut self) -> Option<u32> {
+        let mut value = 0u32;
+        for i in 0..5 {
+            let byte = self.u8()?;
+            // Leading zeros are not allowed.
+            if i == 0 && byte == 0x80 {
+                return None;
+            }
+            value = value.checked_mul(128)? | (byte & 0x7f) as u32;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    // 255UInt16: a byte below 253 is the value, the others say how the value follows.
+    fn u255(&mut self) -> Option<u16> {
+        match self.u8()? {
+            253 => self.u16(),
+            254 => Some(self.u8()? as u16 + 253 * 2),
+            255 => Some(self.u8()? as u16 + 253),
+            code => Some(code as u16),
+        }
+    }
+
+    // The x and y deltas of a point of a simple glyph. `flag` tells how many bytes they take
+    // and how they are split between the two; its low bits are the signs.
+    fn triplet(&mut self, flag: u8) -> Option<(i32, i32)> {
+        let with_sign = |flag: u8, value: i32| if flag & 1 == 1 { value } else { -value };
+        let flag32 = flag as i32;
+        Some(match flag {
+            0..=9 => {
+                let b0 = self.u8()? as i32;
+                (0, with_sign(flag, ((flag32 & 14) << 7) + b0))
+            }
+            10..=19 => {
+                let b0 = self.u8()? as i32;
+                (with_sign(flag, (((flag32 - 10) & 14) << 7) + b0), 0)
+            }
+            20..=83 => {
+                let b0 = flag32 - 20;
+                let b1 = self.u8()? as i32;
+                (
+                    with_sign(flag, 1 + (b0 & 0x30) + (b1 >> 4)),
+                    with_sign(flag >> 1, 1 + ((b0 & 0x0c) << 2) + (b1 & 0x0f)),
+                )
+            }
+            84..=119 => {
+                let b0 = flag32 - 84;
+                let (b1, b2) = (self.u8()? as i32, self.u8()? as i32);
+                (
+                    with_sign(flag, 1 + ((b0 / 12) << 8) + b1),
+                    with_sign(flag >> 1, 1 + (((b0 % 12) >> 2) << 8) + b2),
+                )
+            }
+            120..=123 => {
+                let (b1, b2, b3) = (self.u8()? as i32, self.u8()? as i32, self.u8()? as i32);
+                (
+                    with_sign(flag, (b1 << 4) + (b2 >> 4)),
+                    with_sign(flag >> 1, ((b2 & 0x0f) << 8) + b3),
+                )
+            }
+            _ => {
+                let (x, y) = (self.u16()? as i32, self.u16()? as i32);
+                (with_sign(flag, x), with_sign(flag >> 1, y))
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `data` as a Brotli stream of a single uncompressed meta-block.
+    fn brotli_stored(data: &[u8]) -> Vec<u8> {
+        // A 16-bit window, a meta-block of four nibbles of length that is not the last one,
+        // and the flag that it is uncompressed, padded to a byte.
+        let header = ((data.len() as u32 - 1) << 4) | 1 << 20;
+        let mut stream = header.to_le_bytes()[..3].to_vec();
+        stream.extend_from_slice(data);
+        // An empty last meta-block.
+        stream.push(0x03);
+        stream
+    }
+
+    // A WOFF2 file with a `name` table of `length` bytes, whose compressed stream holds
+    // `stream`.
+    fn woff2(length: u8, stream: &[u8]) -> Vec<u8> {
+        let compressed = brotli_stored(stream);
+        let mut file = Vec::new();
+        file.extend_from_slice(WOFF2);
+        file.extend_from_slice(&0x00010000u32.to_be_bytes());
+        file.extend_from_slice(&[0; 4]);
+        file.extend_from_slice(&1u16.to_be_bytes());
+        file.extend_from_slice(&[0; 6]);
+        file.extend_from_slice(&(compressed.len() as u32).to_be_bytes());
+        file.extend_from_slice(&[0; 24]);
+        // The `name` table by its index among the known tags, untransformed.
+        file.extend_from_slice(&[5, length]);
+        file.extend_from_slice(&compressed);
+        file
+    }
+
+    // A WOFF file of `tables`: the tag of each, its length once unpacked and the bytes stored
+    // for it.
+    fn woff(tables: &[([u8; 4], u32, &[u8])]) -> Vec<u8> {
+        let mut file = Vec::new();
+        file.extend_from_slice(WOFF);
+        file.extend_from_slice(&0x00010000u32.to_be_bytes());
+        file.extend_from_slice(&[0; 4]);
+        file.extend_from_slice(&(tables.len() as u16).to_be_bytes());
+        file.extend_from_slice(&[0; 30]);
+        let mut offset = file.len() + tables.len() * 20;
+        for &(tag, length, stored) in tables {
+            file.extend_from_slice(&tag);
+            file.extend_from_slice(&(offset as u32).to_be_bytes());
+            file.extend_from_slice(&(stored.len() as u32).to_be_bytes());
+            file.extend_from_slice(&length.to_be_bytes());
+            file.extend_from_slice(&[0; 4]);
+            offset += stored.len();
+        }
+        for &(_, _, stored) in tables {
+            file.extend_from_slice(stored);
+        }
+        file
+    }
+
+    #[test]
+    fn woff_tables_are_inflated() {
+        let stored = miniz_oxide::deflate::compress_to_vec_zlib(&[7; 64], 6);
+        let font = decode(woff(&[(*b"name", 64, &stored)])).unwrap();
+        assert_eq!(&font[12..16], b"name");
+        assert!(font.ends_with(&[7; 64]));
+    }
+
+    #[test]
+    fn woff_tables_that_inflate_past_their_length_are_rejected() {
+        let stored = miniz_oxide::deflate::compress_to_vec_zlib(&vec![0; 1 << 20], 6);
+        assert_eq!(decode(woff(&[(*b"name", 64, &stored)])), None);
+    }
+
+    #[test]
+    fn woff_files_with_thousands_of_tables_are_unpacked() {
+        let tables: Vec<([u8; 4], u32, &[u8])> = (0..5000u32)
+            .map(|i| (i.to_be_bytes(), 0, &[][..]))
+            .collect();
+        let font = decode(woff(&tables)).unwrap();
+        assert_eq!(&font[4..6], &5000u16.to_be_bytes());
+        assert_eq!(font.len(), 12 + 5000 * 16);
+    }
+
+    #[test]
+    fn woff2_tables_are_unpacked() {
+        let font = decode(woff2(4, b"abcd")).unwrap();
+        assert_eq!(&font[12..16], b"name");
+        assert!(font.ends_with(b"abcd"));
+    }
+
+    #[test]
+    fn woff2_streams_longer_or_shorter_than_the_tables_are_rejected() {
+        assert_eq!(decode(woff2(4, b"abcdefgh")), None);
+        assert_eq!(decode(woff2(4, b"ab")), None);
+    }
+}
@@ -1,8 +1,11 @@
 use parser::boxmodel::layout_tree;
-use parser::css::parse_css;
 use parser::datatypes::Node;
 use parser::display::build_display_list;
+use parser::document::document_stylesheets;
 use parser::dom::parse_element;
+use parser::fontdb::FontDatabase;
+use parser::loader::FileLoader;
+use parser::media::Viewport;
 use parser::print_token;
 use parser::styles::style_tree;
 use parser::tokenizer::Tokenizer;
@@ -16,6 +19,47 @@ fn main() {
 <html>
     <head prop=test prop2=val2>
         <title>TitleData</title>
+        <style>
+            * {
+                background: #000000;
+            }
+            body {
+                margin: 10px;
+                background: #FFFFFF;
+            }
+            p {
+                margin: 10px;
+                font-size: 48px;
+                padding: 20px;
+                color: #FFFFFF;
+                border-width: 10px;
+                border-color: #FF77FF;
+            }
+            .c1 {
+                padding: 0px;
+                background: #FF0000;
+            }
+            .c2 {
+                border-width: 0px;
+                background: #00FF00;
+            }
+            .c3 {
+                width: 100px;
+                background: #00FFFF;
+            }
+            .c4 {
+                background: #0000FF;
+                color: #000000;
+            }
+            h1 {
+                padding: 50px;
+                color: #000000;
+                background: #FF7700;
+                margin: 20px;
+                border-width: 40px;
+                border-color: #FF0000;
+            }
+        </style>
     </head>
     <body>
         <p>This is some text</p>
@@ -28,51 +72,6 @@ fn main() {
     </body>
 </html>
 ";
-    let css = "
-        * {
-            display: block;
-            background: #000000;
-        }
-        head {
-            display: none;
-        }
-        body {
-            margin: 10px;
-            background: #FFFFFF;
-        }
-        p {
-            margin: 10px;
-            font-size: 48px;
-            padding: 20px;
-            color: #FFFFFF;
-            border-width: 10px;
-            border-color: #FF77FF;
-        }
-        .c1 {
-            padding: 0px;
-            background: #FF0000;
-        }
-        .c2 {
-            border-width: 0px;
-            background: #00FF00;
-        }
-        .c3 {
-            width: 100px;
-            background: #00FFFF;
-        }
-        .c4 {
-            background: #0000FF;
-            color: #000000;
-        }
-        h1 {
-            padding: 50px;
-            color: #000000;
-            background: #FF7700;
-            margin: 20px;
-            border-width: 40px;
-            border-color: #FF0000;
-        }
-        ";
     let mut tokenizer = Tokenizer::new(html);
     // print_token(&mut tokenizer);
 
@@ -87,20 +86,28 @@ fn main() {
     let (element, _) = parse_element(&tokens);
     // println!("{:#?}", element);
 
-    let stylesheet = parse_css(css.to_string());
+    let node = Node::Element(element);
+    let loader = FileLoader::new(".");
+    let stylesheets = document_stylesheets(&node, &loader);
 
-    // println!("{:#?}", stylesheet);
+    // println!("{:#?}", stylesheets);
 
-    let node = Node::Element(element);
-    let style_tree = style_tree(&node, &stylesheet);
-    // println!("{:#?}", style_tree);
+    let mut fonts = FontDatabase::bundled();
+    fonts.load_system_fonts();
+    fonts.add_font_faces(&stylesheets, &loader);
+
+    make_window(width, height, &fonts, |w, h| {
+        let viewport = Viewport::new(w as f32, h as f32);
+        let style_tree = style_tree(&node, &stylesheets, &viewport);
+        // println!("{:#?}", style_tree);
 
-    let layout = layout_tree(width as f32, height as f32, &style_tree);
-    // println!("{:#?}", layout);
+        let layout = layout_tree(w as f32, h as f32, &style_tree, &fonts);
+        // println!("{:#?}", layout);
 
-    let displaylist = build_display_list(&layout);
+        let displaylist = build_display_list(&layout);
 
-    // println!("{:#?}", displaylist);
+        // println!("{:#?}", displaylist);
 
-    make_window(width, height, displaylist);
+        displaylist
+    });
 }